@@ -0,0 +1,59 @@
+use rltk::Point;
+use specs::prelude::*;
+use crate::constants::{VIEW_WIDTH,VIEW_HEIGHT};
+use crate::map::Map;
+
+/// Which part of the map is on screen. The view follows the player, stopping at the edges of the map
+/// so it never shows more of the void than it has to. A map smaller than the view sits in the middle of it.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Camera {
+    /// The map tile drawn in the top left corner of the screen. Negative if the map is smaller than the view.
+    pub origin: Point,
+}
+
+impl Camera {
+    /// A camera on a `map_width` x `map_height` map, looking at `centre`.
+    pub fn new(map_width: i32, map_height: i32, centre: Point) -> Camera {
+        Camera {
+            origin: Point::new(
+                axis_origin(centre.x, map_width, VIEW_WIDTH),
+                axis_origin(centre.y, map_height, VIEW_HEIGHT),
+            ),
+        }
+    }
+
+    /// The camera following the player around the current map.
+    pub fn for_player(ecs: &World) -> Camera {
+        let map = ecs.fetch::<Map>();
+        let player_pos = ecs.fetch::<Point>();
+        Camera::new(map.width, map.height, *player_pos)
+    }
+
+    /// Where the map tile `world` is drawn, if it's in view.
+    pub fn to_screen(&self, world: Point) -> Option<Point> {
+        let screen = Point::new(world.x - self.origin.x, world.y - self.origin.y);
+        if on_view(screen) { Some(screen) } else { None }
+    }
+
+    /// The map tile drawn at `screen`, or None if that's outside the view (over the UI, for instance).
+    /// The tile may still be off the edge of the map.
+    pub fn to_world(&self, screen: Point) -> Option<Point> {
+        if on_view(screen) {
+            Some(Point::new(screen.x + self.origin.x, screen.y + self.origin.y))
+        } else {
+            None
+        }
+    }
+}
+
+fn on_view(screen: Point) -> bool {
+    screen.x >= 0 && screen.x < VIEW_WIDTH && screen.y >= 0 && screen.y < VIEW_HEIGHT
+}
+
+/// Where the view starts along one axis: centred on `centre`, but kept within the map.
+fn axis_origin(centre: i32, map_size: i32, view_size: i32) -> i32 {
+    if map_size <= view_size {
+        return (map_size - view_size) / 2;
+    }
+    i32::max(0, i32::min(centre - view_size / 2, map_size - view_size))
+}
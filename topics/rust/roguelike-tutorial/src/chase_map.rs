@@ -0,0 +1,151 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use rltk::Point;
+use crate::map::{Map,TileType};
+
+/// Fleeing monsters would rather run somewhere with more room than just away: scaling the distances by this
+/// before smoothing them out makes the far end of a corridor look better than the dead end next to them.
+const FLEE_FACTOR: f32 = -1.2;
+
+/// How far it is from every tile on the level to the player, shared by all the monsters.
+/// Chasers step downhill on `towards`, fleeing monsters step downhill on `away`. Only worked out again
+/// when the player moves, changes level or the layout changes, however many monsters are using it.
+pub struct ChaseMap {
+    pub towards: Vec<f32>,
+    pub away: Vec<f32>,
+    /// Where the player was, on which level, and the level's `layout_changes`, when it was last worked out.
+    built_for: Option<(Point, i32, u32)>,
+}
+
+impl Default for ChaseMap {
+    fn default() -> ChaseMap {
+        ChaseMap::new()
+    }
+}
+
+impl ChaseMap {
+    pub fn new() -> ChaseMap {
+        ChaseMap{ towards: Vec::new(), away: Vec::new(), built_for: None }
+    }
+
+    /// Work the maps out again if the player or the layout has moved on since last time. Returns true if it did.
+    pub fn update(&mut self, map: &Map, player: Point) -> bool {
+        let built_for = (player, map.depth, map.layout_changes);
+        if self.built_for == Some(built_for) && self.towards.len() == map.tiles.len() {
+            return false;
+        }
+
+        let player_idx = map.xy_idx(player.x, player.y);
+        self.towards = flow(map, &[(player_idx, 0.0)]);
+        let seeds: Vec<(usize, f32)> = self.towards.iter().enumerate()
+            .filter(|(_idx, distance)| **distance < f32::MAX)
+            .map(|(idx, distance)| (idx, distance * FLEE_FACTOR))
+            .collect();
+        self.away = flow(map, &seeds);
+        self.built_for = Some(built_for);
+        true
+    }
+
+    /// The next step towards the player from `from`.
+    pub fn step_towards(&self, map: &Map, from: Point) -> Option<Point> {
+        self.downhill(&self.towards, map, from)
+    }
+
+    /// The next step away from the player from `from`.
+    pub fn step_away(&self, map: &Map, from: Point) -> Option<Point> {
+        self.downhill(&self.away, map, from)
+    }
+
+    /// The lowest neighbouring tile on `values` that's lower than `from` and free to step into right now.
+    /// Monsters that moved earlier this turn have already updated `blocked_tiles`, so nobody doubles up.
+    fn downhill(&self, values: &[f32], map: &Map, from: Point) -> Option<Point> {
+        if values.len() != map.tiles.len() || !map.contains(from.x, from.y) {
+            return None;
+        }
+        let player = self.built_for.map(|(player, _depth, _changes)| player);
+
+        let mut best: Option<Point> = None;
+        let mut best_value = values[map.xy_idx(from.x, from.y)];
+        for (dx, dy) in NEIGHBOURS.iter() {
+            let candidate = Point::new(from.x + dx, from.y + dy);
+            if !map.xy_in_map(candidate.x, candidate.y) || Some(candidate) == player {
+                continue;
+            }
+            let idx = map.xy_idx(candidate.x, candidate.y);
+            // Closed doors are blocked, but walking into one opens it.
+            if map.blocked_tiles[idx] && map.tiles[idx] != TileType::Door {
+                continue;
+            }
+            if values[idx] < best_value {
+                best = Some(candidate);
+                best_value = values[idx];
+            }
+        }
+        best
+    }
+}
+
+/// Straight moves first, so they win ties with diagonals.
+const NEIGHBOURS: [(i32, i32); 8] = [(-1, 0), (1, 0), (0, -1), (0, 1), (-1, -1), (1, -1), (-1, 1), (1, 1)];
+
+/// Somewhere a monster could walk, ignoring whoever is standing there. Closed doors count, since they open.
+fn walkable(map: &Map, idx: usize) -> bool {
+    !map.tiles[idx].blocks_movement() || map.tiles[idx] == TileType::Door
+}
+
+#[derive(PartialEq)]
+struct Frontier {
+    value: f32,
+    idx: usize,
+}
+
+impl Eq for Frontier {}
+
+impl Ord for Frontier {
+    // Reversed, so the heap hands out the lowest value first.
+    fn cmp(&self, other: &Frontier) -> Ordering {
+        other.value.partial_cmp(&self.value).unwrap_or(Ordering::Equal).then_with(|| other.idx.cmp(&self.idx))
+    }
+}
+
+impl PartialOrd for Frontier {
+    fn partial_cmp(&self, other: &Frontier) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A Dijkstra map: the cheapest cost from any of the `seeds` (tile, starting value) to every walkable tile,
+/// moving like monsters do. f32::MAX where there's no way through.
+fn flow(map: &Map, seeds: &[(usize, f32)]) -> Vec<f32> {
+    let mut values = vec![f32::MAX; map.tiles.len()];
+    let mut frontier = BinaryHeap::new();
+    for (idx, value) in seeds.iter() {
+        values[*idx] = *value;
+        frontier.push(Frontier{ value: *value, idx: *idx });
+    }
+
+    while let Some(Frontier{ value, idx }) = frontier.pop() {
+        if value > values[idx] {
+            // Already reached more cheaply.
+            continue;
+        }
+        let x = idx as i32 % map.width;
+        let y = idx as i32 / map.width;
+        for (dx, dy) in NEIGHBOURS.iter() {
+            if !map.xy_in_map(x + dx, y + dy) {
+                continue;
+            }
+            let next = map.xy_idx(x + dx, y + dy);
+            if !walkable(map, next) {
+                continue;
+            }
+            let cost = if *dx != 0 && *dy != 0 { 1.45 } else { 1.0 };
+            if value + cost < values[next] {
+                values[next] = value + cost;
+                frontier.push(Frontier{ value: value + cost, idx: next });
+            }
+        }
+    }
+
+    values
+}
@@ -5,6 +5,10 @@ use specs::prelude::*;
 use specs::saveload::{SimpleMarker,ConvertSaveload,Marker};
 use specs::error::NoError;
 use crate::map::Map;
+use crate::dungeon::MasterDungeonMap;
+use crate::gamelog::GameLog;
+use crate::identification::Identification;
+use crate::morgue::RunStats;
 
 #[derive(Component, ConvertSaveload, Clone)]
 pub struct Position {
@@ -12,6 +16,14 @@ pub struct Position {
     pub y: i32,
 }
 
+/// Where an entity waits while the player is on another level. Replaces its `Position`.
+#[derive(Component, ConvertSaveload, Clone)]
+pub struct OtherLevelPosition {
+    pub x: i32,
+    pub y: i32,
+    pub depth: i32,
+}
+
 #[derive(Component, ConvertSaveload, Clone)]
 pub struct Renderable {
     pub glyph: u16,
@@ -30,6 +42,12 @@ pub struct Viewshed {
     pub dirty: bool,
 }
 
+/// Lights up the tiles around it, brightest in the middle and fading out to nothing `range` tiles away.
+#[derive(Component, ConvertSaveload, Clone)]
+pub struct LightSource {
+    pub range: i32,
+}
+
 #[derive(Component, ConvertSaveload, Clone)]
 pub struct Name {
     pub name: String
@@ -38,6 +56,57 @@ pub struct Name {
 #[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Monster {}
 
+/// Buys and sells, and never picks a fight. Their stock is whatever is in their backpack.
+/// Not a `Monster`, so the monster AI leaves them alone.
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct Vendor {}
+
+#[derive(PartialEq, Copy, Clone, Serialize, Deserialize, Debug)]
+pub enum AiState {
+    /// Nothing to do: stands still, or wanders if its behaviour says so.
+    Idle,
+    Wandering,
+    /// Heading for where the player was last seen.
+    Chasing,
+    /// Too hurt to fight, keeping away from the player.
+    Fleeing,
+}
+
+/// A monster's working memory.
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct MonsterBrain {
+    pub state: AiState,
+    pub last_known_player: Option<rltk::Point>,
+}
+
+impl Default for MonsterBrain {
+    fn default() -> MonsterBrain {
+        MonsterBrain{ state: AiState::Idle, last_known_player: None }
+    }
+}
+
+/// Per monster type settings for `MonsterAI`. Monsters without one use the defaults.
+#[derive(Component, Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AiBehaviour {
+    /// Idle monsters wander about rather than standing still.
+    pub wanders: bool,
+    /// Runs away once hp drops to this percentage of max. 0 never flees.
+    pub flee_at_hp_percent: i32,
+    /// On spotting the player, alerts other monsters within this many tiles.
+    pub alert_radius: i32,
+    /// How far away it can attack from. 1 is melee only.
+    pub attack_range: i32,
+    /// Backs off if the player gets closer than this (but not adjacent, where it just fights).
+    pub keep_distance: i32,
+}
+
+impl Default for AiBehaviour {
+    fn default() -> AiBehaviour {
+        AiBehaviour{ wanders: false, flee_at_hp_percent: 0, alert_radius: 0, attack_range: 1, keep_distance: 0 }
+    }
+}
+
 #[derive(Component, Serialize, Deserialize, Clone)]
 pub struct BlocksTile {}
 
@@ -56,20 +125,78 @@ pub struct WantsToMelee {
 
 #[derive(Component, ConvertSaveload, Clone)]
 pub struct SufferDamage {
-    pub amount: Vec<i32>
+    pub amount: Vec<i32>,
+    /// What dealt the most recent of it, eg: "Goblin" or "starvation". Ends up on the player's tombstone.
+    pub cause: String,
 }
 
 impl SufferDamage {
-    pub fn new_damage(store: &mut WriteStorage<SufferDamage>, victim: Entity, amount: i32) {
+    pub fn new_damage(store: &mut WriteStorage<SufferDamage>, victim: Entity, amount: i32, cause: &str) {
         if let Some(suffering) = store.get_mut(victim) {
             suffering.amount.push(amount);
+            suffering.cause = cause.to_string();
         } else {
-            let dmg = SufferDamage { amount : vec![amount] };
+            let dmg = SufferDamage { amount : vec![amount], cause: cause.to_string() };
             store.insert(victim, dmg).expect("Unable to insert damage");
         }
     }
 }
 
+/// Who last hurt this entity, so a kill can be credited to them. Not saved: a reloaded game just forgets.
+#[derive(Component, Clone)]
+pub struct LastAttacker {
+    pub attacker: Entity
+}
+
+/// The gold the player is carrying.
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct Wallet {
+    pub gold: i32,
+}
+
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct Experience {
+    pub level: i32,
+    /// Progress towards the next level, which takes `xp_to_next_level`.
+    pub xp: i32,
+    /// Level-ups that haven't had a stat boost chosen yet.
+    pub pending_boosts: i32,
+}
+
+impl Experience {
+    pub fn xp_to_next_level(&self) -> i32 {
+        self.level * 50
+    }
+}
+
+/// XP awarded to whoever kills this.
+#[derive(Component, ConvertSaveload, Clone)]
+pub struct GivesExperience {
+    pub xp: i32
+}
+
+/// The choice offered on levelling up.
+#[derive(PartialEq, Copy, Clone, Serialize, Deserialize, Debug)]
+pub enum StatBoost {
+    /// +1 power
+    Strength,
+    /// +1 defense
+    Toughness,
+    /// +5 max hp
+    Vitality,
+}
+
+/// Everything that takes turns gains `speed` energy each tick, and gets a turn on reaching `initiative_system::TURN_COST`.
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct Initiative {
+    pub energy: i32,
+    pub speed: i32,
+}
+
+/// Present while it's this entity's turn.
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct MyTurn {}
+
 #[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Item {}
 
@@ -98,6 +225,26 @@ pub struct WantsToDropItem {
 #[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Consumable {}
 
+/// A pile of coins. Picking it up puts it straight in the wallet.
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct Gold {
+    pub amount: i32,
+}
+
+/// What a vendor charges for one of these. They buy it back for half.
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct Price {
+    pub gold: i32,
+}
+
+/// Opens one locked door, and is used up doing it.
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct Key {}
+
+/// What the whole dungeon is for. Picking it up wins the game.
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct GoalItem {}
+
 #[derive(Component, ConvertSaveload, Clone)]
 pub struct ProvidesHealing {
     pub heal_amount: i32
@@ -108,6 +255,94 @@ pub struct Ranged {
     pub range: i32
 }
 
+/// Something to shoot with. On a bow it's equipped and fires ammo; on ammo itself it's thrown by hand.
+/// Monsters can have one of their own, which never runs out.
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct RangedWeapon {
+    pub range: i32,
+    pub power: i32,
+}
+
+/// Arrows, throwing knives, etc. One is used up from the stack with each shot.
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct Ammo {}
+
+/// Several of the same item sharing one backpack slot. Picking up another merges it into the stack,
+/// and using one only takes one off the top.
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct Stackable {
+    pub count: i32,
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Serialize, Deserialize, Debug)]
+pub enum MagicItemKind {
+    Scroll,
+    Potion,
+}
+
+/// Goes by a made up name until the player has used one. See `identification`.
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct MagicItem {
+    pub kind: MagicItemKind,
+}
+
+/// Only lives until the shot lands, so it isn't saved.
+#[derive(Component, Clone)]
+pub struct WantsToShoot {
+    pub target: rltk::Point,
+    /// What's being fired. `None` for monsters, who don't carry ammo.
+    pub ammo: Option<Entity>,
+}
+
+/// What a special attack does when it lands. Armour doesn't help against either.
+#[derive(PartialEq, Copy, Clone, Serialize, Deserialize, Debug)]
+pub enum SpecialAttackKind {
+    /// Hits everything within `radius` tiles of the target for `damage`, except the attacker.
+    Breath { radius: i32, damage: i32 },
+    /// Hits the target for `damage` and heals the attacker by as much.
+    Drain { damage: i32 },
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct SpecialAttack {
+    pub name: String,
+    pub kind: SpecialAttackKind,
+    /// How far away the target can be.
+    pub range: i32,
+    /// How many of its turns after using it before it can use it again.
+    pub cooldown: i32,
+    /// Turns until it's ready. 0 is ready now.
+    #[serde(default)]
+    pub recharge: i32,
+}
+
+/// Attacks a monster uses instead of its usual one whenever it can see the player and one is ready.
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct SpecialAttacks {
+    pub attacks: Vec<SpecialAttack>,
+}
+
+impl SpecialAttacks {
+    /// Another turn closer to being ready, for everything that's been used.
+    pub fn recharge(&mut self) {
+        for attack in self.attacks.iter_mut() {
+            attack.recharge = i32::max(0, attack.recharge - 1);
+        }
+    }
+
+    /// The first attack that's ready and can reach a target `distance` away.
+    pub fn ready(&self, distance: f32) -> Option<usize> {
+        self.attacks.iter().position(|attack| attack.recharge == 0 && distance < attack.range as f32 + 0.5)
+    }
+}
+
+#[derive(Component, ConvertSaveload, Clone)]
+pub struct WantsToUseSpecial {
+    pub target: Entity,
+    /// Which of its `SpecialAttacks`.
+    pub attack: usize,
+}
+
 #[derive(Component, ConvertSaveload, Clone)]
 pub struct AreaOfEffect {
     pub radius: i32
@@ -118,9 +353,199 @@ pub struct InflictsDamage {
     pub damage: i32
 }
 
+/// Something that wears off after a while. The stacking rule decides what happens when it's applied again.
+#[derive(PartialEq, Eq, Copy, Clone, Serialize, Deserialize, Debug)]
+pub enum StatusEffectKind {
+    /// Staggers in a random direction instead of where it meant to go.
+    Confusion,
+    /// Loses `magnitude` hp at the start of each turn.
+    Poison,
+    /// Gains `magnitude` hp at the start of each turn.
+    Regeneration,
+    /// Half speed.
+    Slow,
+    /// Double speed.
+    Haste,
+    /// Can only see adjacent tiles.
+    Blindness,
+    /// Loses its turns.
+    Paralysis,
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum Stacking {
+    /// Keep the longer duration and the stronger magnitude.
+    Refresh,
+    /// Add the new duration on to what's left.
+    Extend,
+    /// Keep the longer duration, and add the magnitudes together.
+    Intensify,
+}
+
+impl StatusEffectKind {
+    pub fn stacking(self) -> Stacking {
+        match self {
+            StatusEffectKind::Poison => Stacking::Intensify,
+            StatusEffectKind::Confusion => Stacking::Extend,
+            _ => Stacking::Refresh,
+        }
+    }
+
+    /// Effects that undo this one, and are removed when it is applied.
+    pub fn cancels(self) -> Option<StatusEffectKind> {
+        match self {
+            StatusEffectKind::Slow => Some(StatusEffectKind::Haste),
+            StatusEffectKind::Haste => Some(StatusEffectKind::Slow),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StatusEffectKind::Confusion => "confused",
+            StatusEffectKind::Poison => "poisoned",
+            StatusEffectKind::Regeneration => "regenerating",
+            StatusEffectKind::Slow => "slowed",
+            StatusEffectKind::Haste => "hasted",
+            StatusEffectKind::Blindness => "blind",
+            StatusEffectKind::Paralysis => "paralysed",
+        }
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Serialize, Deserialize, Debug)]
+pub struct StatusEffect {
+    pub kind: StatusEffectKind,
+    /// Turns left, counting the current one.
+    pub turns: i32,
+    /// How strong it is, for effects where that means something (eg: poison damage per turn).
+    pub magnitude: i32,
+}
+
+/// Everything currently affecting an entity, player or monster. At most one entry per kind.
+#[derive(Component, Serialize, Deserialize, Clone, Default)]
+pub struct StatusEffects {
+    pub effects: Vec<StatusEffect>,
+}
+
+impl StatusEffects {
+    /// Apply `effect` to `target`, stacking it with anything of the same kind it already has.
+    pub fn add(store: &mut WriteStorage<StatusEffects>, target: Entity, effect: StatusEffect) {
+        if let Some(current) = store.get_mut(target) {
+            current.apply(effect);
+        } else {
+            store.insert(target, StatusEffects{ effects: vec![effect] }).expect("Unable to insert status effects");
+        }
+    }
+
+    pub fn apply(&mut self, effect: StatusEffect) {
+        if let Some(cancelled) = effect.kind.cancels() {
+            self.effects.retain(|e| e.kind != cancelled);
+        }
+
+        match self.effects.iter_mut().find(|e| e.kind == effect.kind) {
+            None => self.effects.push(effect),
+            Some(existing) => match effect.kind.stacking() {
+                Stacking::Refresh => {
+                    existing.turns = i32::max(existing.turns, effect.turns);
+                    existing.magnitude = i32::max(existing.magnitude, effect.magnitude);
+                }
+                Stacking::Extend => existing.turns += effect.turns,
+                Stacking::Intensify => {
+                    existing.turns = i32::max(existing.turns, effect.turns);
+                    existing.magnitude += effect.magnitude;
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, kind: StatusEffectKind) -> Option<&StatusEffect> {
+        self.effects.iter().find(|e| e.kind == kind)
+    }
+
+    pub fn has(&self, kind: StatusEffectKind) -> bool {
+        self.get(kind).is_some()
+    }
+
+    /// `speed` after haste and slow.
+    pub fn adjust_speed(&self, speed: i32) -> i32 {
+        if self.has(StatusEffectKind::Haste) {
+            speed * 2
+        } else if self.has(StatusEffectKind::Slow) {
+            speed / 2
+        } else {
+            speed
+        }
+    }
+}
+
+/// On an item: applied to whatever it's used on. On a monster: applied to whatever it hits.
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct InflictsStatus {
+    pub effect: StatusEffect
+}
+
+/// Not drawn, and doesn't show in tooltips, until it's found by searching or by setting it off.
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct Hidden {}
+
+/// Goes off on whoever steps onto its tile, applying its `InflictsDamage`, `InflictsStatus` and `Teleports`.
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct EntryTrigger {}
+
+/// Sends whatever it's used on to a random spot on the level.
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct Teleports {}
+
+/// Stepped onto a new tile this turn, so entry triggers there go off. Not saved: cleared every turn.
+#[derive(Component, Clone)]
+pub struct EntityMoved {}
+
+#[derive(PartialEq, Copy, Clone, Serialize, Deserialize, Debug)]
+pub enum HungerState {
+    WellFed,
+    Normal,
+    Hungry,
+    Starving,
+}
+
+/// Counts down each of its owner's turns, and moves to the next hunger state when it runs out.
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct HungerClock {
+    pub state: HungerState,
+    pub duration: i32,
+}
+
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct ProvidesFood {}
+
+#[derive(PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub enum EquipmentSlot {
+    Melee,
+    Shield,
+    /// Bows and the like, fired with whatever key `Command::Fire` is bound to.
+    Ranged,
+}
+
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct Equippable {
+    pub slot: EquipmentSlot
+}
+
+#[derive(Component, ConvertSaveload, Clone)]
+pub struct Equipped {
+    pub owner: Entity,
+    pub slot: EquipmentSlot
+}
+
+#[derive(Component, ConvertSaveload, Clone)]
+pub struct MeleePowerBonus {
+    pub power: i32
+}
+
 #[derive(Component, ConvertSaveload, Clone)]
-pub struct Confusion {
-    pub turns: i32
+pub struct DefenseBonus {
+    pub defense: i32
 }
 
 // Marker to save/load.
@@ -129,31 +554,69 @@ pub struct SerializeMe;
 // Special component that exists to help serialize the game data
 #[derive(Component, Serialize, Deserialize, Clone)]
 pub struct SerializationHelper {
-    pub map: Map
+    pub map: Map,
+    pub turns: i32,
+    pub dungeon: MasterDungeonMap,
+    pub log: GameLog,
+    pub identification: Identification,
+    pub stats: RunStats,
 }
 
 pub fn register_components(ecs: &mut World) {
     ecs.register::<Position>();
+    ecs.register::<OtherLevelPosition>();
     ecs.register::<Renderable>();
     ecs.register::<Player>();
     ecs.register::<Viewshed>();
+    ecs.register::<LightSource>();
     ecs.register::<Monster>();
+    ecs.register::<Vendor>();
+    ecs.register::<MonsterBrain>();
+    ecs.register::<AiBehaviour>();
     ecs.register::<Name>();
     ecs.register::<BlocksTile>();
     ecs.register::<CombatStats>();
     ecs.register::<WantsToMelee>();
     ecs.register::<SufferDamage>();
+    ecs.register::<Initiative>();
+    ecs.register::<MyTurn>();
+    ecs.register::<LastAttacker>();
+    ecs.register::<Experience>();
+    ecs.register::<Wallet>();
+    ecs.register::<GivesExperience>();
     ecs.register::<Item>();
     ecs.register::<InBackpack>();
     ecs.register::<WantsToPickupItem>();
     ecs.register::<WantsToUseItem>();
     ecs.register::<WantsToDropItem>();
     ecs.register::<Consumable>();
+    ecs.register::<Key>();
+    ecs.register::<GoalItem>();
+    ecs.register::<Gold>();
+    ecs.register::<Price>();
     ecs.register::<ProvidesHealing>();
     ecs.register::<Ranged>();
+    ecs.register::<RangedWeapon>();
+    ecs.register::<Ammo>();
+    ecs.register::<Stackable>();
+    ecs.register::<MagicItem>();
+    ecs.register::<WantsToShoot>();
+    ecs.register::<SpecialAttacks>();
+    ecs.register::<WantsToUseSpecial>();
     ecs.register::<AreaOfEffect>();
     ecs.register::<InflictsDamage>();
-    ecs.register::<Confusion>();
+    ecs.register::<StatusEffects>();
+    ecs.register::<InflictsStatus>();
+    ecs.register::<Hidden>();
+    ecs.register::<EntryTrigger>();
+    ecs.register::<Teleports>();
+    ecs.register::<EntityMoved>();
+    ecs.register::<HungerClock>();
+    ecs.register::<ProvidesFood>();
+    ecs.register::<Equippable>();
+    ecs.register::<Equipped>();
+    ecs.register::<MeleePowerBonus>();
+    ecs.register::<DefenseBonus>();
     ecs.register::<SimpleMarker<SerializeMe>>();
     ecs.register::<SerializationHelper>();
 }
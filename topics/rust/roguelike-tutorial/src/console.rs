@@ -0,0 +1,232 @@
+use rltk::{RGB,Rltk,VirtualKeyCode,FontCharType};
+
+/// Somewhere to draw the game and read the player's keys and mouse from. `draw_map` and the gui only talk to this,
+/// so the same screens work in the rltk window and in a `TextConsole`.
+pub trait Console {
+    fn cls(&mut self);
+    fn set(&mut self, x: i32, y: i32, fg: RGB, bg: RGB, glyph: FontCharType);
+    fn set_bg(&mut self, x: i32, y: i32, bg: RGB);
+    /// White on black.
+    fn print(&mut self, x: i32, y: i32, text: &str);
+    fn print_color(&mut self, x: i32, y: i32, fg: RGB, bg: RGB, text: &str);
+    fn print_color_centered(&mut self, y: i32, fg: RGB, bg: RGB, text: &str);
+    /// A filled box with a single line border, from (x, y) to (x + width, y + height) inclusive.
+    fn draw_box(&mut self, x: i32, y: i32, width: i32, height: i32, fg: RGB, bg: RGB);
+    /// A bar `width` cells long, filled in proportion to `n` out of `max`.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_bar_horizontal(&mut self, x: i32, y: i32, width: i32, n: i32, max: i32, fg: RGB, bg: RGB);
+
+    /// The key pressed this frame, if any.
+    fn key(&self) -> Option<VirtualKeyCode>;
+    fn mouse_pos(&self) -> (i32, i32);
+    fn left_click(&self) -> bool;
+}
+
+impl Console for Rltk {
+    fn cls(&mut self) {
+        Rltk::cls(self);
+    }
+
+    fn set(&mut self, x: i32, y: i32, fg: RGB, bg: RGB, glyph: FontCharType) {
+        Rltk::set(self, x, y, fg, bg, glyph);
+    }
+
+    fn set_bg(&mut self, x: i32, y: i32, bg: RGB) {
+        Rltk::set_bg(self, x, y, bg);
+    }
+
+    fn print(&mut self, x: i32, y: i32, text: &str) {
+        Rltk::print(self, x, y, text);
+    }
+
+    fn print_color(&mut self, x: i32, y: i32, fg: RGB, bg: RGB, text: &str) {
+        Rltk::print_color(self, x, y, fg, bg, text);
+    }
+
+    fn print_color_centered(&mut self, y: i32, fg: RGB, bg: RGB, text: &str) {
+        Rltk::print_color_centered(self, y, fg, bg, text);
+    }
+
+    fn draw_box(&mut self, x: i32, y: i32, width: i32, height: i32, fg: RGB, bg: RGB) {
+        Rltk::draw_box(self, x, y, width, height, fg, bg);
+    }
+
+    fn draw_bar_horizontal(&mut self, x: i32, y: i32, width: i32, n: i32, max: i32, fg: RGB, bg: RGB) {
+        Rltk::draw_bar_horizontal(self, x, y, width, n, max, fg, bg);
+    }
+
+    fn key(&self) -> Option<VirtualKeyCode> {
+        self.key
+    }
+
+    fn mouse_pos(&self) -> (i32, i32) {
+        Rltk::mouse_pos(self)
+    }
+
+    fn left_click(&self) -> bool {
+        self.left_click
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+struct Cell {
+    glyph: char,
+    fg: RGB,
+    bg: RGB,
+}
+
+impl Cell {
+    fn blank() -> Cell {
+        Cell{ glyph: ' ', fg: RGB::named(rltk::WHITE), bg: RGB::named(rltk::BLACK) }
+    }
+}
+
+/// A screen held in memory as characters and colours. Print it as plain text for tests and logs,
+/// or with ANSI colours to watch the game in a terminal. Input is whatever the fields say, so scripts can set it.
+pub struct TextConsole {
+    width: i32,
+    height: i32,
+    cells: Vec<Cell>,
+    pub key: Option<VirtualKeyCode>,
+    pub mouse_pos: (i32, i32),
+    pub left_click: bool,
+}
+
+impl TextConsole {
+    pub fn new(width: i32, height: i32) -> TextConsole {
+        TextConsole {
+            width,
+            height,
+            cells: vec![Cell::blank(); (width * height) as usize],
+            key: None,
+            // Off the screen, so there's no cursor or tooltip unless a test asks for one.
+            mouse_pos: (-1, -1),
+            left_click: false,
+        }
+    }
+
+    /// The same size as the rltk window.
+    pub fn screen() -> TextConsole {
+        TextConsole::new(80, 50)
+    }
+
+    fn cell_mut(&mut self, x: i32, y: i32) -> Option<&mut Cell> {
+        if x < 0 || x >= self.width || y < 0 || y >= self.height {
+            return None;
+        }
+        let idx = (y * self.width + x) as usize;
+        self.cells.get_mut(idx)
+    }
+
+    fn put(&mut self, x: i32, y: i32, glyph: char, fg: RGB, bg: RGB) {
+        if let Some(cell) = self.cell_mut(x, y) {
+            *cell = Cell{ glyph, fg, bg };
+        }
+    }
+
+    /// Row `y` as text, without trailing spaces.
+    pub fn line(&self, y: i32) -> String {
+        let start = (y * self.width) as usize;
+        let row: String = self.cells[start .. start + self.width as usize].iter().map(|cell| cell.glyph).collect();
+        row.trim_end().to_string()
+    }
+
+    /// The whole screen as text, one line per row.
+    pub fn to_text(&self) -> String {
+        (0..self.height).map(|y| self.line(y)).collect::<Vec<String>>().join("\n")
+    }
+
+    /// The whole screen with 24-bit ANSI colour codes, ready to print to a terminal.
+    pub fn to_ansi(&self) -> String {
+        let mut out = String::new();
+        for y in 0..self.height {
+            let mut current: Option<(RGB, RGB)> = None;
+            for x in 0..self.width {
+                let cell = self.cells[(y * self.width + x) as usize];
+                if current != Some((cell.fg, cell.bg)) {
+                    out += &format!("\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m",
+                        channel(cell.fg.r), channel(cell.fg.g), channel(cell.fg.b),
+                        channel(cell.bg.r), channel(cell.bg.g), channel(cell.bg.b));
+                    current = Some((cell.fg, cell.bg));
+                }
+                out.push(cell.glyph);
+            }
+            out += "\x1b[0m\n";
+        }
+        out
+    }
+}
+
+fn channel(value: f32) -> u8 {
+    (value * 255.0) as u8
+}
+
+impl Console for TextConsole {
+    fn cls(&mut self) {
+        for cell in self.cells.iter_mut() {
+            *cell = Cell::blank();
+        }
+    }
+
+    fn set(&mut self, x: i32, y: i32, fg: RGB, bg: RGB, glyph: FontCharType) {
+        self.put(x, y, rltk::to_char(glyph as u8), fg, bg);
+    }
+
+    fn set_bg(&mut self, x: i32, y: i32, bg: RGB) {
+        if let Some(cell) = self.cell_mut(x, y) {
+            cell.bg = bg;
+        }
+    }
+
+    fn print(&mut self, x: i32, y: i32, text: &str) {
+        self.print_color(x, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), text);
+    }
+
+    fn print_color(&mut self, x: i32, y: i32, fg: RGB, bg: RGB, text: &str) {
+        for (i, glyph) in text.chars().enumerate() {
+            self.put(x + i as i32, y, glyph, fg, bg);
+        }
+    }
+
+    fn print_color_centered(&mut self, y: i32, fg: RGB, bg: RGB, text: &str) {
+        let x = self.width / 2 - text.chars().count() as i32 / 2;
+        self.print_color(x, y, fg, bg, text);
+    }
+
+    fn draw_box(&mut self, x: i32, y: i32, width: i32, height: i32, fg: RGB, bg: RGB) {
+        for box_y in y ..= y + height {
+            for box_x in x ..= x + width {
+                let glyph = match (box_x == x, box_x == x + width, box_y == y, box_y == y + height) {
+                    (true, _, true, _) => '┌',
+                    (_, true, true, _) => '┐',
+                    (true, _, _, true) => '└',
+                    (_, true, _, true) => '┘',
+                    (_, _, true, _) | (_, _, _, true) => '─',
+                    (true, _, _, _) | (_, true, _, _) => '│',
+                    _ => ' ',
+                };
+                self.put(box_x, box_y, glyph, fg, bg);
+            }
+        }
+    }
+
+    fn draw_bar_horizontal(&mut self, x: i32, y: i32, width: i32, n: i32, max: i32, fg: RGB, bg: RGB) {
+        let filled = (n as f32 / max as f32 * width as f32) as i32;
+        for i in 0..width {
+            let glyph = if i <= filled { '▓' } else { '░' };
+            self.put(x + i, y, glyph, fg, bg);
+        }
+    }
+
+    fn key(&self) -> Option<VirtualKeyCode> {
+        self.key
+    }
+
+    fn mouse_pos(&self) -> (i32, i32) {
+        self.mouse_pos
+    }
+
+    fn left_click(&self) -> bool {
+        self.left_click
+    }
+}
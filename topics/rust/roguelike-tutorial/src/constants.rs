@@ -1,5 +1,12 @@
-pub const WORLD_WIDTH: usize = 80;
-pub const WORLD_HEIGHT: usize = 43;
-pub const MAPCOUNT: usize = WORLD_WIDTH * WORLD_HEIGHT;
+/// The part of the screen the map is drawn in. The UI takes the rows below it.
+pub const VIEW_WIDTH: i32 = 80;
+pub const VIEW_HEIGHT: i32 = 43;
+
+/// How big a generated level is. Bigger than the view, so the camera scrolls to follow the player.
+pub const MAP_WIDTH: i32 = 120;
+pub const MAP_HEIGHT: i32 = 70;
 
 pub const MAX_MONSTERS : i32 = 4;
+
+/// The bottom of the dungeon, where the amulet is kept. There are no stairs further down.
+pub const FINAL_DEPTH: i32 = 10;
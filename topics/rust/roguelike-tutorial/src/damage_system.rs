@@ -1,18 +1,30 @@
 use specs::prelude::*;
-use super::{CombatStats, SufferDamage, Player, GameLog, Name};
+use super::{CombatStats, SufferDamage, Player, GameLog, Name, LastAttacker, GivesExperience, Experience, Position, InBackpack};
+use crate::morgue::RunStats;
+use crate::RunState;
+
+/// Max HP gained on every level up, before the chosen boost.
+pub const LEVEL_UP_HP: i32 = 5;
 
 pub struct DamageSystem {}
 
 impl<'a> System<'a> for DamageSystem {
-    type SystemData = ( WriteStorage<'a, CombatStats>,
+    type SystemData = ( Entities<'a>,
+                        ReadExpect<'a, Entity>,
+                        WriteExpect<'a, RunStats>,
+                        WriteStorage<'a, CombatStats>,
                         WriteStorage<'a, SufferDamage> );
 
     fn run(&mut self, data : Self::SystemData) {
-        let (mut stats, mut damage) = data;
+        let (entities, player_entity, mut run_stats, mut stats, mut damage) = data;
 
         // Apply damage for round
-        for (mut stats, damage) in (&mut stats, &damage).join() {
+        for (entity, stats, damage) in (&entities, &mut stats, &damage).join() {
             stats.hp -= damage.amount.iter().sum::<i32>();
+
+            if entity == *player_entity && stats.hp < 1 && run_stats.cause_of_death.is_none() {
+                run_stats.cause_of_death = Some(damage.cause.clone());
+            }
         }
 
         // Clear for next round
@@ -20,13 +32,24 @@ impl<'a> System<'a> for DamageSystem {
     }
 }
 
+/// Has the player run out of hit points?
+pub fn player_is_dead(ecs: &World) -> bool {
+    let player_entity = ecs.fetch::<Entity>();
+    ecs.read_storage::<CombatStats>().get(*player_entity).is_some_and(|stats| stats.hp < 1)
+}
+
 pub fn delete_the_dead(ecs: &mut World) {
     let mut dead: Vec<Entity> = Vec::new();
-    
+    // This runs every frame, the game over screen included, where the player is still dead.
+    let run_over = matches!(*ecs.fetch::<RunState>(), RunState::GameOver | RunState::Victory);
+
     {
         let mut log = ecs.write_resource::<GameLog>();
+        let mut run_stats = ecs.write_resource::<RunStats>();
+        let player_entity = *ecs.fetch::<Entity>();
         let combat_stats = ecs.read_storage::<CombatStats>();
         let names = ecs.read_storage::<Name>();
+        let last_attackers = ecs.read_storage::<LastAttacker>();
         let entities = ecs.entities();
         let players = ecs.read_storage::<Player>();
         for (entity, stats) in (&entities, &combat_stats).join() {
@@ -35,18 +58,90 @@ pub fn delete_the_dead(ecs: &mut World) {
                     None => {
                         let victim_name = names.get(entity);
                         if let Some(victim_name) = victim_name {
-                            log.info(format!("{} is dead", &victim_name.name));
+                            log.combat(format!("{} is dead", &victim_name.name));
+
+                            if last_attackers.get(entity).is_some_and(|last| last.attacker == player_entity) {
+                                run_stats.record_kill(&victim_name.name);
+                            }
                         }
                         dead.push(entity);
                     },
-                    Some(_) => log.info("You are dead".to_string())
+                    Some(_) => {
+                        if !run_over {
+                            log.combat("You are dead".to_string());
+                        }
+                    }
                 }
                 
             }
         }
     }
 
+    for victim in dead.iter() {
+        award_experience(ecs, *victim);
+        drop_belongings(ecs, *victim);
+    }
+
     for victim in dead {
         ecs.delete_entity(victim).expect("Unable to delete");
     }
 }
+
+/// Whatever `victim` was carrying, like a shopkeeper's stock, falls where they died rather than
+/// being left in the pack of something that's gone.
+fn drop_belongings(ecs: &mut World, victim: Entity) {
+    let entities = ecs.entities();
+    let mut backpack = ecs.write_storage::<InBackpack>();
+    let mut positions = ecs.write_storage::<Position>();
+    let fell_at = positions.get(victim).map(|pos| (pos.x, pos.y));
+
+    let carried: Vec<Entity> = (&entities, &backpack).join()
+        .filter(|(_item, pack)| pack.owner == victim)
+        .map(|(item, _pack)| item)
+        .collect();
+    for item in carried {
+        backpack.remove(item);
+        match fell_at {
+            Some((x, y)) => { positions.insert(item, Position{ x, y }).expect("Unable to drop item"); }
+            None => entities.delete(item).expect("Unable to delete item"),
+        }
+    }
+}
+
+/// Credit whoever landed the killing blow with the victim's XP, levelling them up if it's enough.
+fn award_experience(ecs: &mut World, victim: Entity) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let last_attackers = ecs.read_storage::<LastAttacker>();
+    let gives_experience = ecs.read_storage::<GivesExperience>();
+    let mut experience = ecs.write_storage::<Experience>();
+    let mut combat_stats = ecs.write_storage::<CombatStats>();
+    let mut log = ecs.write_resource::<GameLog>();
+
+    let killer = match last_attackers.get(victim) {
+        None => return,
+        Some(last) => last.attacker,
+    };
+    let xp = match gives_experience.get(victim) {
+        None => return,
+        Some(gives) => gives.xp,
+    };
+    let killer_xp = match experience.get_mut(killer) {
+        None => return,
+        Some(killer_xp) => killer_xp,
+    };
+
+    killer_xp.xp += xp;
+    while killer_xp.xp >= killer_xp.xp_to_next_level() {
+        killer_xp.xp -= killer_xp.xp_to_next_level();
+        killer_xp.level += 1;
+        killer_xp.pending_boosts += 1;
+
+        if let Some(stats) = combat_stats.get_mut(killer) {
+            stats.max_hp += LEVEL_UP_HP;
+            stats.hp += LEVEL_UP_HP;
+        }
+        if killer == player_entity {
+            log.level(format!("Welcome to level {}!", killer_xp.level));
+        }
+    }
+}
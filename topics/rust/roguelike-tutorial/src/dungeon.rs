@@ -0,0 +1,81 @@
+use serde::{Serialize,Deserialize};
+use specs::prelude::*;
+use std::collections::HashMap;
+use crate::components::*;
+use crate::map::Map;
+
+/// Every level the player has left, keyed by depth. The current level lives in the `Map` resource instead.
+#[derive(Default, Serialize, Deserialize, Clone)]
+pub struct MasterDungeonMap {
+    maps: HashMap<i32, Map>,
+}
+
+impl MasterDungeonMap {
+    pub fn new() -> MasterDungeonMap {
+        MasterDungeonMap{ maps: HashMap::new() }
+    }
+
+    pub fn store_map(&mut self, map: &Map) {
+        self.maps.insert(map.depth, map.clone());
+    }
+
+    /// The level at `depth` as the player left it, if they've been there.
+    pub fn get_map(&self, depth: i32) -> Option<Map> {
+        self.maps.get(&depth).map(|stored| {
+            let mut map = stored.clone();
+            // Not saved, and rebuilt by the indexing and visibility systems anyway.
+            map.tile_content = vec![Vec::new(); map.tiles.len()];
+            for t in map.visible_tiles.iter_mut() {
+                *t = false;
+            }
+            map
+        })
+    }
+}
+
+/// Take everything but the player off the current level, remembering which level it was on.
+/// Frozen entities have no `Position`, so no system touches them until the player comes back.
+pub fn freeze_level_entities(ecs: &mut World) {
+    let entities = ecs.entities();
+    let mut positions = ecs.write_storage::<Position>();
+    let mut other_level_positions = ecs.write_storage::<OtherLevelPosition>();
+    let player_entity = ecs.fetch::<Entity>();
+    let depth = ecs.fetch::<Map>().depth;
+
+    let mut frozen: Vec<Entity> = Vec::new();
+    for (entity, pos) in (&entities, &positions).join() {
+        if entity != *player_entity {
+            other_level_positions.insert(entity, OtherLevelPosition{ x: pos.x, y: pos.y, depth }).expect("Unable to insert other level position");
+            frozen.push(entity);
+        }
+    }
+
+    for entity in frozen.iter() {
+        positions.remove(*entity);
+    }
+}
+
+/// Put back everything that was frozen on the current level.
+pub fn thaw_level_entities(ecs: &mut World) {
+    let entities = ecs.entities();
+    let mut positions = ecs.write_storage::<Position>();
+    let mut other_level_positions = ecs.write_storage::<OtherLevelPosition>();
+    let mut viewsheds = ecs.write_storage::<Viewshed>();
+    let depth = ecs.fetch::<Map>().depth;
+
+    let mut thawed: Vec<Entity> = Vec::new();
+    for (entity, pos) in (&entities, &other_level_positions).join() {
+        if pos.depth == depth {
+            positions.insert(entity, Position{ x: pos.x, y: pos.y }).expect("Unable to insert position");
+            thawed.push(entity);
+        }
+    }
+
+    for entity in thawed.iter() {
+        other_level_positions.remove(*entity);
+        // They haven't been looking around while we were away.
+        if let Some(viewshed) = viewsheds.get_mut(*entity) {
+            viewshed.dirty = true;
+        }
+    }
+}
@@ -1,20 +1,119 @@
+use rltk::RGB;
+use serde::{Serialize,Deserialize};
+use std::collections::VecDeque;
+
+/// The oldest entries are dropped once the log holds this many.
+pub const MAX_LOG_ENTRIES: usize = 200;
+
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Serialize, Deserialize, Debug)]
+pub enum LogCategory {
+    /// Attacks, damage and deaths.
+    Combat,
+    /// Picking up, using and dropping things.
+    Item,
+    /// Everything else: status effects, hunger, saving, things you can't do.
+    System,
+    /// Changing depth and levelling up.
+    Level,
+}
+
+impl LogCategory {
+    pub fn colour(self) -> RGB {
+        match self {
+            LogCategory::Combat => RGB::named(rltk::RED),
+            LogCategory::Item => RGB::named(rltk::CYAN),
+            LogCategory::System => RGB::named(rltk::WHITE),
+            LogCategory::Level => RGB::named(rltk::YELLOW),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LogCategory::Combat => "Combat",
+            LogCategory::Item => "Items",
+            LogCategory::System => "System",
+            LogCategory::Level => "Level",
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Serialize, Deserialize, Debug)]
+pub struct LogEntry {
+    pub text: String,
+    pub category: LogCategory,
+    /// The turn it last happened on.
+    pub turn: i32,
+    /// How many times in a row it happened.
+    pub count: i32,
+}
+
+impl LogEntry {
+    /// The text as shown, with repeats counted on the end.
+    pub fn display(&self) -> String {
+        if self.count > 1 {
+            format!("{} x{}", self.text, self.count)
+        } else {
+            self.text.clone()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
 pub struct GameLog {
-    entries: Vec<String>
+    entries: VecDeque<LogEntry>,
+    /// Stamped on new entries. Kept in step with `TurnCount`.
+    turn: i32,
 }
 
 impl GameLog {
 
     pub fn new(init_messages: &[String]) -> GameLog {
-        GameLog {
-            entries: Vec::from(init_messages),
+        let mut log = GameLog::default();
+        for message in init_messages.iter() {
+            log.system(message.clone());
+        }
+        log
+    }
+
+    pub fn combat(&mut self, message: String) {
+        self.add(LogCategory::Combat, message);
+    }
+
+    pub fn item(&mut self, message: String) {
+        self.add(LogCategory::Item, message);
+    }
+
+    pub fn system(&mut self, message: String) {
+        self.add(LogCategory::System, message);
+    }
+
+    pub fn level(&mut self, message: String) {
+        self.add(LogCategory::Level, message);
+    }
+
+    /// Add an entry, or count it again if it's the same as the last one.
+    pub fn add(&mut self, category: LogCategory, message: String) {
+        let turn = self.turn;
+        if let Some(last) = self.entries.back_mut() {
+            if last.category == category && last.text == message {
+                last.count += 1;
+                last.turn = turn;
+                return;
+            }
+        }
+
+        self.entries.push_back(LogEntry{ text: message, category, turn, count: 1 });
+        while self.entries.len() > MAX_LOG_ENTRIES {
+            self.entries.pop_front();
         }
     }
 
-    pub fn info(&mut self, message: String) {
-        self.entries.push(message);
+    pub fn set_turn(&mut self, turn: i32) {
+        self.turn = turn;
     }
 
-    pub fn entries(&self) -> &Vec<String> {
+    /// Oldest first.
+    pub fn entries(&self) -> &VecDeque<LogEntry> {
         &self.entries
     }
 }
@@ -1,34 +1,69 @@
-use rltk::{RGB,Rltk,Point,VirtualKeyCode};
+use rltk::{RGB,Point,VirtualKeyCode};
 use crate::components::*;
 use specs::prelude::*;
 use crate::constants::*;
 use crate::map::Map;
-use crate::gamelog::GameLog;
+use crate::camera::Camera;
+use crate::console::Console;
+use crate::gamelog::{GameLog,LogCategory};
 use crate::{State,RunState};
+use crate::saveload_system::{self,SaveMetadata,SaveError};
+use crate::ranged_combat_system::line_of_fire;
+use crate::identification::Identification;
+use crate::inventory_system::{self,BACKPACK_SLOTS};
+use crate::shop;
+use crate::keybindings::{Command,Keybindings};
+use crate::morgue::{self,RunStats};
 
-pub fn draw_ui(ecs: &World, ctx: &mut Rltk) {
-    ctx.draw_box(0, WORLD_HEIGHT, WORLD_WIDTH-1, 6, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
+/// How long a projectile takes to cross each tile.
+pub const PROJECTILE_FRAME_MS: f32 = 30.0;
+
+pub fn draw_ui(ecs: &World, ctx: &mut dyn Console) {
+    ctx.draw_box(0, VIEW_HEIGHT, VIEW_WIDTH-1, 6, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
 
     let map = ecs.fetch::<Map>();
     let depth = format!("Depth: {}", map.depth);
-    ctx.print_color(2, 43, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), &depth);
+    ctx.print_color(2, VIEW_HEIGHT, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), &depth);
 
     let combat_states = ecs.read_storage::<CombatStats>();
     let players = ecs.read_storage::<Player>();
     for (_player, stats) in (&players, &combat_states).join() {
         let health = format!("HP: {} / {}", stats.hp, stats.max_hp);
-        ctx.print_color(12, WORLD_HEIGHT, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), &health);
+        ctx.print_color(12, VIEW_HEIGHT, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), &health);
 
-        ctx.draw_bar_horizontal(28, 43, 51, stats.hp, stats.max_hp, RGB::named(rltk::RED), RGB::named(rltk::BLACK));
+        ctx.draw_bar_horizontal(28, VIEW_HEIGHT, 51, stats.hp, stats.max_hp, RGB::named(rltk::RED), RGB::named(rltk::BLACK));
     }
 
-    let log = ecs.fetch::<GameLog>();
-    let mut y = 44;
-    for s in log.entries().iter().rev() {
-        if y < 49 {
-            ctx.print(2, y, s);
+    let hunger_clocks = ecs.read_storage::<HungerClock>();
+    for (_player, clock) in (&players, &hunger_clocks).join() {
+        match clock.state {
+            HungerState::WellFed => ctx.print_color(VIEW_WIDTH - 11, VIEW_HEIGHT + 6, RGB::named(rltk::GREEN), RGB::named(rltk::BLACK), "Well Fed"),
+            HungerState::Normal => {}
+            HungerState::Hungry => ctx.print_color(VIEW_WIDTH - 11, VIEW_HEIGHT + 6, RGB::named(rltk::ORANGE), RGB::named(rltk::BLACK), "Hungry"),
+            HungerState::Starving => ctx.print_color(VIEW_WIDTH - 11, VIEW_HEIGHT + 6, RGB::named(rltk::RED), RGB::named(rltk::BLACK), "Starving"),
         }
-        y += 1;
+    }
+
+    let wallets = ecs.read_storage::<Wallet>();
+    for (_player, wallet) in (&players, &wallets).join() {
+        ctx.print_color(VIEW_WIDTH - 25, VIEW_HEIGHT + 6, RGB::named(rltk::GOLD), RGB::named(rltk::BLACK), &format!("Gold: {}", wallet.gold));
+    }
+
+    // Status effects along the bottom of the box, each an icon and the turns it has left.
+    let statuses = ecs.read_storage::<StatusEffects>();
+    for (_player, status) in (&players, &statuses).join() {
+        let mut x = 2;
+        for effect in status.effects.iter() {
+            let (glyph, colour) = status_icon(effect.kind);
+            ctx.set(x, VIEW_HEIGHT + 6, colour, RGB::named(rltk::BLACK), rltk::to_cp437(glyph));
+            ctx.print_color(x + 1, VIEW_HEIGHT + 6, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), &effect.turns.to_string());
+            x += 5;
+        }
+    }
+
+    let log = ecs.fetch::<GameLog>();
+    for (y, entry) in (VIEW_HEIGHT + 1 .. VIEW_HEIGHT + 6).zip(log.entries().iter().rev()) {
+        ctx.print_color(2, y, entry.category.colour(), RGB::named(rltk::BLACK), &entry.display());
     }
 
     // Draw mouse cursor
@@ -38,24 +73,98 @@ pub fn draw_ui(ecs: &World, ctx: &mut Rltk) {
     draw_tooltips(ecs, ctx);
 }
 
-fn draw_tooltips(ecs: &World, ctx: &mut Rltk) {
+fn status_icon(kind: StatusEffectKind) -> (char, RGB) {
+    match kind {
+        StatusEffectKind::Confusion => ('?', RGB::named(rltk::MAGENTA)),
+        StatusEffectKind::Poison => ('♣', RGB::named(rltk::GREEN)),
+        StatusEffectKind::Regeneration => ('♥', RGB::named(rltk::RED)),
+        StatusEffectKind::Slow => ('▼', RGB::named(rltk::CYAN)),
+        StatusEffectKind::Haste => ('▲', RGB::named(rltk::YELLOW)),
+        StatusEffectKind::Blindness => ('○', RGB::named(rltk::GREY)),
+        StatusEffectKind::Paralysis => ('≡', RGB::named(rltk::ORANGE)),
+    }
+}
+
+/// Draw each shot in flight `frame` tiles along its line of fire, skipping anything the player can't see.
+/// Returns false once they've all landed.
+pub fn draw_projectiles(ecs: &World, ctx: &mut dyn Console, frame: usize) -> bool {
+    let map = ecs.fetch::<Map>();
+    let positions = ecs.read_storage::<Position>();
+    let shots = ecs.read_storage::<WantsToShoot>();
+    let camera = Camera::for_player(ecs);
+
+    let mut in_flight = false;
+    for (shot, pos) in (&shots, &positions).join() {
+        let from = Point::new(pos.x, pos.y);
+        let path: Vec<Point> = line_of_fire(&map, from, shot.target).into_iter()
+            .filter(|p| map.visible_tiles[map.xy_idx(p.x, p.y)])
+            .collect();
+        if let Some(point) = path.get(frame) {
+            if let Some(screen) = camera.to_screen(*point) {
+                ctx.set(screen.x, screen.y, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), rltk::to_cp437(projectile_glyph(from, shot.target)));
+            }
+            in_flight = true;
+        }
+    }
+
+    in_flight
+}
+
+fn projectile_glyph(from: Point, to: Point) -> char {
+    let (dx, dy) = (to.x - from.x, to.y - from.y);
+    if dy.abs() * 2 < dx.abs() {
+        '-'
+    } else if dx.abs() * 2 < dy.abs() {
+        '|'
+    } else if (dx > 0) == (dy > 0) {
+        '\\'
+    } else {
+        '/'
+    }
+}
+
+/// An item's name as the player knows it, with how many there are if it's a stack.
+fn item_label(identification: &Identification, name: &Name, stack: Option<&Stackable>) -> String {
+    match stack {
+        None => identification.name(&name.name),
+        Some(stack) => format!("{} ({})", identification.name(&name.name), stack.count),
+    }
+}
+
+/// The map tile under the mouse, if it's over the map.
+fn mouse_tile(ecs: &World, ctx: &dyn Console) -> Option<Point> {
+    let map = ecs.fetch::<Map>();
+    let (x, y) = ctx.mouse_pos();
+    Camera::for_player(ecs).to_world(Point::new(x, y)).filter(|tile| map.contains(tile.x, tile.y))
+}
+
+fn draw_tooltips(ecs: &World, ctx: &mut dyn Console) {
     let tooltip_fg = RGB::named(rltk::WHITE);
     let tooltip_bg = RGB::named(rltk::GREY);
 
-    let map = ecs.fetch::<Map>();
     let names = ecs.read_storage::<Name>();
     let positions = ecs.read_storage::<Position>();
 
     let mouse_pos = ctx.mouse_pos();
-    if mouse_pos.0 >= map.width || mouse_pos.1 >= map.height {
-        // Mouse outide of map.
-        return;
-    }
+    let tile = match mouse_tile(ecs, ctx) {
+        None => return, // Mouse outside of map.
+        Some(tile) => tile,
+    };
+
+    let statuses = ecs.read_storage::<StatusEffects>();
+    let hidden = ecs.read_storage::<Hidden>();
+    let identification = ecs.fetch::<Identification>();
 
     let mut tooltip : Vec<String> = Vec::new();
-    for (name, position) in (&names, &positions).join() {
-        if position.x == mouse_pos.0 && position.y == mouse_pos.1 {
-            tooltip.push(name.name.to_string());
+    for (entity, name, position, _hidden) in (&ecs.entities(), &names, &positions, !&hidden).join() {
+        if position.x == tile.x && position.y == tile.y {
+            match statuses.get(entity) {
+                None => tooltip.push(identification.name(&name.name)),
+                Some(status) => {
+                    let effects: Vec<&str> = status.effects.iter().map(|e| e.kind.name()).collect();
+                    tooltip.push(format!("{} ({})", name.name, effects.join(", ")));
+                }
+            }
         }
     }
 
@@ -66,7 +175,7 @@ fn draw_tooltips(ecs: &World, ctx: &mut Rltk) {
             .max()
             .unwrap() as i32 + 3;
 
-        if mouse_pos.0 > WORLD_WIDTH as i32 / 2 {
+        if mouse_pos.0 > VIEW_WIDTH / 2 {
             let arrow_pos = Point::new(mouse_pos.0 - 2, mouse_pos.1);
             let left_x = mouse_pos.0 - width;
             let mut y = mouse_pos.1;
@@ -103,99 +212,587 @@ fn draw_tooltips(ecs: &World, ctx: &mut Rltk) {
 #[derive(PartialEq, Copy, Clone)]
 pub enum ItemMenuResult { Cancel, NoResponse, Selected }
 
-pub fn show_inventory(gs : &mut State, ctx : &mut Rltk) -> (ItemMenuResult, Option<Entity>) {
-    let player_entity = gs.ecs.fetch::<Entity>();
-    let names = gs.ecs.read_storage::<Name>();
-    let backpack = gs.ecs.read_storage::<InBackpack>();
-    let entities = gs.ecs.entities();
+/// What sort of thing an item is, for sorting and filtering the inventory.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug)]
+pub enum ItemCategory { Potion, Scroll, Food, Equipment, Ammo, Other }
+
+impl ItemCategory {
+    pub fn name(self) -> &'static str {
+        match self {
+            ItemCategory::Potion => "Potions",
+            ItemCategory::Scroll => "Scrolls",
+            ItemCategory::Food => "Food",
+            ItemCategory::Equipment => "Equipment",
+            ItemCategory::Ammo => "Ammo",
+            ItemCategory::Other => "Other",
+        }
+    }
+
+    pub fn of(ecs: &World, item: Entity) -> ItemCategory {
+        if ecs.read_storage::<Ammo>().get(item).is_some() {
+            return ItemCategory::Ammo;
+        }
+        if ecs.read_storage::<Equippable>().get(item).is_some() {
+            return ItemCategory::Equipment;
+        }
+        if ecs.read_storage::<ProvidesFood>().get(item).is_some() {
+            return ItemCategory::Food;
+        }
+        match ecs.read_storage::<MagicItem>().get(item).map(|magic| magic.kind) {
+            Some(MagicItemKind::Potion) => ItemCategory::Potion,
+            Some(MagicItemKind::Scroll) => ItemCategory::Scroll,
+            None => ItemCategory::Other,
+        }
+    }
+}
+
+/// Left and right step through these in the inventory.
+const ITEM_FILTERS: [Option<ItemCategory>; 7] = [
+    None,
+    Some(ItemCategory::Potion),
+    Some(ItemCategory::Scroll),
+    Some(ItemCategory::Food),
+    Some(ItemCategory::Equipment),
+    Some(ItemCategory::Ammo),
+    Some(ItemCategory::Other),
+];
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum InventorySort { Category, Name, Count }
+
+impl InventorySort {
+    pub fn name(self) -> &'static str {
+        match self {
+            InventorySort::Category => "category",
+            InventorySort::Name => "name",
+            InventorySort::Count => "count",
+        }
+    }
 
-    let inventory = (&backpack, &names).join().filter(|item| item.0.owner == *player_entity );
-    let count = inventory.count();
+    fn next(self) -> InventorySort {
+        match self {
+            InventorySort::Category => InventorySort::Name,
+            InventorySort::Name => InventorySort::Count,
+            InventorySort::Count => InventorySort::Category,
+        }
+    }
+}
 
-    let mut y = (25 - (count / 2)) as i32;
-    ctx.draw_box(15, y-2, 31, (count+3) as i32, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
-    ctx.print_color(18, y-2, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Inventory");
-    ctx.print_color(18, y+count as i32+1, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "ESCAPE to cancel");
+/// How the inventory (or drop menu) is being looked at. Kept in the run state between frames.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct InventoryView {
+    pub sort: InventorySort,
+    /// The only category shown, if any.
+    pub filter: Option<ItemCategory>,
+    /// The highlighted row, whose description is shown alongside.
+    pub cursor: usize,
+}
 
-    let mut equippable : Vec<Entity> = Vec::new();
-    let mut j = 0;
-    for (entity, _pack, name) in (&entities, &backpack, &names).join().filter(|item| item.1.owner == *player_entity ) {
-        ctx.set(17, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437('('));
-        ctx.set(18, y, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), 97+j as u8);
-        ctx.set(19, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437(')'));
+impl Default for InventoryView {
+    fn default() -> InventoryView {
+        InventoryView{ sort: InventorySort::Category, filter: None, cursor: 0 }
+    }
+}
 
-        
-        ctx.print(21, y, &name.name.to_string());
-        equippable.push(entity);
+impl InventoryView {
+    fn step_filter(self, offset: i32) -> InventoryView {
+        let current = ITEM_FILTERS.iter().position(|filter| *filter == self.filter).unwrap_or(0) as i32;
+        let next = (current + offset).rem_euclid(ITEM_FILTERS.len() as i32) as usize;
+        InventoryView{ filter: ITEM_FILTERS[next], cursor: 0, ..self }
+    }
+}
+
+/// One line of the inventory.
+pub struct InventoryRow {
+    pub item: Entity,
+    pub label: String,
+    pub category: ItemCategory,
+    pub worn: bool,
+}
+
+/// What the player has, as `view` shows it: carried items first, then anything worn if `include_worn`.
+pub fn inventory_rows(ecs: &World, view: &InventoryView, include_worn: bool) -> Vec<InventoryRow> {
+    let player_entity = ecs.fetch::<Entity>();
+    let names = ecs.read_storage::<Name>();
+    let backpack = ecs.read_storage::<InBackpack>();
+    let equipped = ecs.read_storage::<Equipped>();
+    let stacks = ecs.read_storage::<Stackable>();
+    let identification = ecs.fetch::<Identification>();
+    let entities = ecs.entities();
+
+    let mut rows: Vec<(InventoryRow, i32)> = Vec::new();
+    for (item, pack, name) in (&entities, &backpack, &names).join() {
+        if pack.owner == *player_entity {
+            let row = InventoryRow{ item, label: item_label(&identification, name, stacks.get(item)), category: ItemCategory::of(ecs, item), worn: false };
+            rows.push((row, stacks.get(item).map_or(1, |stack| stack.count)));
+        }
+    }
+    if include_worn {
+        for (item, worn, name) in (&entities, &equipped, &names).join() {
+            if worn.owner == *player_entity {
+                let row = InventoryRow{ item, label: identification.name(&name.name), category: ItemCategory::of(ecs, item), worn: true };
+                rows.push((row, 1));
+            }
+        }
+    }
+
+    rows.retain(|(row, _count)| view.filter.is_none_or(|category| row.category == category));
+    rows.sort_by(|(a, a_count), (b, b_count)| {
+        let by_view = match view.sort {
+            InventorySort::Category => a.category.cmp(&b.category),
+            InventorySort::Name => std::cmp::Ordering::Equal,
+            InventorySort::Count => b_count.cmp(a_count),
+        };
+        a.worn.cmp(&b.worn).then(by_view).then_with(|| a.label.cmp(&b.label))
+    });
+    rows.into_iter().map(|(row, _count)| row).collect()
+}
+
+/// What the description panel says about an item: what it is, and what its components do.
+/// Effects stay a mystery until it's identified.
+pub fn item_description(ecs: &World, item: Entity) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let category = ItemCategory::of(ecs, item);
+    lines.push(match category {
+        ItemCategory::Potion => "Potion".to_string(),
+        ItemCategory::Scroll => "Scroll".to_string(),
+        _ => category.name().to_string(),
+    });
+    if let Some(stack) = ecs.read_storage::<Stackable>().get(item) {
+        lines.push(format!("{} in the stack", stack.count));
+    }
+
+    let known = ecs.read_storage::<Name>().get(item).is_none_or(|name| ecs.fetch::<Identification>().is_known(&name.name));
+    if !known {
+        lines.push("Unidentified.".to_string());
+        lines.push("Use one to find out".to_string());
+        lines.push("what it does.".to_string());
+        return lines;
+    }
+
+    if ecs.read_storage::<Consumable>().get(item).is_some() {
+        lines.push("Used up when used".to_string());
+    }
+    if let Some(healing) = ecs.read_storage::<ProvidesHealing>().get(item) {
+        lines.push(format!("Heals {} hp", healing.heal_amount));
+    }
+    if ecs.read_storage::<ProvidesFood>().get(item).is_some() {
+        lines.push("Fills you up".to_string());
+    }
+    if let Some(ranged) = ecs.read_storage::<Ranged>().get(item) {
+        lines.push(format!("Targeted, range {}", ranged.range));
+    }
+    if let Some(damage) = ecs.read_storage::<InflictsDamage>().get(item) {
+        lines.push(format!("Deals {} damage", damage.damage));
+    }
+    if let Some(aoe) = ecs.read_storage::<AreaOfEffect>().get(item) {
+        lines.push(format!("Hits everything within {}", aoe.radius));
+    }
+    if let Some(inflicts) = ecs.read_storage::<InflictsStatus>().get(item) {
+        lines.push(format!("Leaves the target {}", inflicts.effect.kind.name()));
+        lines.push(format!("  for {} turns", inflicts.effect.turns));
+    }
+    if let Some(equippable) = ecs.read_storage::<Equippable>().get(item) {
+        let slot = match equippable.slot {
+            EquipmentSlot::Melee => "melee weapon",
+            EquipmentSlot::Shield => "shield",
+            EquipmentSlot::Ranged => "ranged weapon",
+        };
+        lines.push(format!("Worn as a {}", slot));
+    }
+    if let Some(bonus) = ecs.read_storage::<MeleePowerBonus>().get(item) {
+        lines.push(format!("{:+} power", bonus.power));
+    }
+    if let Some(bonus) = ecs.read_storage::<DefenseBonus>().get(item) {
+        lines.push(format!("{:+} defense", bonus.defense));
+    }
+    if let Some(weapon) = ecs.read_storage::<RangedWeapon>().get(item) {
+        lines.push(format!("Shoots for {}, range {}", weapon.power, weapon.range));
+    }
+    if ecs.read_storage::<Ammo>().get(item).is_some() {
+        let keys = ecs.fetch::<Keybindings>().key_names(Command::Fire);
+        if keys.is_empty() {
+            lines.push("Fire has no key bound".to_string());
+        } else {
+            lines.push(format!("Fired with {}", keys.join(" or ")));
+        }
+    }
+    if ecs.read_storage::<Key>().get(item).is_some() {
+        lines.push("Opens a locked door".to_string());
+    }
+    lines
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum InventoryMenuResult { Cancel, Browsing{ view: InventoryView }, Selected{ item: Entity } }
+
+pub fn show_inventory(gs: &mut State, ctx: &mut dyn Console, view: InventoryView) -> InventoryMenuResult {
+    inventory_menu(gs, ctx, view, "Inventory", true)
+}
+
+pub fn drop_item_menu(gs: &mut State, ctx: &mut dyn Console, view: InventoryView) -> InventoryMenuResult {
+    inventory_menu(gs, ctx, view, "Drop Which Item?", false)
+}
+
+/// The item list, with a description of the highlighted item beside it. Pick with a letter,
+/// or move the highlight with up and down and press ENTER.
+fn inventory_menu(gs: &mut State, ctx: &mut dyn Console, view: InventoryView, title: &str, include_worn: bool) -> InventoryMenuResult {
+    let rows = inventory_rows(&gs.ecs, &view, include_worn);
+    let cursor = usize::min(view.cursor, rows.len().saturating_sub(1));
+    let view = InventoryView{ cursor, ..view };
+
+    let player_entity = *gs.ecs.fetch::<Entity>();
+    let slots_used = gs.ecs.read_storage::<InBackpack>().join().filter(|pack| pack.owner == player_entity).count();
+
+    let white = RGB::named(rltk::WHITE);
+    let yellow = RGB::named(rltk::YELLOW);
+    let black = RGB::named(rltk::BLACK);
+
+    // Worn items get their own header line below the backpack.
+    let worn_header = rows.iter().any(|row| row.worn);
+    let lines = usize::max(1, rows.len() + if worn_header { 1 } else { 0 });
+
+    let mut y = (25 - (lines / 2)) as i32;
+    ctx.draw_box(15, y-3, 36, (lines+4) as i32, white, black);
+    ctx.print_color(18, y-3, yellow, black, title);
+    ctx.print_color(40, y-3, yellow, black, &format!("Pack {}/{}", slots_used, BACKPACK_SLOTS));
+    let filter = view.filter.map_or("All", |category| category.name());
+    ctx.print_color(17, y-2, RGB::named(rltk::CYAN), black, &format!("< {} >  by {}", filter, view.sort.name()));
+    ctx.print_color(18, y+lines as i32+1, yellow, black, "ESCAPE to cancel");
+    ctx.print_color(15, y+lines as i32+2, RGB::named(rltk::GREY), black, "LEFT/RIGHT filter, TAB sort, ENTER use");
+
+    if rows.is_empty() {
+        ctx.print_color(17, y, RGB::named(rltk::GREY), black, "Nothing here.");
+    }
+    let mut shown_worn_header = false;
+    for (j, row) in rows.iter().enumerate() {
+        if row.worn && !shown_worn_header {
+            ctx.print_color(17, y, yellow, black, "Worn");
+            y += 1;
+            shown_worn_header = true;
+        }
+        draw_menu_option(ctx, y, j, &row.label);
+        if j == cursor {
+            ctx.print_color(21, y, black, white, &row.label);
+        }
         y += 1;
-        j += 1;
     }
 
-    match ctx.key {
-        None => (ItemMenuResult::NoResponse, None),
-        Some(key) => {
-            match key {
-                VirtualKeyCode::Escape => { (ItemMenuResult::Cancel, None) },
-                _ => { 
-                    let selection = rltk::letter_to_option(key);
-                    if selection > -1 && selection < count as i32 {
-                        return (ItemMenuResult::Selected, Some(equippable[selection as usize]));
-                    }  
-                    (ItemMenuResult::NoResponse, None)
+    if let Some(row) = rows.get(cursor) {
+        let description = item_description(&gs.ecs, row.item);
+        let top = (25 - (lines / 2)) as i32 - 3;
+        ctx.draw_box(52, top, 27, (description.len()+1) as i32, white, black);
+        ctx.print_color(54, top, yellow, black, "Description");
+        for (line_y, line) in (top+1..).zip(description.iter()) {
+            ctx.print_color(54, line_y, white, black, line);
+        }
+    }
+
+    match ctx.key() {
+        None => InventoryMenuResult::Browsing{ view },
+        Some(key) => match key {
+            VirtualKeyCode::Escape => InventoryMenuResult::Cancel,
+            VirtualKeyCode::Left => InventoryMenuResult::Browsing{ view: view.step_filter(-1) },
+            VirtualKeyCode::Right => InventoryMenuResult::Browsing{ view: view.step_filter(1) },
+            VirtualKeyCode::Tab => InventoryMenuResult::Browsing{ view: InventoryView{ sort: view.sort.next(), ..view } },
+            VirtualKeyCode::Up => InventoryMenuResult::Browsing{ view: InventoryView{ cursor: cursor.saturating_sub(1), ..view } },
+            VirtualKeyCode::Down => InventoryMenuResult::Browsing{ view: InventoryView{ cursor: cursor + 1, ..view } },
+            VirtualKeyCode::Return => match rows.get(cursor) {
+                Some(row) => InventoryMenuResult::Selected{ item: row.item },
+                None => InventoryMenuResult::Browsing{ view },
+            },
+            _ => {
+                let selection = rltk::letter_to_option(key);
+                if selection > -1 && selection < rows.len() as i32 {
+                    return InventoryMenuResult::Selected{ item: rows[selection as usize].item };
                 }
+                InventoryMenuResult::Browsing{ view }
             }
         }
     }
 }
 
-pub fn drop_item_menu(gs: &mut State, ctx: &mut Rltk) -> (ItemMenuResult, Option<Entity>) {
+fn draw_menu_option(ctx: &mut dyn Console, y: i32, index: usize, text: &str) {
+    ctx.set(17, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437('('));
+    ctx.set(18, y, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), 97+index as u16);
+    ctx.set(19, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437(')'));
+
+    ctx.print(21, y, text);
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum LevelUpMenuResult { NoResponse, Selected{ boost: StatBoost } }
+
+/// Pick a stat boost after levelling up. There's no way out but choosing.
+pub fn level_up_menu(gs: &mut State, ctx: &mut dyn Console) -> LevelUpMenuResult {
+    let player_entity = gs.ecs.fetch::<Entity>();
+    let experience = gs.ecs.read_storage::<Experience>();
+    let level = experience.get(*player_entity).map_or(1, |xp| xp.level);
+
+    let options = [
+        (StatBoost::Strength, "Strength (+1 power)"),
+        (StatBoost::Toughness, "Toughness (+1 defense)"),
+        (StatBoost::Vitality, "Vitality (+5 max hp)"),
+    ];
+
+    let y = 23;
+    ctx.draw_box(15, y-2, 31, (options.len()+3) as i32, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
+    ctx.print_color(18, y-2, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), &format!("Level {}! Choose a boost", level));
+    for (j, (_boost, text)) in options.iter().enumerate() {
+        draw_menu_option(ctx, y + j as i32, j, text);
+    }
+
+    match ctx.key() {
+        None => LevelUpMenuResult::NoResponse,
+        Some(key) => {
+            let selection = rltk::letter_to_option(key);
+            if selection > -1 && selection < options.len() as i32 {
+                return LevelUpMenuResult::Selected{ boost: options[selection as usize].0 };
+            }
+            LevelUpMenuResult::NoResponse
+        }
+    }
+}
+
+/// Stats, level and what's worn. Returns true once it's dismissed.
+pub fn character_sheet(gs: &mut State, ctx: &mut dyn Console) -> bool {
     let player_entity = gs.ecs.fetch::<Entity>();
     let names = gs.ecs.read_storage::<Name>();
-    let backpack = gs.ecs.read_storage::<InBackpack>();
+    let combat_stats = gs.ecs.read_storage::<CombatStats>();
+    let experience = gs.ecs.read_storage::<Experience>();
+    let equipped = gs.ecs.read_storage::<Equipped>();
+    let melee_bonuses = gs.ecs.read_storage::<MeleePowerBonus>();
+    let defense_bonuses = gs.ecs.read_storage::<DefenseBonus>();
+    let ranged_weapons = gs.ecs.read_storage::<RangedWeapon>();
     let entities = gs.ecs.entities();
 
-    let inventory = (&backpack, &names).join().filter(|item| item.0.owner == *player_entity );
-    let count = inventory.count();
+    let mut lines: Vec<(RGB, String)> = Vec::new();
+    let white = RGB::named(rltk::WHITE);
+    let cyan = RGB::named(rltk::CYAN);
+
+    if let Some(xp) = experience.get(*player_entity) {
+        lines.push((white, format!("Level: {}", xp.level)));
+        lines.push((white, format!("XP: {} / {}", xp.xp, xp.xp_to_next_level())));
+    }
+
+    let mut power_bonus = 0;
+    let mut defense_bonus = 0;
+    let mut worn: Vec<String> = Vec::new();
+    for (entity, item, name) in (&entities, &equipped, &names).join() {
+        if item.owner != *player_entity {
+            continue;
+        }
+        let mut bonuses: Vec<String> = Vec::new();
+        if let Some(bonus) = melee_bonuses.get(entity) {
+            power_bonus += bonus.power;
+            bonuses.push(format!("{:+} power", bonus.power));
+        }
+        if let Some(bonus) = defense_bonuses.get(entity) {
+            defense_bonus += bonus.defense;
+            bonuses.push(format!("{:+} defense", bonus.defense));
+        }
+        if let Some(weapon) = ranged_weapons.get(entity) {
+            bonuses.push(format!("{} ranged power, range {}", weapon.power, weapon.range));
+        }
+        worn.push(format!("{} ({})", name.name, bonuses.join(", ")));
+    }
 
-    let mut y = (25 - (count / 2)) as i32;
-    ctx.draw_box(15, y-2, 31, (count+3) as i32, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
-    ctx.print_color(18, y-2, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Drop Which Item?");
-    ctx.print_color(18, y+count as i32+1, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "ESCAPE to cancel");
+    if let Some(stats) = combat_stats.get(*player_entity) {
+        lines.push((white, format!("HP: {} / {}", stats.hp, stats.max_hp)));
+        lines.push((white, format!("Power: {} ({:+} from equipment)", stats.power + power_bonus, power_bonus)));
+        lines.push((white, format!("Defense: {} ({:+} from equipment)", stats.defense + defense_bonus, defense_bonus)));
+    }
+    if let Some(wallet) = gs.ecs.read_storage::<Wallet>().get(*player_entity) {
+        lines.push((white, format!("Gold: {}", wallet.gold)));
+    }
 
-    let mut equippable: Vec<Entity> = Vec::new();
-    let mut j = 0;
-    for (entity, _pack, name) in (&entities, &backpack, &names).join().filter(|item| item.1.owner == *player_entity ) {
-        ctx.set(17, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437('('));
-        ctx.set(18, y, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), 97+j as u8);
-        ctx.set(19, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437(')'));
+    if !worn.is_empty() {
+        lines.push((RGB::named(rltk::YELLOW), "Worn".to_string()));
+        for item in worn.into_iter() {
+            lines.push((cyan, item));
+        }
+    }
 
-        ctx.print(21, y, &name.name.to_string());
-        equippable.push(entity);
-        y += 1;
-        j += 1;
+    let width = lines.iter().map(|(_colour, text)| text.len()).max().unwrap_or(0).max(20) as i32 + 4;
+    let y = (25 - (lines.len() / 2)) as i32;
+    ctx.draw_box(15, y-2, width, (lines.len()+3) as i32, white, RGB::named(rltk::BLACK));
+    ctx.print_color(18, y-2, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Character");
+    ctx.print_color(18, y+lines.len() as i32+1, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "ESCAPE to close");
+    for (row, (colour, text)) in (y..).zip(lines.iter()) {
+        ctx.print_color(17, row, *colour, RGB::named(rltk::BLACK), text);
     }
 
-    match ctx.key {
-        None => (ItemMenuResult::NoResponse, None),
-        Some(key) => {
-            match key {
-                VirtualKeyCode::Escape => { (ItemMenuResult::Cancel, None) }
-                _ => {
-                    let selection = rltk::letter_to_option(key);
-                    if selection > -1 && selection < count as i32 {
-                        return (ItemMenuResult::Selected, Some(equippable[selection as usize]));
-                    }  
-                    (ItemMenuResult::NoResponse, None)
+    ctx.key() == Some(VirtualKeyCode::Escape) || ctx.key() == Some(VirtualKeyCode::C)
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum LogHistoryResult { Browsing{ offset: i32, filter: Option<LogCategory> }, Done }
+
+const LOG_HISTORY_FILTERS: [(VirtualKeyCode, Option<LogCategory>); 5] = [
+    (VirtualKeyCode::A, None),
+    (VirtualKeyCode::C, Some(LogCategory::Combat)),
+    (VirtualKeyCode::I, Some(LogCategory::Item)),
+    (VirtualKeyCode::S, Some(LogCategory::System)),
+    (VirtualKeyCode::L, Some(LogCategory::Level)),
+];
+
+/// Every message still in the log, newest at the bottom, with the turn it happened on.
+/// Scroll with the arrow keys and page up/down, and filter by category with the letter keys.
+pub fn log_history(gs: &mut State, ctx: &mut dyn Console, offset: i32, filter: Option<LogCategory>) -> LogHistoryResult {
+    let log = gs.ecs.fetch::<GameLog>();
+    let entries: Vec<_> = log.entries().iter()
+        .filter(|entry| filter.is_none_or(|category| entry.category == category))
+        .collect();
+
+    let rows = VIEW_HEIGHT - 4;
+    let max_offset = i32::max(0, entries.len() as i32 - rows);
+    let offset = offset.clamp(0, max_offset);
+
+    ctx.draw_box(1, 1, VIEW_WIDTH - 3, rows + 1, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
+    let title = match filter {
+        None => "Messages".to_string(),
+        Some(category) => format!("Messages: {}", category.name()),
+    };
+    ctx.print_color(3, 1, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), &title);
+    ctx.print_color(3, rows + 2, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK),
+        "ESCAPE to close, A/C/I/S/L to filter, arrows to scroll");
+
+    let first = i32::max(0, entries.len() as i32 - rows - offset) as usize;
+    let last = (entries.len() as i32 - offset) as usize;
+    for (y, entry) in (2..).zip(entries[first..last].iter()) {
+        ctx.print_color(3, y, RGB::named(rltk::GREY), RGB::named(rltk::BLACK), &format!("{:>5}", entry.turn));
+        ctx.print_color(9, y, entry.category.colour(), RGB::named(rltk::BLACK), &entry.display());
+    }
+
+    match ctx.key() {
+        None => LogHistoryResult::Browsing{ offset, filter },
+        Some(key) => match key {
+            VirtualKeyCode::Escape => LogHistoryResult::Done,
+            VirtualKeyCode::Up => LogHistoryResult::Browsing{ offset: i32::min(max_offset, offset + 1), filter },
+            VirtualKeyCode::Down => LogHistoryResult::Browsing{ offset: i32::max(0, offset - 1), filter },
+            VirtualKeyCode::PageUp => LogHistoryResult::Browsing{ offset: i32::min(max_offset, offset + rows), filter },
+            VirtualKeyCode::PageDown => LogHistoryResult::Browsing{ offset: i32::max(0, offset - rows), filter },
+            _ => match LOG_HISTORY_FILTERS.iter().find(|(filter_key, _category)| *filter_key == key) {
+                // A new filter starts back at the newest messages.
+                Some((_key, category)) => LogHistoryResult::Browsing{ offset: 0, filter: *category },
+                None => LogHistoryResult::Browsing{ offset, filter },
+            }
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum ShopMode { Buy, Sell }
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum ShopMenuResult { Done, Browsing{ mode: ShopMode }, Buy{ item: Entity }, Sell{ item: Entity } }
+
+/// Trade with `vendor`: their stock and its price when buying, what the player has that they'd pay for when selling.
+/// TAB switches between the two.
+pub fn shop_menu(gs: &mut State, ctx: &mut dyn Console, vendor: Entity, mode: ShopMode) -> ShopMenuResult {
+    let player_entity = *gs.ecs.fetch::<Entity>();
+    let owner = if mode == ShopMode::Buy { vendor } else { player_entity };
+    let mut wares: Vec<(Entity, String, i32)> = Vec::new();
+    {
+        let names = gs.ecs.read_storage::<Name>();
+        let backpack = gs.ecs.read_storage::<InBackpack>();
+        let stacks = gs.ecs.read_storage::<Stackable>();
+        let identification = gs.ecs.fetch::<Identification>();
+        for (item, pack, name) in (&gs.ecs.entities(), &backpack, &names).join() {
+            if pack.owner != owner {
+                continue;
+            }
+            let price = match mode {
+                ShopMode::Buy => shop::buy_price(&gs.ecs, item),
+                ShopMode::Sell => shop::sell_price(&gs.ecs, item),
+            };
+            if let Some(price) = price {
+                wares.push((item, item_label(&identification, name, stacks.get(item)), price));
+            }
+        }
+    }
+    wares.sort_by(|a, b| a.1.cmp(&b.1));
+
+    let vendor_name = gs.ecs.read_storage::<Name>().get(vendor).map_or("Vendor".to_string(), |n| n.name.clone());
+    let gold = gs.ecs.read_storage::<Wallet>().get(player_entity).map_or(0, |wallet| wallet.gold);
+
+    let white = RGB::named(rltk::WHITE);
+    let yellow = RGB::named(rltk::YELLOW);
+    let black = RGB::named(rltk::BLACK);
+
+    let lines = usize::max(1, wares.len());
+    let y = (25 - (lines / 2)) as i32;
+    ctx.draw_box(15, y-3, 44, (lines+4) as i32, white, black);
+    let title = match mode {
+        ShopMode::Buy => format!("{} is selling", vendor_name),
+        ShopMode::Sell => format!("{} will buy", vendor_name),
+    };
+    ctx.print_color(18, y-3, yellow, black, &title);
+    ctx.print_color(17, y-2, RGB::named(rltk::GOLD), black, &format!("You have {} gold", gold));
+    ctx.print_color(18, y+lines as i32+1, yellow, black, "TAB buy/sell, ESCAPE to leave");
+
+    if wares.is_empty() {
+        let nothing = if mode == ShopMode::Buy { "Sold out." } else { "You have nothing they want." };
+        ctx.print_color(17, y, RGB::named(rltk::GREY), black, nothing);
+    }
+    for (j, (row, (_item, label, price))) in (y..).zip(wares.iter()).enumerate() {
+        draw_menu_option(ctx, row, j, label);
+        let colour = if mode == ShopMode::Buy && *price > gold { RGB::named(rltk::RED) } else { RGB::named(rltk::GOLD) };
+        let price_text = format!("{} gold", price);
+        ctx.print_color(57 - price_text.len() as i32, row, colour, black, &price_text);
+    }
+
+    match ctx.key() {
+        None => ShopMenuResult::Browsing{ mode },
+        Some(key) => match key {
+            VirtualKeyCode::Escape => ShopMenuResult::Done,
+            VirtualKeyCode::Tab => ShopMenuResult::Browsing{ mode: if mode == ShopMode::Buy { ShopMode::Sell } else { ShopMode::Buy } },
+            _ => {
+                let selection = rltk::letter_to_option(key);
+                if selection > -1 && selection < wares.len() as i32 {
+                    let item = wares[selection as usize].0;
+                    return match mode {
+                        ShopMode::Buy => ShopMenuResult::Buy{ item },
+                        ShopMode::Sell => ShopMenuResult::Sell{ item },
+                    };
                 }
+                ShopMenuResult::Browsing{ mode }
             }
         }
     }
 }
 
-pub fn ranged_target(gs : &mut State, ctx : &mut Rltk, range : i32) -> (ItemMenuResult, Option<Point>) {
+#[derive(PartialEq, Copy, Clone)]
+pub enum ConfirmAttackResult { NoResponse, Attack, Trade, Cancel }
+
+/// Check the player really means to attack someone peaceful. Vendors can be traded with instead.
+pub fn confirm_attack(gs: &mut State, ctx: &mut dyn Console, target: Entity) -> ConfirmAttackResult {
+    let name = gs.ecs.read_storage::<Name>().get(target).map_or("them".to_string(), |n| n.name.clone());
+    let is_vendor = gs.ecs.read_storage::<Vendor>().get(target).is_some();
+
+    let y = 23;
+    let question = format!("Really attack the {}?", name);
+    ctx.draw_box(15, y-2, i32::max(44, question.len() as i32 + 4), 4, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
+    ctx.print_color(17, y, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), &question);
+    let options = if is_vendor { "Y to attack, T to trade, ESCAPE to leave" } else { "Y to attack, ESCAPE to leave" };
+    ctx.print_color(17, y+1, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), options);
+
+    match ctx.key() {
+        None => ConfirmAttackResult::NoResponse,
+        Some(key) => match key {
+            VirtualKeyCode::Y => ConfirmAttackResult::Attack,
+            VirtualKeyCode::T if is_vendor => ConfirmAttackResult::Trade,
+            VirtualKeyCode::Escape | VirtualKeyCode::N => ConfirmAttackResult::Cancel,
+            _ => ConfirmAttackResult::NoResponse,
+        }
+    }
+}
+
+pub fn ranged_target(gs : &mut State, ctx: &mut dyn Console, range : i32) -> (ItemMenuResult, Option<Point>) {
     let player_entity = gs.ecs.fetch::<Entity>();
     let player_pos = gs.ecs.fetch::<Point>();
     let viewsheds = gs.ecs.read_storage::<Viewshed>();
+    let camera = Camera::for_player(&gs.ecs);
 
     ctx.print_color(5, 0, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Select Target:");
     
@@ -206,7 +803,9 @@ pub fn ranged_target(gs : &mut State, ctx : &mut Rltk, range : i32) -> (ItemMenu
         for idx in visible.visible_tiles.iter() {
             let distance = rltk::DistanceAlg::Pythagoras.distance2d(*player_pos, *idx);
             if distance <= range as f32 {
-                ctx.set_bg(idx.x, idx.y, RGB::named(rltk::BLUE));
+                if let Some(screen) = camera.to_screen(*idx) {
+                    ctx.set_bg(screen.x, screen.y, RGB::named(rltk::BLUE));
+                }
                 available_cells.push(idx);
             }
         }
@@ -216,22 +815,27 @@ pub fn ranged_target(gs : &mut State, ctx : &mut Rltk, range : i32) -> (ItemMenu
 
     // Draw cursor to select target
     let mouse_pos = ctx.mouse_pos();
-    let valid_target = available_cells.iter().any(|idx| {
-        idx.x == mouse_pos.0 && idx.y == mouse_pos.1
-    });
-    if valid_target {
+    let target = mouse_tile(&gs.ecs, ctx).filter(|tile| available_cells.contains(&tile));
+    if let Some(target) = target {
+        // Show where the shot would actually go, in case something's in the way.
+        let map = gs.ecs.fetch::<Map>();
+        for tile in line_of_fire(&map, *player_pos, target).iter() {
+            if let Some(screen) = camera.to_screen(*tile) {
+                ctx.set_bg(screen.x, screen.y, RGB::named(rltk::DARK_CYAN));
+            }
+        }
         ctx.set_bg(mouse_pos.0, mouse_pos.1, RGB::named(rltk::CYAN));
-        if ctx.left_click {
-            return (ItemMenuResult::Selected, Some(Point::new(mouse_pos.0, mouse_pos.1)));
+        if ctx.left_click() {
+            return (ItemMenuResult::Selected, Some(target));
         }
     } else {
         ctx.set_bg(mouse_pos.0, mouse_pos.1, RGB::named(rltk::RED));
-        if ctx.left_click {
+        if ctx.left_click() {
             return (ItemMenuResult::Cancel, None);
         }
     }
 
-    match ctx.key {
+    match ctx.key() {
         None => (ItemMenuResult::NoResponse, None),
         Some(key) => match key {
             VirtualKeyCode::Escape => (ItemMenuResult::Cancel, None),
@@ -240,6 +844,21 @@ pub fn ranged_target(gs : &mut State, ctx : &mut Rltk, range : i32) -> (ItemMenu
     }
 }
 
+/// The map tile the player just clicked on to travel to, if it's somewhere they've seen.
+pub fn clicked_map_tile(ecs: &World, ctx: &dyn Console) -> Option<Point> {
+    if !ctx.left_click() {
+        return None;
+    }
+
+    let tile = mouse_tile(ecs, ctx)?;
+    let map = ecs.fetch::<Map>();
+    let player_pos = ecs.fetch::<Point>();
+    if !map.xy_in_map(tile.x, tile.y) || !map.revealed_tiles[map.xy_idx(tile.x, tile.y)] || tile == *player_pos {
+        return None;
+    }
+    Some(tile)
+}
+
 #[derive(PartialEq, Copy, Clone)]
 pub enum MainMenuSelection {
     NewGame,
@@ -247,18 +866,83 @@ pub enum MainMenuSelection {
     Quit,
 }
 
+#[derive(PartialEq, Copy, Clone)]
+pub enum GameOverResult { NoSelection, QuitToMenu }
+
+/// How the run ended, and where the morgue file went.
+pub fn game_over(gs: &mut State, ctx: &mut dyn Console) -> GameOverResult {
+    let entry = morgue::high_score_for_run(&gs.ecs, 0);
+    let white = RGB::named(rltk::WHITE);
+    let black = RGB::named(rltk::BLACK);
+
+    ctx.print_color_centered(15, RGB::named(rltk::RED), black, "Your journey has ended!");
+    ctx.print_color_centered(18, white, black, &format!("{} was killed by {}.", entry.name, entry.cause));
+    ctx.print_color_centered(19, white, black, &format!("You reached depth {}, in {} turns.", entry.depth, entry.turns));
+    run_summary(gs, ctx, &entry)
+}
+
+/// The player has the amulet. The morgue file is written just the same as for a death.
+pub fn victory(gs: &mut State, ctx: &mut dyn Console) -> GameOverResult {
+    let entry = morgue::high_score_for_run(&gs.ecs, 0);
+    let goal = inventory_system::goal_item_carried(&gs.ecs).unwrap_or_else(|| "amulet".to_string());
+    let white = RGB::named(rltk::WHITE);
+    let black = RGB::named(rltk::BLACK);
+
+    ctx.print_color_centered(15, RGB::named(rltk::GREEN), black, "You have won!");
+    ctx.print_color_centered(18, white, black, &format!("{} took the {} from depth {}.", entry.name, goal, entry.depth));
+    ctx.print_color_centered(19, white, black, &format!("It took you {} turns.", entry.turns));
+    run_summary(gs, ctx, &entry)
+}
+
+/// The bottom half of the game over and victory screens: kills, score, and the way back to the menu.
+fn run_summary(gs: &mut State, ctx: &mut dyn Console, entry: &morgue::HighScore) -> GameOverResult {
+    let kills = gs.ecs.fetch::<RunStats>().total_kills();
+    let black = RGB::named(rltk::BLACK);
+
+    ctx.print_color_centered(20, RGB::named(rltk::WHITE), black, &format!("You killed {} monsters.", kills));
+    ctx.print_color_centered(22, RGB::named(rltk::YELLOW), black, &format!("Score: {}", entry.score));
+
+    if let Some(message) = &gs.menu_message {
+        ctx.print_color_centered(25, RGB::named(rltk::GREY), black, message);
+    }
+    ctx.print_color_centered(28, RGB::named(rltk::MAGENTA), black, "Press ENTER to return to the menu.");
+
+    match ctx.key() {
+        Some(VirtualKeyCode::Return) | Some(VirtualKeyCode::Escape) => GameOverResult::QuitToMenu,
+        _ => GameOverResult::NoSelection,
+    }
+}
+
+/// The best runs so far, under the main menu.
+fn draw_high_scores(gs: &State, ctx: &mut dyn Console) {
+    if gs.high_scores.is_empty() {
+        return;
+    }
+
+    ctx.print_color_centered(33, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "High Scores");
+    for (i, entry) in gs.high_scores.iter().enumerate() {
+        let line = format!("{:>2}. {:>6}  {:<12} depth {:>2}, {}", i + 1, entry.score, entry.name, entry.depth, entry.ending());
+        ctx.print_color(10, 35 + i as i32, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), &line);
+    }
+}
+
 #[derive(PartialEq, Copy, Clone)]
 pub enum MainMenuResult { 
     NoSelection{ selected : MainMenuSelection }, 
     Selected{ selected: MainMenuSelection },
 }
 
-pub fn main_menu(gs: &mut State, ctx: &mut Rltk) -> MainMenuResult {
-    let save_exists = super::saveload_system::does_save_exist();
+pub fn main_menu(gs: &mut State, ctx: &mut dyn Console) -> MainMenuResult {
+    let save_exists = saveload_system::does_any_save_exist();
     let runstate = gs.ecs.fetch::<RunState>();
 
     ctx.print_color_centered(15, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Rust Roguelike Tutorial");
 
+    if let Some(message) = &gs.menu_message {
+        ctx.print_color_centered(30, RGB::named(rltk::RED), RGB::named(rltk::BLACK), message);
+    }
+    draw_high_scores(gs, ctx);
+
     if let RunState::MainMenu{ menu_selection: selection } = *runstate {
         if selection == MainMenuSelection::NewGame {
             ctx.print_color_centered(24, RGB::named(rltk::MAGENTA), RGB::named(rltk::BLACK), "Begin New Game");
@@ -281,7 +965,7 @@ pub fn main_menu(gs: &mut State, ctx: &mut Rltk) -> MainMenuResult {
             ctx.print_color_centered(26, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), "Quit");
         }
 
-        match ctx.key {
+        match ctx.key() {
             None => return MainMenuResult::NoSelection{ selected: selection },
             Some(key) => {
                 match key {
@@ -321,3 +1005,68 @@ pub fn main_menu(gs: &mut State, ctx: &mut Rltk) -> MainMenuResult {
 
     MainMenuResult::NoSelection{ selected: MainMenuSelection::NewGame }
 }
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum SaveSlotMenuResult {
+    Cancel,
+    NoResponse,
+    Selected{ slot: &'static str },
+}
+
+pub fn save_game_menu(ctx: &mut dyn Console) -> SaveSlotMenuResult {
+    save_slot_menu(ctx, "Save To Which Slot?", &saveload_system::SAVE_SLOTS, false)
+}
+
+pub fn load_game_menu(ctx: &mut dyn Console) -> SaveSlotMenuResult {
+    let mut slots = vec![saveload_system::AUTOSAVE_SLOT];
+    slots.extend_from_slice(&saveload_system::SAVE_SLOTS);
+    save_slot_menu(ctx, "Load Which Save?", &slots, true)
+}
+
+fn describe_save(metadata: &Result<Option<SaveMetadata>, SaveError>) -> String {
+    match metadata {
+        Ok(None) => "empty".to_string(),
+        Ok(Some(m)) => format!("{}, depth {}, turn {}, {}", m.character_name, m.depth, m.turns, saveload_system::format_timestamp(m.timestamp)),
+        Err(_) => "damaged".to_string(),
+    }
+}
+
+/// Pick a slot by letter. When loading, only slots holding a save can be picked.
+fn save_slot_menu(ctx: &mut dyn Console, title: &str, slots: &[&'static str], must_exist: bool) -> SaveSlotMenuResult {
+    let count = slots.len();
+    let y = (25 - (count / 2)) as i32;
+    ctx.draw_box(10, y-2, 60, (count+3) as i32, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
+    ctx.print_color(13, y-2, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), title);
+    ctx.print_color(13, y+count as i32+1, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "ESCAPE to cancel");
+
+    let mut selectable: Vec<bool> = Vec::new();
+    for (j, (row, slot)) in (y..).zip(slots.iter()).enumerate() {
+        let metadata = saveload_system::read_metadata(slot);
+        let is_selectable = !must_exist || matches!(metadata, Ok(Some(_)));
+
+        ctx.set(12, row, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437('('));
+        ctx.set(13, row, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), 97+j as u16);
+        ctx.set(14, row, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437(')'));
+
+        let fg = if is_selectable { RGB::named(rltk::WHITE) } else { RGB::named(rltk::GREY) };
+        ctx.print_color(16, row, fg, RGB::named(rltk::BLACK), &format!("{}: {}", slot, describe_save(&metadata)));
+
+        selectable.push(is_selectable);
+    }
+
+    match ctx.key() {
+        None => SaveSlotMenuResult::NoResponse,
+        Some(key) => {
+            match key {
+                VirtualKeyCode::Escape => SaveSlotMenuResult::Cancel,
+                _ => {
+                    let selection = rltk::letter_to_option(key);
+                    if selection > -1 && selection < count as i32 && selectable[selection as usize] {
+                        return SaveSlotMenuResult::Selected{ slot: slots[selection as usize] };
+                    }
+                    SaveSlotMenuResult::NoResponse
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use specs::prelude::*;
+use crate::RunState;
+use crate::components::*;
+use crate::gamelog::GameLog;
+
+/// Turns spent in each state before getting hungrier.
+pub const WELL_FED_DURATION: i32 = 20;
+pub const NORMAL_DURATION: i32 = 200;
+pub const HUNGRY_DURATION: i32 = 200;
+
+/// HP lost each turn while starving.
+pub const STARVATION_DAMAGE: i32 = 1;
+
+/// Ticks hunger clocks at the start of each of their owner's turns, and hurts anything that's starving.
+pub struct HungerSystem {}
+
+impl<'a> System<'a> for HungerSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = ( Entities<'a>,
+                        WriteStorage<'a, HungerClock>,
+                        ReadStorage<'a, MyTurn>,
+                        ReadExpect<'a, Entity>,
+                        ReadExpect<'a, RunState>,
+                        WriteStorage<'a, SufferDamage>,
+                        WriteExpect<'a, GameLog>,
+                      );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, mut hunger_clock, turns, player_entity, runstate, mut inflict_damage, mut log) = data;
+
+        if *runstate != RunState::Ticking {
+            return;
+        }
+
+        for (entity, clock, _turn) in (&entities, &mut hunger_clock, &turns).join() {
+            let is_player = entity == *player_entity;
+
+            clock.duration -= 1;
+            if clock.duration > 0 {
+                continue;
+            }
+
+            match clock.state {
+                HungerState::WellFed => {
+                    clock.state = HungerState::Normal;
+                    clock.duration = NORMAL_DURATION;
+                    if is_player {
+                        log.system("You are no longer well fed.".to_string());
+                    }
+                }
+                HungerState::Normal => {
+                    clock.state = HungerState::Hungry;
+                    clock.duration = HUNGRY_DURATION;
+                    if is_player {
+                        log.system("You are hungry.".to_string());
+                    }
+                }
+                HungerState::Hungry => {
+                    clock.state = HungerState::Starving;
+                    clock.duration = 0;
+                    if is_player {
+                        log.system("You are starving!".to_string());
+                    }
+                }
+                HungerState::Starving => {
+                    // Stays starving until it eats, hurting every turn.
+                    clock.duration = 0;
+                    if is_player {
+                        log.system(format!("Your hunger pangs are getting painful! You suffer {} hp damage.", STARVATION_DAMAGE));
+                    }
+                    SufferDamage::new_damage(&mut inflict_damage, entity, STARVATION_DAMAGE, "starvation");
+                }
+            }
+        }
+    }
+}
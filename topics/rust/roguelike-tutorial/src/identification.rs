@@ -0,0 +1,77 @@
+use rltk::RandomNumberGenerator;
+use serde::{Serialize,Deserialize};
+use std::collections::{HashMap,HashSet};
+use crate::components::MagicItemKind;
+use crate::raws::RawMaster;
+
+const POTION_LOOKS: [&str; 12] = [
+    "Bubbling", "Murky", "Fizzy", "Smoky", "Glowing", "Cloudy", "Oily", "Sparkling", "Viscous", "Swirling", "Milky", "Golden",
+];
+
+const SCROLL_SYLLABLES: [&str; 16] = [
+    "ka", "zu", "mor", "ith", "el", "ven", "dra", "ul", "ox", "pha", "lo", "gri", "nak", "tes", "yu", "bri",
+];
+
+/// What scrolls and potions are called before they've been used. The names are shuffled for each run,
+/// so a "Murky Potion" heals in one game and hastes in the next. Lives in the ECS as a resource, and is saved.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Identification {
+    /// Made up names, by real name.
+    aliases: HashMap<String, String>,
+    /// Real names the player has learned.
+    known: HashSet<String>,
+}
+
+impl Identification {
+    /// Make up a name for every magic item in the raws.
+    pub fn new(raws: &RawMaster, rng: &mut RandomNumberGenerator) -> Identification {
+        let mut aliases = HashMap::new();
+        let mut used: HashSet<String> = HashSet::new();
+        for (name, kind) in raws.magic_items().into_iter() {
+            let mut alias = made_up_name(kind, rng);
+            while used.contains(&alias) {
+                alias = made_up_name(kind, rng);
+            }
+            used.insert(alias.clone());
+            aliases.insert(name, alias);
+        }
+        Identification{ aliases, known: HashSet::new() }
+    }
+
+    /// What the player calls the item whose real name is `name`.
+    pub fn name(&self, name: &str) -> String {
+        match self.aliases.get(name) {
+            Some(alias) if !self.known.contains(name) => alias.clone(),
+            _ => name.to_string(),
+        }
+    }
+
+    pub fn is_known(&self, name: &str) -> bool {
+        !self.aliases.contains_key(name) || self.known.contains(name)
+    }
+
+    /// Learn what `name` really is. Returns true if it wasn't known already.
+    pub fn identify(&mut self, name: &str) -> bool {
+        !self.is_known(name) && self.known.insert(name.to_string())
+    }
+}
+
+fn made_up_name(kind: MagicItemKind, rng: &mut RandomNumberGenerator) -> String {
+    match kind {
+        MagicItemKind::Potion => {
+            let look = POTION_LOOKS[rng.range(0, POTION_LOOKS.len() as i32) as usize];
+            format!("{} Potion", look)
+        }
+        MagicItemKind::Scroll => {
+            let mut words = Vec::new();
+            for _word in 0..2 {
+                let mut word = String::new();
+                for _syllable in 0..rng.roll_dice(1, 2) {
+                    word += SCROLL_SYLLABLES[rng.range(0, SCROLL_SYLLABLES.len() as i32) as usize];
+                }
+                words.push(word.to_uppercase());
+            }
+            format!("Scroll of {}", words.join(" "))
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use specs::prelude::*;
+use crate::RunState;
+use crate::components::*;
+
+/// Energy spent taking a turn. Something at normal speed gets a turn every ten ticks.
+pub const TURN_COST: i32 = 1000;
+
+/// Speed of the player and of any monster that doesn't say otherwise.
+pub const NORMAL_SPEED: i32 = 100;
+
+/// Advances the clock while `RunState::Ticking`: last tick's turns are over, everything on the level
+/// gains energy each tick, and whoever has enough gets a `MyTurn`. Ticks where nobody would act are skipped.
+pub struct InitiativeSystem {}
+
+impl<'a> System<'a> for InitiativeSystem {
+    type SystemData = ( ReadExpect<'a, RunState>,
+                        Entities<'a>,
+                        WriteStorage<'a, Initiative>,
+                        WriteStorage<'a, MyTurn>,
+                        ReadStorage<'a, Position>,
+                        ReadStorage<'a, StatusEffects>,
+                      );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (runstate, entities, mut initiatives, mut turns, positions, statuses) = data;
+
+        if *runstate != RunState::Ticking {
+            return;
+        }
+
+        turns.clear();
+
+        // Only what's on the current level: anything frozen on another level has no position.
+        let speed_of = |entity: Entity, initiative: &Initiative| {
+            let speed = statuses.get(entity).map_or(initiative.speed, |status| status.adjust_speed(initiative.speed));
+            i32::max(1, speed)
+        };
+
+        let ticks = (&entities, &initiatives, &positions).join()
+            .map(|(entity, initiative, _pos)| {
+                let speed = speed_of(entity, initiative);
+                i32::max(1, (TURN_COST - initiative.energy + speed - 1) / speed)
+            })
+            .min()
+            .unwrap_or(1);
+
+        for (entity, initiative, _pos) in (&entities, &mut initiatives, &positions).join() {
+            initiative.energy += speed_of(entity, initiative) * ticks;
+            if initiative.energy >= TURN_COST {
+                initiative.energy -= TURN_COST;
+                turns.insert(entity, MyTurn{}).expect("Unable to insert turn");
+            }
+        }
+    }
+}
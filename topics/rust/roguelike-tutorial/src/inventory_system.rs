@@ -2,27 +2,113 @@ use specs::prelude::*;
 use crate::gamelog::GameLog;
 use crate::components::*;
 use crate::map::Map;
+use crate::identification::Identification;
+use crate::morgue::RunStats;
+use crate::hunger_system;
+use crate::ranged_combat_system::line_of_fire;
+use rltk::Point;
+
+/// How many different things fit in a backpack. A stack only takes up one slot, however big it is.
+pub const BACKPACK_SLOTS: usize = 26;
+
+/// Take one off the top of a stack, or use up the whole item if it isn't one (or that was the last of it).
+pub fn use_up_one(entities: &Entities, stacks: &mut WriteStorage<Stackable>, item: Entity) {
+    if let Some(stack) = stacks.get_mut(item) {
+        if stack.count > 1 {
+            stack.count -= 1;
+            return;
+        }
+    }
+    entities.delete(item).expect("Delete failed!");
+}
+
+/// Put `item` in `owner`'s backpack, merging it into a stack of the same thing if there is one.
+/// Returns false, and leaves it be, if it needs a slot of its own and there isn't one free.
+pub fn stow_item(entities: &Entities, backpack: &mut WriteStorage<InBackpack>, names: &ReadStorage<Name>,
+                 stacks: &mut WriteStorage<Stackable>, owner: Entity, item: Entity) -> bool {
+    let name = &names.get(item).unwrap().name;
+    let stack = match stacks.get(item) {
+        None => None,
+        Some(_) => (entities, &*backpack, names, &*stacks).join()
+            .find(|(stack, pack, stack_name, _count)| *stack != item && pack.owner == owner && stack_name.name == *name)
+            .map(|(stack, _pack, _name, _count)| stack),
+    };
+    match stack {
+        Some(stack) => {
+            let count = stacks.get(item).unwrap().count;
+            stacks.get_mut(stack).unwrap().count += count;
+            entities.delete(item).expect("Delete failed!");
+        }
+        None => {
+            let carried = (&*backpack).join().filter(|pack| pack.owner == owner).count();
+            if carried >= BACKPACK_SLOTS {
+                return false;
+            }
+            backpack.insert(item, InBackpack{ owner }).expect("Unable to insert backpack entry");
+        }
+    }
+    true
+}
+
+/// The name of the `GoalItem` in the player's pack, if they've got it.
+pub fn goal_item_carried(ecs: &World) -> Option<String> {
+    let player_entity = ecs.fetch::<Entity>();
+    let goals = ecs.read_storage::<GoalItem>();
+    let backpack = ecs.read_storage::<InBackpack>();
+    let names = ecs.read_storage::<Name>();
+    (&goals, &backpack, &names).join()
+        .find(|(_goal, pack, _name)| pack.owner == *player_entity)
+        .map(|(_goal, _pack, name)| name.name.clone())
+}
+
+/// Has the player got hold of what they came for?
+pub fn player_has_won(ecs: &World) -> bool {
+    goal_item_carried(ecs).is_some()
+}
 
 pub struct ItemCollectionSystem {}
 
 impl<'a> System<'a> for ItemCollectionSystem {
+    #[allow(clippy::type_complexity)]
     type SystemData = ( ReadExpect<'a, Entity>,
                         WriteExpect<'a, GameLog>,
+                        ReadExpect<'a, Identification>,
+                        Entities<'a>,
                         WriteStorage<'a, WantsToPickupItem>,
                         WriteStorage<'a, Position>,
                         ReadStorage<'a, Name>,
                         WriteStorage<'a, InBackpack>,
+                        WriteStorage<'a, Stackable>,
+                        ReadStorage<'a, Gold>,
+                        WriteStorage<'a, Wallet>,
                       );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (player_entity, mut gamelog, mut wants_pickup, mut positions, names, mut backpack) = data;
+        let (player_entity, mut gamelog, identification, entities, mut wants_pickup, mut positions, names, mut backpack,
+            mut stacks, gold, mut wallets) = data;
 
         for pickup in wants_pickup.join() {
+            // Gold goes straight in the wallet, if there's one to put it in.
+            if let (Some(pile), Some(wallet)) = (gold.get(pickup.item), wallets.get_mut(pickup.collected_by)) {
+                wallet.gold += pile.amount;
+                entities.delete(pickup.item).expect("Delete failed!");
+                if pickup.collected_by == *player_entity {
+                    gamelog.item(format!("You pick up {} gold.", pile.amount));
+                }
+                continue;
+            }
+
+            if !stow_item(&entities, &mut backpack, &names, &mut stacks, pickup.collected_by, pickup.item) {
+                if pickup.collected_by == *player_entity {
+                    gamelog.item("Your pack is full.".to_string());
+                }
+                continue;
+            }
             positions.remove(pickup.item);
-            backpack.insert(pickup.item, InBackpack{ owner: pickup.collected_by }).expect("Unable to insert backpack entry");
 
             if pickup.collected_by == *player_entity {
-                gamelog.info(format!("You pick up the {}.", names.get(pickup.item).unwrap().name));
+                let name = &names.get(pickup.item).unwrap().name;
+                gamelog.item(format!("You pick up the {}.", identification.name(name)));
             }
         }
         wants_pickup.clear();
@@ -35,6 +121,7 @@ impl<'a> System<'a> for ItemUseSystem {
     #[allow(clippy::type_complexity)]
     type SystemData = ( ReadExpect<'a, Entity>,
                         WriteExpect<'a, GameLog>,
+                        WriteExpect<'a, Identification>,
                         Entities<'a>,
                         WriteStorage<'a, WantsToUseItem>,
                         ReadStorage<'a, Name>,
@@ -45,13 +132,24 @@ impl<'a> System<'a> for ItemUseSystem {
                         WriteStorage<'a, SufferDamage>,
                         ReadExpect<'a, Map>,
                         ReadStorage<'a, AreaOfEffect>,
-                        WriteStorage<'a, Confusion>,
+                        ReadStorage<'a, InflictsStatus>,
+                        WriteStorage<'a, StatusEffects>,
+                        ReadStorage<'a, ProvidesFood>,
+                        WriteStorage<'a, HungerClock>,
+                        WriteStorage<'a, LastAttacker>,
+                        ReadStorage<'a, Equippable>,
+                        WriteStorage<'a, Equipped>,
+                        WriteStorage<'a, InBackpack>,
+                        ReadStorage<'a, Position>,
+                        WriteStorage<'a, Stackable>,
+                        WriteExpect<'a, RunStats>,
                       );
 
     fn run(&mut self, data: Self::SystemData) {
         let (
             player_entity, 
             mut gamelog, 
+            mut identification,
             entities, 
             mut wants_use, 
             names, 
@@ -62,35 +160,127 @@ impl<'a> System<'a> for ItemUseSystem {
             mut suffer_damage, 
             map, 
             aoe,
-            mut confused
+            inflicts_status,
+            mut statuses,
+            provides_food,
+            mut hunger_clocks,
+            mut last_attackers,
+            equippable,
+            mut equipped,
+            mut backpack,
+            positions,
+            mut stacks,
+            mut run_stats
         ) = data;
 
         for (entity, useitem) in (&entities, &wants_use).join() {
             let mut used_item = false;
 
+            // Drinking or reading something is how you find out what it is.
+            if entity == *player_entity {
+                let name = &names.get(useitem.item).unwrap().name;
+                let alias = identification.name(name);
+                if identification.identify(name) {
+                    gamelog.item(format!("The {} was a {}!", alias, name));
+                }
+            }
+
             let mut targets: Vec<Entity> = Vec::new();
             match useitem.target {
                 None => { targets.push(*player_entity); }
                 Some(target) => {
-                    let area_effect = aoe.get(useitem.item);
-                    match area_effect {
-                        None => {
-                            // Single tile
-                            let idx = map.xy_idx(target.x, target.y);
-                            for mob in map.tile_content[idx].iter() {
-                                targets.push(*mob);
-                            }
-                        }
-                        Some(area_effect) => {
-                            // AoE
-                            let mut blast_tiles = rltk::field_of_view(target, area_effect.radius, &*map);
-                            blast_tiles.retain(|p| map.point_in_map(p));
-                            for tile_idx in blast_tiles.iter() {
-                                let idx = map.xy_idx(tile_idx.x, tile_idx.y);
+                    // It goes as far as the line of fire allows: into the first thing in the way, or up to a wall.
+                    let impact = match positions.get(entity) {
+                        None => Some(target),
+                        Some(pos) => line_of_fire(&map, Point::new(pos.x, pos.y), target).last().copied(),
+                    };
+                    if let Some(target) = impact {
+                        let area_effect = aoe.get(useitem.item);
+                        match area_effect {
+                            None => {
+                                // Single tile
+                                let idx = map.xy_idx(target.x, target.y);
                                 for mob in map.tile_content[idx].iter() {
                                     targets.push(*mob);
                                 }
                             }
+                            Some(area_effect) => {
+                                // AoE
+                                let mut blast_tiles = rltk::field_of_view(target, area_effect.radius, &*map);
+                                blast_tiles.retain(|p| map.point_in_map(p));
+                                for tile_idx in blast_tiles.iter() {
+                                    let idx = map.xy_idx(tile_idx.x, tile_idx.y);
+                                    for mob in map.tile_content[idx].iter() {
+                                        targets.push(*mob);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            // Wear the item, or take it off if it is already worn. One item per slot.
+            let item_equippable = equippable.get(useitem.item);
+            match item_equippable {
+                None => {}
+                Some(can_equip) => {
+                    let target_slot = can_equip.slot;
+                    let already_worn = match equipped.get(useitem.item) {
+                        None => false,
+                        Some(worn) => worn.owner == entity,
+                    };
+
+                    if already_worn {
+                        let carried = (&backpack).join().filter(|pack| pack.owner == entity).count();
+                        if carried >= BACKPACK_SLOTS {
+                            // Swapping for something in the pack frees a slot, but just taking it off needs one.
+                            if entity == *player_entity {
+                                gamelog.item(format!("There's no room in your pack for {}.", names.get(useitem.item).unwrap().name));
+                            }
+                        } else {
+                            equipped.remove(useitem.item);
+                            backpack.insert(useitem.item, InBackpack{ owner: entity }).expect("Unable to insert backpack entry");
+                            if entity == *player_entity {
+                                gamelog.item(format!("You unequip {}.", names.get(useitem.item).unwrap().name));
+                            }
+                        }
+                    } else {
+                        let mut to_unequip: Vec<Entity> = Vec::new();
+                        for (item_entity, worn, name) in (&entities, &equipped, &names).join() {
+                            if worn.owner == entity && worn.slot == target_slot {
+                                to_unequip.push(item_entity);
+                                if entity == *player_entity {
+                                    gamelog.item(format!("You unequip {}.", name.name));
+                                }
+                            }
+                        }
+                        for item in to_unequip.iter() {
+                            equipped.remove(*item);
+                            backpack.insert(*item, InBackpack{ owner: entity }).expect("Unable to insert backpack entry");
+                        }
+
+                        equipped.insert(useitem.item, Equipped{ owner: entity, slot: target_slot }).expect("Unable to insert equipped component");
+                        backpack.remove(useitem.item);
+                        if entity == *player_entity {
+                            gamelog.item(format!("You equip {}.", names.get(useitem.item).unwrap().name));
+                        }
+                    }
+                }
+            }
+
+            let item_edible = provides_food.get(useitem.item);
+            match item_edible {
+                None => {}
+                Some(_) => {
+                    for target in targets.iter() {
+                        if let Some(clock) = hunger_clocks.get_mut(*target) {
+                            clock.state = HungerState::WellFed;
+                            clock.duration = hunger_system::WELL_FED_DURATION;
+                            if *target == *player_entity {
+                                gamelog.item(format!("You eat the {}.", names.get(useitem.item).unwrap().name));
+                            }
+                            used_item = true;
                         }
                     }
                 }
@@ -107,7 +297,7 @@ impl<'a> System<'a> for ItemUseSystem {
 
                             if entity == *player_entity {
                                 let name = &names.get(useitem.item).unwrap().name;
-                                gamelog.info(format!("You drink to {}, healing {} hp", name, healer.heal_amount))
+                                gamelog.item(format!("You drink to {}, healing {} hp", name, healer.heal_amount))
                             }
                             
                             used_item = true;
@@ -121,12 +311,13 @@ impl<'a> System<'a> for ItemUseSystem {
                 None => {},
                 Some(damage) => {
                     for mob in targets.iter() {
-                        SufferDamage::new_damage(&mut suffer_damage, *mob, damage.damage);
+                        SufferDamage::new_damage(&mut suffer_damage, *mob, damage.damage, &names.get(useitem.item).unwrap().name);
+                        last_attackers.insert(*mob, LastAttacker{ attacker: entity }).expect("Unable to insert attacker");
 
                         if entity == *player_entity {
                             let mob_name = names.get(*mob).unwrap();
                             let item_name = names.get(useitem.item).unwrap();
-                            gamelog.info(format!("You use {} on {}, inflicting {} hp.", item_name.name, mob_name.name, damage.damage));
+                            gamelog.item(format!("You use {} on {}, inflicting {} hp.", item_name.name, mob_name.name, damage.damage));
                         }
 
                         used_item = true;
@@ -134,34 +325,37 @@ impl<'a> System<'a> for ItemUseSystem {
                 }
             }
 
-            let mut add_confusion = Vec::new();
-            {
-                let causes_confusion = confused.get(useitem.item);
-                match causes_confusion {
-                    None => {}
-                    Some(confusion) => {
-                        for mob in targets.iter() {
-                            add_confusion.push((*mob, confusion.turns));
-                            if entity == *player_entity {
-                                let mob_name = names.get(*mob).unwrap();
-                                let item_name = names.get(useitem.item).unwrap();
-                                gamelog.info(format!("You use {} on {}, confusing them.", item_name.name, mob_name.name));
-                            }
-                            used_item = true;
+            let item_inflicts_status = inflicts_status.get(useitem.item);
+            match item_inflicts_status {
+                None => {}
+                Some(inflicts) => {
+                    for mob in targets.iter() {
+                        StatusEffects::add(&mut statuses, *mob, inflicts.effect);
+                        if *mob != entity {
+                            // Whoever poisoned it gets the credit if it dies of it.
+                            last_attackers.insert(*mob, LastAttacker{ attacker: entity }).expect("Unable to insert attacker");
+                        }
+                        if *mob == *player_entity {
+                            gamelog.item(format!("You are {}!", inflicts.effect.kind.name()));
+                        } else if entity == *player_entity {
+                            let mob_name = names.get(*mob).unwrap();
+                            let item_name = names.get(useitem.item).unwrap();
+                            gamelog.item(format!("You use {} on {}, leaving them {}.", item_name.name, mob_name.name, inflicts.effect.kind.name()));
                         }
+                        used_item = true;
                     }
                 }
             }
-            for mob in add_confusion.iter() {
-                confused.insert(mob.0, Confusion{ turns: mob.1 }).expect("Unable to insert status");
-            }
 
             if used_item {
                 let consumable = consumables.get(useitem.item);
                 match consumable {
                     None => {},
                     Some(_) => {
-                        entities.delete(useitem.item).expect("Delete failed!");
+                        if entity == *player_entity {
+                            run_stats.record_item_used(&names.get(useitem.item).unwrap().name);
+                        }
+                        use_up_one(&entities, &mut stacks, useitem.item);
                     }
                 }
             }
@@ -177,6 +371,7 @@ impl<'a> System<'a> for ItemDropSystem {
     #[allow(clippy::type_complexity)]
     type SystemData = ( ReadExpect<'a, Entity>,
                         WriteExpect<'a, GameLog>,
+                        ReadExpect<'a, Identification>,
                         Entities<'a>,
                         WriteStorage<'a, WantsToDropItem>,
                         ReadStorage<'a, Name>,
@@ -184,7 +379,7 @@ impl<'a> System<'a> for ItemDropSystem {
                         WriteStorage<'a, InBackpack>
                       );
     fn run(&mut self, data: Self::SystemData) {
-        let (player_entity, mut gamelog, entities, mut wants_drop, names, mut positions, mut backpack) = data;
+        let (player_entity, mut gamelog, identification, entities, mut wants_drop, names, mut positions, mut backpack) = data;
 
         for (entity, to_drop) in (&entities, &wants_drop).join() {
             let mut dropper_pos: Position = Position{x: 0, y: 0};
@@ -198,7 +393,7 @@ impl<'a> System<'a> for ItemDropSystem {
             backpack.remove(to_drop.item);
 
             if entity == *player_entity {
-                gamelog.info(format!("You drop the {}.", names.get(to_drop.item).unwrap().name));
+                gamelog.item(format!("You drop the {}.", identification.name(&names.get(to_drop.item).unwrap().name)));
             }
         }
 
@@ -0,0 +1,181 @@
+use rltk::VirtualKeyCode;
+use serde::{Serialize,Deserialize};
+use std::collections::{BTreeMap,HashMap};
+use std::fs;
+use std::path::Path;
+
+pub const KEYBINDINGS_FILE_NAME: &str = "./keybindings.json";
+
+/// Everything a key can be bound to.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Serialize, Deserialize, Debug)]
+pub enum Command {
+    MoveNorth,
+    MoveSouth,
+    MoveEast,
+    MoveWest,
+    MoveNorthEast,
+    MoveNorthWest,
+    MoveSouthEast,
+    MoveSouthWest,
+    Wait,
+    AutoExplore,
+    Search,
+    PickUp,
+    Descend,
+    Ascend,
+    Fire,
+    Inventory,
+    Drop,
+    CharacterSheet,
+    Messages,
+    SaveGame,
+    ToggleDebug,
+    Quit,
+}
+
+impl Command {
+    /// Which way a move command goes.
+    pub fn direction(self) -> Option<(i32, i32)> {
+        match self {
+            Command::MoveNorth => Some((0, -1)),
+            Command::MoveSouth => Some((0, 1)),
+            Command::MoveEast => Some((1, 0)),
+            Command::MoveWest => Some((-1, 0)),
+            Command::MoveNorthEast => Some((1, -1)),
+            Command::MoveNorthWest => Some((-1, -1)),
+            Command::MoveSouthEast => Some((1, 1)),
+            Command::MoveSouthWest => Some((-1, 1)),
+            _ => None,
+        }
+    }
+}
+
+/// Arrows, the numpad and vi-keys all move; everything else has one key.
+fn default_bindings() -> BTreeMap<Command, Vec<VirtualKeyCode>> {
+    use VirtualKeyCode::*;
+    let defaults = vec![
+        (Command::MoveNorth, vec![Up, Numpad8, K]),
+        (Command::MoveSouth, vec![Down, Numpad2, J]),
+        (Command::MoveEast, vec![Right, Numpad6, L]),
+        (Command::MoveWest, vec![Left, Numpad4, H]),
+        (Command::MoveNorthEast, vec![Numpad9, U]),
+        (Command::MoveNorthWest, vec![Numpad7, Y]),
+        (Command::MoveSouthEast, vec![Numpad3, N]),
+        (Command::MoveSouthWest, vec![Numpad1, B]),
+        (Command::Wait, vec![Space, Numpad5]),
+        (Command::AutoExplore, vec![X]),
+        (Command::Search, vec![S]),
+        (Command::PickUp, vec![G]),
+        (Command::Descend, vec![Period]),
+        (Command::Ascend, vec![Comma]),
+        (Command::Fire, vec![F]),
+        (Command::Inventory, vec![I]),
+        (Command::Drop, vec![D]),
+        (Command::CharacterSheet, vec![C]),
+        (Command::Messages, vec![M]),
+        (Command::SaveGame, vec![Escape]),
+        (Command::ToggleDebug, vec![Grave]),
+        (Command::Quit, vec![Q]),
+    ];
+    defaults.into_iter().collect()
+}
+
+/// Which key does what. Lives in the ECS as a resource.
+pub struct Keybindings {
+    keys: HashMap<VirtualKeyCode, Command>,
+}
+
+impl Keybindings {
+    pub fn defaults() -> Keybindings {
+        Keybindings::build(default_bindings()).0
+    }
+
+    /// The defaults, with any commands listed in the file at `path` rebound. No file just means the defaults.
+    /// Also returns a description of each key that was bound to more than one command.
+    pub fn load(path: &str) -> Result<(Keybindings, Vec<String>), String> {
+        if !Path::new(path).exists() {
+            return Ok((Keybindings::defaults(), Vec::new()));
+        }
+        let data = fs::read_to_string(path).map_err(|e| format!("unable to read {}: {}", path, e))?;
+        Keybindings::from_json(&data).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Parse a JSON object of command names to lists of key names, eg: `{ "Wait": ["Space", "Period"] }`.
+    pub fn from_json(data: &str) -> Result<(Keybindings, Vec<String>), String> {
+        let rebound: BTreeMap<Command, Vec<String>> = serde_json::from_str(data).map_err(|e| e.to_string())?;
+
+        let mut bindings = default_bindings();
+        for (command, names) in rebound.into_iter() {
+            let mut keys = Vec::new();
+            for name in names.iter() {
+                let key = key_from_name(name).ok_or_else(|| format!("{:?} is bound to unknown key {}", command, name))?;
+                keys.push(key);
+            }
+            bindings.insert(command, keys);
+        }
+
+        Ok(Keybindings::build(bindings))
+    }
+
+    /// When a key is bound twice, the command that comes first in `Command` gets it.
+    fn build(bindings: BTreeMap<Command, Vec<VirtualKeyCode>>) -> (Keybindings, Vec<String>) {
+        let mut keys: HashMap<VirtualKeyCode, Command> = HashMap::new();
+        let mut conflicts = Vec::new();
+        for (command, bound) in bindings.iter() {
+            for key in bound.iter() {
+                match keys.get(key) {
+                    Some(existing) if existing != command => {
+                        conflicts.push(format!("{:?} is bound to both {:?} and {:?}, using it for {:?}", key, existing, command, existing));
+                    }
+                    _ => { keys.insert(*key, *command); }
+                }
+            }
+        }
+
+        (Keybindings{ keys }, conflicts)
+    }
+
+    pub fn command(&self, key: VirtualKeyCode) -> Option<Command> {
+        self.keys.get(&key).copied()
+    }
+
+    /// The names of the keys bound to `command`, eg: `["Space", "Numpad5"]`. Empty if it has been left unbound.
+    pub fn key_names(&self, command: Command) -> Vec<&'static str> {
+        let mut names: Vec<&'static str> = self.keys.iter()
+            .filter(|(_key, bound)| **bound == command)
+            .filter_map(|(key, _bound)| key_name(*key))
+            .collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+macro_rules! key_names {
+    ($( $key:ident ),*) => {
+        /// The key with this `VirtualKeyCode` name, eg: `"Numpad8"`.
+        fn key_from_name(name: &str) -> Option<VirtualKeyCode> {
+            match name {
+                $( stringify!($key) => Some(VirtualKeyCode::$key), )*
+                _ => None,
+            }
+        }
+
+        /// The name `key_from_name` knows `key` by.
+        fn key_name(key: VirtualKeyCode) -> Option<&'static str> {
+            match key {
+                $( VirtualKeyCode::$key => Some(stringify!($key)), )*
+                _ => None,
+            }
+        }
+    };
+}
+
+key_names!(
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    Up, Down, Left, Right, Home, End, PageUp, PageDown, Insert, Delete,
+    Escape, Space, Return, Tab, Back,
+    Period, Comma, Grave, Slash, Backslash, Semicolon, Apostrophe, Minus, Equals, LBracket, RBracket,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12
+);
@@ -0,0 +1,757 @@
+extern crate serde;
+
+pub mod map;
+pub mod camera;
+pub mod console;
+pub mod map_builders;
+pub mod dungeon;
+pub mod constants;
+pub mod components;
+pub mod player;
+pub mod rect;
+pub mod visibility_system;
+pub mod lighting_system;
+pub mod chase_map;
+pub mod monster_ai_system;
+pub mod initiative_system;
+pub mod status_effect_system;
+pub mod hunger_system;
+pub mod map_indexing_system;
+pub mod melee_combat_system;
+pub mod ranged_combat_system;
+pub mod special_attack_system;
+pub mod trigger_system;
+pub mod damage_system;
+pub mod gui;
+pub mod gamelog;
+pub mod keybindings;
+pub mod spawner;
+pub mod inventory_system;
+pub mod saveload_system;
+pub mod random_table;
+pub mod raws;
+pub mod identification;
+pub mod replay;
+pub mod travel;
+pub mod shop;
+pub mod morgue;
+pub mod simulation;
+
+use map::*;
+use camera::Camera;
+use console::Console;
+use constants::{MAP_WIDTH,MAP_HEIGHT};
+use components::*;
+use player::*;
+use visibility_system::VisibilitySystem;
+use lighting_system::{LightingSystem,can_make_out};
+use chase_map::ChaseMap;
+use monster_ai_system::MonsterAI;
+use initiative_system::InitiativeSystem;
+use status_effect_system::{StatusEffectSystem,TurnPhase};
+use hunger_system::HungerSystem;
+use map_indexing_system::MapIndexingSystem;
+use melee_combat_system::MeleeCombatSystem;
+use ranged_combat_system::RangedCombatSystem;
+use special_attack_system::SpecialAttackSystem;
+use trigger_system::TriggerSystem;
+use damage_system::{DamageSystem,delete_the_dead,player_is_dead};
+use gamelog::{GameLog,LogCategory};
+use dungeon::MasterDungeonMap;
+use inventory_system::*;
+use replay::{PlayerAction,ReplayRecorder};
+use morgue::{RunStats,HighScore};
+
+use rltk::{Rltk,GameState,RGB,Point};
+use specs::prelude::*;
+use specs::saveload::{SimpleMarkerAllocator};
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum RunState {
+    MainMenu {
+        menu_selection: gui::MainMenuSelection
+    },
+    SaveGame,
+    LoadGame,
+    AwaitingInput,
+    PreRun,
+    /// Carry out the action the player just chose.
+    PlayerTurn,
+    /// The clock runs, and monsters take their turns, until the player has initiative again.
+    Ticking,
+    /// Shots are in the air. The window animates them before they land.
+    Firing,
+    ShowInventory {
+        view: gui::InventoryView,
+    },
+    ShowDropItem {
+        view: gui::InventoryView,
+    },
+    ShowTargeting {
+        range: i32,
+        item: Entity,
+    },
+    /// Walked into someone who isn't hostile: attack them, trade with them, or leave them be.
+    ConfirmAttack {
+        target: Entity,
+    },
+    /// Buying from, or selling to, `vendor`.
+    Shop {
+        vendor: Entity,
+        mode: gui::ShopMode,
+    },
+    ShowFireTargeting {
+        range: i32,
+        ammo: Entity,
+    },
+    NextLevel,
+    PreviousLevel,
+    LevelUp,
+    CharacterSheet,
+    /// The full message history. `offset` is how many entries up from the newest it's scrolled,
+    /// `filter` the only category shown, if any.
+    LogHistory {
+        offset: i32,
+        filter: Option<LogCategory>,
+    },
+    /// The player has died. The morgue file is written; this shows how the run went.
+    GameOver,
+    /// The player has the amulet. Goes through the morgue file and high scores just like `GameOver`.
+    Victory,
+}
+
+/// Turns the player has taken this run.
+pub struct TurnCount(pub i32);
+
+pub struct State {
+    pub ecs: World,
+    pub debug_mode: bool,
+    /// Shown on the main menu, eg: why a save couldn't be loaded.
+    pub menu_message: Option<String>,
+    /// How long shots have been in the air, in ms.
+    pub firing_time: f32,
+    /// Auto-explore or click-to-travel in progress, taken a step at a time.
+    pub travel: Option<travel::Travel>,
+    /// The best finished runs, best first, for the main menu.
+    pub high_scores: Vec<HighScore>,
+}
+impl GameState for State {
+    fn tick(&mut self, ctx: &mut Rltk) {
+        let mut newrunstate;
+        {
+            let runstate = self.ecs.fetch::<RunState>();
+            newrunstate = *runstate;
+        }
+
+        ctx.cls();
+
+        let mut changed_level = false;
+        match newrunstate {
+            RunState::MainMenu{ .. } => {
+                self.travel = None;
+                let result = gui::main_menu(self, ctx);
+                match result {
+                    gui::MainMenuResult::NoSelection{ selected } => newrunstate = RunState::MainMenu{ menu_selection: selected },
+                    gui::MainMenuResult::Selected{ selected } => {
+                        self.menu_message = None;
+                        match selected {
+                            gui::MainMenuSelection::NewGame => newrunstate = RunState::PreRun,
+                            gui::MainMenuSelection::LoadGame => newrunstate = RunState::LoadGame,
+                            gui::MainMenuSelection::Quit => ctx.quit(),
+                        }
+                    }
+                }
+            }
+            RunState::LoadGame => {
+                let result = gui::load_game_menu(ctx);
+                match result {
+                    gui::SaveSlotMenuResult::NoResponse => {}
+                    gui::SaveSlotMenuResult::Cancel => newrunstate = RunState::MainMenu{ menu_selection: gui::MainMenuSelection::LoadGame },
+                    gui::SaveSlotMenuResult::Selected{ slot } => {
+                        match self.load_game(slot) {
+                            Ok(()) => newrunstate = RunState::AwaitingInput,
+                            Err(e) => {
+                                self.menu_message = Some(format!("Unable to load {}: {}", slot, e));
+                                newrunstate = RunState::MainMenu{ menu_selection: gui::MainMenuSelection::LoadGame };
+                            }
+                        }
+                    }
+                }
+            }
+            RunState::SaveGame => {
+                let result = gui::save_game_menu(ctx);
+                match result {
+                    gui::SaveSlotMenuResult::NoResponse => {}
+                    gui::SaveSlotMenuResult::Cancel => newrunstate = RunState::AwaitingInput,
+                    gui::SaveSlotMenuResult::Selected{ slot } => {
+                        match saveload_system::save_game(&mut self.ecs, slot) {
+                            Ok(()) => newrunstate = RunState::MainMenu{ menu_selection: gui::MainMenuSelection::LoadGame },
+                            Err(e) => {
+                                self.ecs.fetch_mut::<GameLog>().system(format!("Unable to save to {}: {}", slot, e));
+                                newrunstate = RunState::AwaitingInput;
+                            }
+                        }
+                    }
+                }
+            }
+            RunState::NextLevel | RunState::PreviousLevel => {
+                newrunstate = self.step(newrunstate);
+                changed_level = true;
+            }
+            RunState::GameOver | RunState::Victory => {
+                let result = if newrunstate == RunState::Victory { gui::victory(self, ctx) } else { gui::game_over(self, ctx) };
+                match result {
+                    gui::GameOverResult::NoSelection => {}
+                    gui::GameOverResult::QuitToMenu => {
+                        self.start_new_run();
+                        return;
+                    }
+                }
+            }
+            _ => {
+                self.render(ctx);
+
+                if self.debug_mode {
+                    ctx.print_color(
+                        1, 1, 
+                        RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), 
+                        format!("{:.2}fps", ctx.fps)
+                    );
+                }
+            }
+        }
+
+        match newrunstate {
+            RunState::PreRun | RunState::PlayerTurn | RunState::Ticking => {
+                newrunstate = self.step(newrunstate);
+            }
+            RunState::Firing => {
+                // Let the shots fly across the screen before working out what they hit.
+                self.firing_time += ctx.frame_time_ms;
+                let frame = (self.firing_time / gui::PROJECTILE_FRAME_MS) as usize;
+                if !gui::draw_projectiles(&self.ecs, ctx, frame) {
+                    self.firing_time = 0.0;
+                    newrunstate = self.step(newrunstate);
+                }
+            }
+            RunState::AwaitingInput => {
+                newrunstate = match self.travel.take() {
+                    Some(plan) => travel::continue_travel(self, ctx, plan),
+                    None => player_input(self, ctx),
+                };
+            }
+            RunState::ShowInventory{ view } => {
+                match gui::show_inventory(self, ctx, view) {
+                    gui::InventoryMenuResult::Cancel => newrunstate = RunState::AwaitingInput,
+                    gui::InventoryMenuResult::Browsing{ view } => newrunstate = RunState::ShowInventory{ view },
+                    gui::InventoryMenuResult::Selected{ item: item_entity } => {
+                        let item_range = self.ecs.read_storage::<Ranged>().get(item_entity).map(|ranged| ranged.range);
+                        let is_ammo = self.ecs.read_storage::<Ammo>().get(item_entity).is_some();
+
+                        if is_ammo {
+                            newrunstate = start_firing(&mut self.ecs, Some(item_entity));
+                        } else if let Some(range) = item_range {
+                            newrunstate = RunState::ShowTargeting{
+                                range,
+                                item: item_entity,
+                            };
+                        } else {
+                            newrunstate = perform_action(&mut self.ecs, PlayerAction::UseItem{ item: item_entity.id(), target: None });
+                        }
+                    }
+                }
+            }
+            RunState::LevelUp => {
+                let result = gui::level_up_menu(self, ctx);
+                match result {
+                    gui::LevelUpMenuResult::NoResponse => {}
+                    gui::LevelUpMenuResult::Selected{ boost } => {
+                        newrunstate = perform_action(&mut self.ecs, PlayerAction::LevelUp{ boost });
+                    }
+                }
+            }
+            RunState::CharacterSheet => {
+                if gui::character_sheet(self, ctx) {
+                    newrunstate = RunState::AwaitingInput;
+                }
+            }
+            RunState::LogHistory{ offset, filter } => {
+                match gui::log_history(self, ctx, offset, filter) {
+                    gui::LogHistoryResult::Browsing{ offset, filter } => newrunstate = RunState::LogHistory{ offset, filter },
+                    gui::LogHistoryResult::Done => newrunstate = RunState::AwaitingInput,
+                }
+            }
+            RunState::ShowDropItem{ view } => {
+                match gui::drop_item_menu(self, ctx, view) {
+                    gui::InventoryMenuResult::Cancel => newrunstate = RunState::AwaitingInput,
+                    gui::InventoryMenuResult::Browsing{ view } => newrunstate = RunState::ShowDropItem{ view },
+                    gui::InventoryMenuResult::Selected{ item: item_entity } => {
+                        newrunstate = perform_action(&mut self.ecs, PlayerAction::DropItem{ item: item_entity.id() });
+                    }
+                }
+            }
+            RunState::ConfirmAttack{ target } => {
+                match gui::confirm_attack(self, ctx, target) {
+                    gui::ConfirmAttackResult::NoResponse => {}
+                    gui::ConfirmAttackResult::Cancel => newrunstate = RunState::AwaitingInput,
+                    gui::ConfirmAttackResult::Trade => newrunstate = RunState::Shop{ vendor: target, mode: gui::ShopMode::Buy },
+                    gui::ConfirmAttackResult::Attack => {
+                        newrunstate = perform_action(&mut self.ecs, PlayerAction::Attack{ target: target.id() });
+                    }
+                }
+            }
+            RunState::Shop{ vendor, mode } => {
+                match gui::shop_menu(self, ctx, vendor, mode) {
+                    gui::ShopMenuResult::Done => newrunstate = RunState::AwaitingInput,
+                    gui::ShopMenuResult::Browsing{ mode } => newrunstate = RunState::Shop{ vendor, mode },
+                    gui::ShopMenuResult::Buy{ item } => {
+                        newrunstate = perform_action(&mut self.ecs, PlayerAction::Buy{ vendor: vendor.id(), item: item.id() });
+                    }
+                    gui::ShopMenuResult::Sell{ item } => {
+                        newrunstate = perform_action(&mut self.ecs, PlayerAction::Sell{ vendor: vendor.id(), item: item.id() });
+                    }
+                }
+            }
+            RunState::ShowTargeting{range, item} => {
+                let result = gui::ranged_target(self, ctx, range);
+                match result.0 {
+                    gui::ItemMenuResult::Cancel => newrunstate = RunState::AwaitingInput,
+                    gui::ItemMenuResult::NoResponse => {}
+                    gui::ItemMenuResult::Selected => {
+                        newrunstate = perform_action(&mut self.ecs, PlayerAction::UseItem{ item: item.id(), target: result.1 });
+                    }
+                }
+            }
+            RunState::ShowFireTargeting{range, ammo} => {
+                let result = gui::ranged_target(self, ctx, range);
+                match result.0 {
+                    gui::ItemMenuResult::Cancel => newrunstate = RunState::AwaitingInput,
+                    gui::ItemMenuResult::NoResponse => {}
+                    gui::ItemMenuResult::Selected => {
+                        let target = result.1.unwrap();
+                        newrunstate = perform_action(&mut self.ecs, PlayerAction::Fire{ ammo: ammo.id(), target });
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        {
+            let mut runwriter = self.ecs.write_resource::<RunState>();
+            *runwriter = newrunstate;
+        }
+        delete_the_dead(&mut self.ecs);
+
+        let run_over = newrunstate == RunState::GameOver || newrunstate == RunState::Victory;
+        if !run_over && (player_is_dead(&self.ecs) || player_has_won(&self.ecs)) {
+            self.end_run();
+        }
+
+        if changed_level {
+            if let Err(e) = saveload_system::save_game(&mut self.ecs, saveload_system::AUTOSAVE_SLOT) {
+                self.ecs.fetch_mut::<GameLog>().system(format!("Autosave failed: {}", e));
+            }
+        }
+    }
+}
+
+impl State {
+    /// An empty world with every component registered and the run-wide resources in place, but no level yet.
+    fn new_world(seed: u64, raws: raws::RawMaster, recorder: ReplayRecorder) -> State {
+        let mut gs = State {
+            ecs: World::new(),
+            debug_mode: false,
+            menu_message: None,
+            firing_time: 0.0,
+            travel: None,
+            high_scores: morgue::load_high_scores(),
+        };
+
+        register_components(&mut gs.ecs);
+        gs.ecs.insert(raws);
+        gs.ecs.insert(rltk::RandomNumberGenerator::seeded(seed));
+        gs.ecs.insert(SimpleMarkerAllocator::<SerializeMe>::new());
+        gs.ecs.insert(recorder);
+        gs.ecs.insert(TurnCount(0));
+        gs.ecs.insert(RunStats::default());
+        gs.ecs.insert(MasterDungeonMap::new());
+        gs.ecs.insert(ChaseMap::new());
+        gs.ecs.insert(GameLog::new(&["Welcome to Rusty Roguelike".to_string()]));
+        let identification = {
+            let raws = gs.ecs.fetch::<raws::RawMaster>();
+            let mut rng = gs.ecs.write_resource::<rltk::RandomNumberGenerator>();
+            identification::Identification::new(&raws, &mut rng)
+        };
+        gs.ecs.insert(identification);
+        gs.ecs.insert(keybindings::Keybindings::defaults());
+        gs.ecs.insert(RunState::MainMenu{ menu_selection: gui::MainMenuSelection::NewGame });
+
+        gs
+    }
+
+    /// Put the player into the world at (x, y).
+    fn place_player(&mut self, player_x: i32, player_y: i32) {
+        let player_entity = spawner::player(&mut self.ecs, player_x, player_y);
+        self.ecs.insert(Point::new(player_x, player_y));
+        self.ecs.insert(player_entity);
+    }
+
+    /// A fresh run: a new first level and player, with every random roll coming from `seed`.
+    pub fn new_game(seed: u64, raws: raws::RawMaster, recorder: ReplayRecorder) -> State {
+        let mut gs = State::new_world(seed, raws, recorder);
+
+        gs.ecs.insert(Map::new(1, MAP_WIDTH, MAP_HEIGHT));
+        let start = gs.generate_world_map(1);
+        gs.place_player(start.x, start.y);
+
+        gs
+    }
+
+    /// The player has died, or won: write the morgue file, put the run on the high score table,
+    /// and show how it went.
+    fn end_run(&mut self) {
+        let won = player_has_won(&self.ecs);
+        match morgue::record_run(&self.ecs) {
+            Ok((path, high_scores)) => {
+                self.menu_message = Some(format!("Your story was written to {}", path));
+                self.high_scores = high_scores;
+            }
+            Err(e) => self.menu_message = Some(format!("Unable to write the morgue file: {}", e)),
+        }
+        self.travel = None;
+
+        let mut runwriter = self.ecs.write_resource::<RunState>();
+        *runwriter = if won { RunState::Victory } else { RunState::GameOver };
+    }
+
+    /// Replace the game in progress with the one saved in `slot`.
+    pub fn load_game(&mut self, slot: &str) -> Result<(), saveload_system::SaveError> {
+        saveload_system::load_game(&mut self.ecs, slot)?;
+        // A loaded game can't be reproduced from the seed, so stop recording.
+        *self.ecs.write_resource::<ReplayRecorder>() = ReplayRecorder::disabled();
+        // Light isn't saved, so work it out before drawing anything.
+        let mut lighting = LightingSystem{};
+        lighting.run_now(&self.ecs);
+        // Nor is the way to the player, which is for the old level.
+        *self.ecs.write_resource::<ChaseMap>() = ChaseMap::new();
+        self.travel = None;
+        Ok(())
+    }
+
+    /// Back to the main menu with a whole new game waiting, on a new seed, after the last one ended.
+    fn start_new_run(&mut self) {
+        let seed = rltk::RandomNumberGenerator::new().next_u64();
+        let raws = self.ecs.remove::<raws::RawMaster>().expect("No raws to start a new run with");
+        let keybindings = self.ecs.remove::<keybindings::Keybindings>();
+        let menu_message = self.menu_message.take();
+
+        *self = State::new_game(seed, raws, ReplayRecorder::create(replay::REPLAY_FILE_NAME, seed));
+        if let Some(keybindings) = keybindings {
+            self.ecs.insert(keybindings);
+        }
+        self.menu_message = menu_message;
+        // The binary prints the first run's seed, later ones go in their own log so they can still be replayed.
+        self.ecs.fetch_mut::<GameLog>().system(format!("Seed: {}", seed));
+    }
+
+    /// Advance the run-state machine through the states that don't need a window or the player.
+    /// Anything else is returned unchanged.
+    fn step(&mut self, runstate: RunState) -> RunState {
+        {
+            // Systems (eg: MonsterAI) check whose turn it is.
+            let mut runwriter = self.ecs.write_resource::<RunState>();
+            *runwriter = runstate;
+        }
+
+        match runstate {
+            RunState::PreRun => {
+                self.run_systems();
+                self.ecs.maintain();
+                self.after_systems()
+            }
+            RunState::PlayerTurn => {
+                self.ecs.write_resource::<TurnCount>().0 += 1;
+                let turns = self.ecs.fetch::<TurnCount>().0;
+                self.ecs.fetch_mut::<GameLog>().set_turn(turns);
+                self.run_systems();
+                self.ecs.maintain();
+
+                // That was the player's turn spent.
+                let player_entity = *self.ecs.fetch::<Entity>();
+                self.ecs.write_storage::<MyTurn>().remove(player_entity);
+                self.after_systems()
+            }
+            RunState::Ticking => {
+                self.run_systems();
+                self.ecs.maintain();
+                self.after_systems()
+            }
+            RunState::Firing => {
+                let mut ranged = RangedCombatSystem{};
+                ranged.run_now(&self.ecs);
+
+                let mut damage_system = DamageSystem{};
+                damage_system.run_now(&self.ecs);
+
+                self.ecs.maintain();
+                self.after_systems()
+            }
+            RunState::NextLevel => {
+                self.goto_level(1);
+                RunState::PreRun
+            }
+            RunState::PreviousLevel => {
+                self.goto_level(-1);
+                RunState::PreRun
+            }
+            _ => runstate,
+        }
+    }
+
+    /// Draw the level, everything on it the player can see, and the UI around it.
+    pub fn render(&self, ctx: &mut dyn Console) {
+        draw_map(&self.ecs, ctx, self.debug_mode);
+
+        let positions = self.ecs.read_storage::<Position>();
+        let renderables = self.ecs.read_storage::<Renderable>();
+        let hidden = self.ecs.read_storage::<Hidden>();
+        let map = self.ecs.fetch::<Map>();
+        let player_pos = *self.ecs.fetch::<Point>();
+        let camera = Camera::for_player(&self.ecs);
+
+        let mut data = (&positions, &renderables, !&hidden).join().map(|(pos, render, _hidden)| (pos, render)).collect::<Vec<_>>();
+        data.sort_by_key(|&(_, render)| std::cmp::Reverse(render.render_order));
+        for (pos, render) in data.iter() {
+            let idx = map.xy_idx(pos.x, pos.y);
+            let screen = match camera.to_screen(Point::new(pos.x, pos.y)) {
+                None => continue,
+                Some(screen) => screen,
+            };
+
+            // Only render what the player can see, and make out in the light.
+            if map.visible_tiles[idx] && can_make_out(&map, player_pos, Point::new(pos.x, pos.y)) {
+                ctx.set(screen.x, screen.y, render.fg, render.bg, render.glyph);
+            } else if self.debug_mode {
+                ctx.set(screen.x, screen.y, render.fg.desaturate(), render.bg, render.glyph);
+            }
+        }
+
+        gui::draw_ui(&self.ecs, ctx);
+    }
+
+    /// Play back a recorded run without a window, driving the same run-state machine as `tick`.
+    pub fn run_replay(&mut self, actions: &[PlayerAction]) {
+        self.watch_replay(actions, |_state| {});
+    }
+
+    /// `run_replay`, calling `watch` once at the start and again after every action, to show the run as it goes.
+    pub fn watch_replay<F: FnMut(&State)>(&mut self, actions: &[PlayerAction], mut watch: F) {
+        let mut runstate = self.settle(RunState::PreRun);
+        watch(self);
+        for action in actions.iter() {
+            runstate = perform_action(&mut self.ecs, *action);
+            runstate = self.settle(runstate);
+            watch(self);
+        }
+
+        let mut runwriter = self.ecs.write_resource::<RunState>();
+        *runwriter = runstate;
+    }
+
+    /// Step until the player has to make a decision again.
+    fn settle(&mut self, mut runstate: RunState) -> RunState {
+        loop {
+            let next = self.step(runstate);
+            delete_the_dead(&mut self.ecs);
+            if player_is_dead(&self.ecs) {
+                return RunState::GameOver;
+            }
+            if player_has_won(&self.ecs) {
+                return RunState::Victory;
+            }
+            if next == runstate && next != RunState::Ticking {
+                return next;
+            }
+            runstate = next;
+        }
+    }
+
+    /// Once the systems have run: land any shots first, then over to the player if it's their turn,
+    /// otherwise keep the clock going.
+    fn after_systems(&mut self) -> RunState {
+        if (&self.ecs.read_storage::<WantsToShoot>()).join().next().is_some() {
+            return RunState::Firing;
+        }
+
+        let player_entity = *self.ecs.fetch::<Entity>();
+        if self.ecs.read_storage::<MyTurn>().get(player_entity).is_some() {
+            begin_player_turn(&mut self.ecs)
+        } else {
+            RunState::Ticking
+        }
+    }
+
+    fn run_systems(&mut self) {
+        let mut initiative = InitiativeSystem{};
+        initiative.run_now(&self.ecs);
+
+        let mut status_effects = StatusEffectSystem{ phase: TurnPhase::Start };
+        status_effects.run_now(&self.ecs);
+
+        let mut hunger = HungerSystem{};
+        hunger.run_now(&self.ecs);
+
+        let mut lighting = LightingSystem{};
+        lighting.run_now(&self.ecs);
+
+        let mut vis = VisibilitySystem{};
+        vis.run_now(&self.ecs);
+
+        let mut monster_ai = MonsterAI{};
+        monster_ai.run_now(&self.ecs);
+
+        let mut map_indexing = MapIndexingSystem{};
+        map_indexing.run_now(&self.ecs);
+
+        let mut triggers = TriggerSystem{};
+        triggers.run_now(&self.ecs);
+
+        let mut melee_combat_system = MeleeCombatSystem{};
+        melee_combat_system.run_now(&self.ecs);
+
+        let mut special_attacks = SpecialAttackSystem{};
+        special_attacks.run_now(&self.ecs);
+
+        let mut damage_system = DamageSystem{};
+        damage_system.run_now(&self.ecs);
+
+        let mut item_system = ItemCollectionSystem{};
+        item_system.run_now(&self.ecs);
+
+        let mut item_use_system = ItemUseSystem{};
+        item_use_system.run_now(&self.ecs);
+
+        let mut drop_items = ItemDropSystem{};
+        drop_items.run_now(&self.ecs);
+
+        let mut status_effects_end = StatusEffectSystem{ phase: TurnPhase::End };
+        status_effects_end.run_now(&self.ecs);
+
+        self.ecs.maintain();
+    }
+
+    /// Build a level for `new_depth` with a random builder, install it as the current map and populate it.
+    /// Returns where the player should start.
+    fn generate_world_map(&mut self, new_depth: i32) -> Position {
+        self.ecs.write_resource::<RunStats>().reached_depth(new_depth);
+
+        let mut builder;
+        {
+            let mut rng = self.ecs.write_resource::<rltk::RandomNumberGenerator>();
+            builder = map_builders::random_builder(new_depth, MAP_WIDTH, MAP_HEIGHT, &mut rng);
+            builder.build_map(&mut rng);
+        }
+
+        let vault;
+        let shop;
+        let prefab_spawns;
+        let braziers;
+        {
+            let mut worldmap_resource = self.ecs.write_resource::<Map>();
+            *worldmap_resource = builder.get_map();
+            let start = builder.get_starting_position();
+            if new_depth > 1 {
+                // A way back up, right where the player arrives.
+                worldmap_resource.set_tile(start.x, start.y, TileType::UpStairs);
+            }
+
+            let mut rng = self.ecs.write_resource::<rltk::RandomNumberGenerator>();
+            vault = map_builders::add_vault(&mut worldmap_resource, &mut rng, &start);
+            map_builders::add_doors(&mut worldmap_resource, &mut rng);
+            shop = map_builders::pick_shop_room(&worldmap_resource, &mut rng, &start, vault.as_ref());
+            prefab_spawns = map_builders::add_prefab_vault(&mut worldmap_resource, &mut rng, &start, vault.as_ref(), shop.as_ref());
+            braziers = map_builders::pick_brazier_spots(&worldmap_resource, &mut rng);
+        }
+
+        if let Some(vault) = vault {
+            spawner::spawn_vault(&mut self.ecs, &vault, new_depth);
+        }
+        if let Some(shop) = shop {
+            spawner::spawn_shop(&mut self.ecs, &shop, new_depth);
+        }
+        spawner::spawn_braziers(&mut self.ecs, &braziers);
+        let width = self.ecs.fetch::<Map>().width as usize;
+        for (idx, name) in builder.get_spawn_list().iter().chain(prefab_spawns.iter()) {
+            raws::spawn_named_entity(&mut self.ecs, name, (*idx % width) as i32, (*idx / width) as i32);
+        }
+        for region in builder.get_spawn_regions().iter() {
+            // A prefab may have put walls up where there was floor.
+            let open: Vec<usize> = {
+                let map = self.ecs.fetch::<Map>();
+                region.iter().filter(|idx| !map.tiles[**idx].blocks_movement()).copied().collect()
+            };
+            spawner::spawn_region(&mut self.ecs, &open, new_depth);
+        }
+
+        builder.get_starting_position()
+    }
+
+    /// Move the player `offset` levels down (or up, if negative). The level they leave is stored as it is,
+    /// and levels they've already seen come back exactly as they left them.
+    fn goto_level(&mut self, offset: i32) {
+        dungeon::freeze_level_entities(&mut self.ecs);
+
+        let new_depth;
+        {
+            let worldmap_resource = self.ecs.fetch::<Map>();
+            self.ecs.fetch_mut::<MasterDungeonMap>().store_map(&worldmap_resource);
+            new_depth = worldmap_resource.depth + offset;
+        }
+
+        let stored_map = self.ecs.fetch::<MasterDungeonMap>().get_map(new_depth);
+        let first_visit = stored_map.is_none();
+        let start = match stored_map {
+            None => self.generate_world_map(new_depth),
+            Some(map) => {
+                // Arrive on the stairs that lead back where we came from.
+                let arrival_tile = if offset > 0 { TileType::UpStairs } else { TileType::DownStairs };
+                let arrival = map.find_tile(arrival_tile).expect("Stored level has no stairs to arrive on");
+                *self.ecs.write_resource::<Map>() = map;
+                dungeon::thaw_level_entities(&mut self.ecs);
+                Position{ x: arrival.x, y: arrival.y }
+            }
+        };
+
+        // Move the player to a position on the new map
+        let mut player_position = self.ecs.write_resource::<Point>();
+        *player_position = Point::new(start.x, start.y);
+        let mut position_components = self.ecs.write_storage::<Position>();
+        let player_entity = self.ecs.fetch::<Entity>();
+        let player_pos_comp = position_components.get_mut(*player_entity);
+        if let Some(player_pos_comp) = player_pos_comp {
+            player_pos_comp.x = start.x;
+            player_pos_comp.y = start.y;
+        }
+
+        // Mark player viewshed as dirty
+        let mut viewshed_components = self.ecs.write_storage::<Viewshed>();
+        let vs = viewshed_components.get_mut(*player_entity);
+        if let Some(vs) = vs {
+            vs.dirty = true;
+        }
+
+        let mut gamelog = self.ecs.fetch_mut::<gamelog::GameLog>();
+        if !first_visit {
+            gamelog.level(format!("You return to depth {}.", new_depth));
+            return;
+        }
+
+        // Notify the player and give them some health
+        gamelog.level("You descend to the next level, and take a moment to heal.".to_string());
+        let mut player_health_store = self.ecs.write_storage::<CombatStats>();
+        let player_health = player_health_store.get_mut(*player_entity);
+        if let Some(player_health) = player_health {
+            player_health.hp = i32::max(player_health.hp, player_health.max_hp / 2);
+        }
+    }
+}
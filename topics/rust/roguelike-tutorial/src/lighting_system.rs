@@ -0,0 +1,57 @@
+use specs::prelude::*;
+use rltk::{field_of_view,Point,DistanceAlg};
+use crate::components::{Position,LightSource};
+use crate::map::Map;
+
+/// Tiles lit less than this are too dark to make anything out on, unless it's right next to you.
+pub const LIT_THRESHOLD: f32 = 0.2;
+/// How close something in the dark has to be to be noticed anyway.
+pub const DARK_SIGHT_RANGE: f32 = 1.5;
+
+/// Works out how brightly lit each tile is from the light sources on the level. Only does the work when
+/// a light has moved, or come or gone, or the level itself has changed (eg: a door opening).
+pub struct LightingSystem {}
+
+impl<'a> System<'a> for LightingSystem {
+    type SystemData = ( WriteExpect<'a, Map>,
+                        ReadStorage<'a, Position>,
+                        ReadStorage<'a, LightSource> );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut map, positions, lights) = data;
+
+        let sources: Vec<(i32, i32, i32)> = (&positions, &lights).join()
+            .map(|(pos, light)| (pos.x, pos.y, light.range))
+            .collect();
+        if map.lit_by.as_ref() == Some(&sources) && map.light.len() == map.tiles.len() {
+            return;
+        }
+
+        let mut light = vec![0.0; map.tiles.len()];
+        for (x, y, range) in sources.iter() {
+            let origin = Point::new(*x, *y);
+            for tile in field_of_view(origin, *range, &*map).iter() {
+                if !map.contains(tile.x, tile.y) {
+                    continue;
+                }
+                let distance = DistanceAlg::Pythagoras.distance2d(origin, *tile);
+                let brightness = f32::max(0.0, 1.0 - distance / (*range as f32 + 1.0));
+                let idx = map.xy_idx(tile.x, tile.y);
+                // Overlapping lights add up.
+                light[idx] = f32::min(1.0, light[idx] + brightness);
+            }
+        }
+
+        map.light = light;
+        map.lit_by = Some(sources);
+    }
+}
+
+/// Can someone at `viewer` make out what's at `target`, assuming it's in their line of sight?
+/// Only if it's lit, or close enough to see in the dark.
+pub fn can_make_out(map: &Map, viewer: Point, target: Point) -> bool {
+    if DistanceAlg::Pythagoras.distance2d(viewer, target) <= DARK_SIGHT_RANGE {
+        return true;
+    }
+    map.contains(target.x, target.y) && map.light_at(map.xy_idx(target.x, target.y)) >= LIT_THRESHOLD
+}
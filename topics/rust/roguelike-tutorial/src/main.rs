@@ -1,359 +1,115 @@
-extern crate serde;
-
-mod map;
-mod constants;
-mod components;
-mod player;
-mod rect;
-mod visibility_system;
-mod monster_ai_system;
-mod map_indexing_system;
-mod melee_combat_system;
-mod damage_system;
-mod gui;
-mod gamelog;
-mod spawner;
-mod inventory_system;
-mod saveload_system;
-mod random_table;
-
-use map::*;
-use components::*;
-use player::*;
-use visibility_system::VisibilitySystem;
-use monster_ai_system::MonsterAI;
-use map_indexing_system::MapIndexingSystem;
-use melee_combat_system::MeleeCombatSystem;
-use damage_system::{DamageSystem,delete_the_dead};
-use gamelog::GameLog;
-use inventory_system::*;
-
-use rltk::{Rltk,GameState,RGB,Point};
-use specs::prelude::*;
-use specs::saveload::{SimpleMarkerAllocator};
-
-#[derive(PartialEq, Copy, Clone)]
-pub enum RunState {
-    MainMenu {
-        menu_selection: gui::MainMenuSelection
-    },
-    SaveGame,
-    AwaitingInput,
-    PreRun,
-    PlayerTurn,
-    MonsterTurn,
-    ShowInventory,
-    ShowDropItem,
-    ShowTargeting {
-        range: i32,
-        item: Entity,
-    },
-    NextLevel,
-}
-
-pub struct State {
-    pub ecs: World,
-    pub debug_mode: bool,
+use roguelike_tutorial::{State,raws,replay,keybindings};
+use roguelike_tutorial::console::{Console,TextConsole};
+use roguelike_tutorial::replay::ReplayRecorder;
+
+struct Options {
+    seed: Option<u64>,
+    replay: Option<String>,
+    /// Draw each turn of the replay to the terminal.
+    watch: bool,
 }
-impl GameState for State {
-    fn tick(&mut self, ctx: &mut Rltk) {
-        let mut newrunstate;
-        {
-            let runstate = self.ecs.fetch::<RunState>();
-            newrunstate = *runstate;
-        }
-
-        ctx.cls();
 
-        match newrunstate {
-            RunState::MainMenu{ .. } => {
-                let result = gui::main_menu(self, ctx);
-                match result {
-                    gui::MainMenuResult::NoSelection{ selected } => newrunstate = RunState::MainMenu{ menu_selection: selected },
-                    gui::MainMenuResult::Selected{ selected } => {
-                        match selected {
-                            gui::MainMenuSelection::NewGame => newrunstate = RunState::PreRun,
-                            gui::MainMenuSelection::LoadGame => {
-                                saveload_system::load_game(&mut self.ecs);
-                                newrunstate = RunState::AwaitingInput;
-                                saveload_system::delete_save();
-                            }
-                            gui::MainMenuSelection::Quit => ctx.quit(),
-                        }
-                    }
+/// How long each turn stays on screen when watching a replay.
+const WATCH_FRAME_MS: u64 = 100;
+
+fn parse_args() -> Options {
+    let mut options = Options{ seed: None, replay: None, watch: false };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--seed" => {
+                let seed = args.next().and_then(|s| s.parse::<u64>().ok());
+                if seed.is_none() {
+                    eprintln!("--seed needs a number, eg: --seed 1234");
+                    std::process::exit(1);
                 }
+                options.seed = seed;
             }
-            RunState::SaveGame => {
-                saveload_system::save_game(&mut self.ecs);
-
-                newrunstate = RunState::MainMenu{ menu_selection: gui::MainMenuSelection::LoadGame };
-            }
-            RunState::NextLevel => {
-                self.goto_next_level();
-                newrunstate = RunState::PreRun;
-            }
-            _ => {
-                draw_map(&self.ecs, ctx, self.debug_mode);
-
-                let positions = self.ecs.read_storage::<Position>();
-                let renderables = self.ecs.read_storage::<Renderable>();
-                let map = self.ecs.fetch::<Map>();
-
-                let mut data = (&positions, &renderables).join().collect::<Vec<_>>();
-                data.sort_by(|&a, &b| b.1.render_order.cmp(&a.1.render_order));
-                for (pos, render) in data.iter() {
-                    let idx = map.xy_idx(pos.x, pos.y);
-
-                    // Only render visible renderables.
-                    if map.visible_tiles[idx] {
-                        ctx.set(pos.x, pos.y, render.fg, render.bg, render.glyph);
-                    } else if self.debug_mode {
-                        ctx.set(pos.x, pos.y, render.fg.desaturate(), render.bg, render.glyph);
-                    }
+            "--replay" => {
+                options.replay = args.next();
+                if options.replay.is_none() {
+                    eprintln!("--replay needs a file, eg: --replay {}", replay::REPLAY_FILE_NAME);
+                    std::process::exit(1);
                 }
-
-                if self.debug_mode {
-                    ctx.print_color(
-                        1, 1, 
-                        RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), 
-                        format!("{:.2}fps", ctx.fps)
-                    );
-                }
-
-                gui::draw_ui(&self.ecs, ctx);
             }
-        }
-
-        match newrunstate {
-            RunState::PreRun => {
-                self.run_systems();
-                self.ecs.maintain();
-                newrunstate = RunState::AwaitingInput;
-            }
-            RunState::AwaitingInput => {
-                newrunstate = player_input(self, ctx);
-            }
-            RunState::PlayerTurn => {
-                self.run_systems();
-                self.ecs.maintain();
-                newrunstate = RunState::MonsterTurn;
-            }
-            RunState::MonsterTurn => {
-                self.run_systems();
-                self.ecs.maintain();
-                newrunstate = RunState::AwaitingInput;
-            }
-            RunState::ShowInventory => {
-                let result = gui::show_inventory(self, ctx);
-                match result.0 {
-                    gui::ItemMenuResult::Cancel => newrunstate = RunState::AwaitingInput,
-                    gui::ItemMenuResult::NoResponse => {},
-                    gui::ItemMenuResult::Selected => {
-                        let item_entity = result.1.unwrap();
-                        let is_ranged = self.ecs.read_storage::<Ranged>();
-                        let is_item_ranged = is_ranged.get(item_entity);
-
-                        if let Some(ranged_item) = is_item_ranged {
-                            newrunstate = RunState::ShowTargeting{
-                                range: ranged_item.range,
-                                item: item_entity,
-                            };
-                        } else {
-                            let mut intent = self.ecs.write_storage::<WantsToUseItem>();
-                            intent.insert(*self.ecs.fetch::<Entity>(), WantsToUseItem{ item: item_entity, target: None }).expect("Unable to insert intent");
-    
-                            newrunstate = RunState::PlayerTurn;
-                        }
-                    }
-                }
-            }
-            RunState::ShowDropItem => {
-                let result = gui::drop_item_menu(self, ctx);
-                match result.0 {
-                    gui::ItemMenuResult::Cancel => newrunstate = RunState::AwaitingInput,
-                    gui::ItemMenuResult::NoResponse => {}
-                    gui::ItemMenuResult::Selected => {
-                        let item_entity = result.1.unwrap();
-                        let mut intent = self.ecs.write_storage::<WantsToDropItem>();
-                        intent.insert(*self.ecs.fetch::<Entity>(), WantsToDropItem{ item: item_entity }).expect("Unable to insert intent");
-                        
-                        newrunstate = RunState::PlayerTurn;
-                    }
-                }
-            }
-            RunState::ShowTargeting{range, item} => {
-                let result = gui::ranged_target(self, ctx, range);
-                match result.0 {
-                    gui::ItemMenuResult::Cancel => newrunstate = RunState::AwaitingInput,
-                    gui::ItemMenuResult::NoResponse => {}
-                    gui::ItemMenuResult::Selected => {
-                        let mut intent = self.ecs.write_storage::<WantsToUseItem>();
-                        intent.insert(*self.ecs.fetch::<Entity>(), WantsToUseItem{ item, target: result.1 }).expect("Unable to insert intent");
-
-                        newrunstate = RunState::PlayerTurn;
-                    }
-                }
+            "--watch" => options.watch = true,
+            _ => {
+                eprintln!("Unknown argument {}. Usage: roguelike-tutorial [--seed <number>] [--replay <file> [--watch]]", arg);
+                std::process::exit(1);
             }
-            _ => {}
         }
-
-        {
-            let mut runwriter = self.ecs.write_resource::<RunState>();
-            *runwriter = newrunstate;
-        }
-        delete_the_dead(&mut self.ecs);
     }
-}
-
-impl State {
-    fn run_systems(&mut self) {
-        let mut vis = VisibilitySystem{};
-        vis.run_now(&self.ecs);
-
-        let mut monster_ai = MonsterAI{};
-        monster_ai.run_now(&self.ecs);
-
-        let mut map_indexing = MapIndexingSystem{};
-        map_indexing.run_now(&self.ecs);
-
-        let mut melee_combat_system = MeleeCombatSystem{};
-        melee_combat_system.run_now(&self.ecs);
-
-        let mut damage_system = DamageSystem{};
-        damage_system.run_now(&self.ecs);
-
-        let mut item_system = ItemCollectionSystem{};
-        item_system.run_now(&self.ecs);
-
-        let mut item_use_system = ItemUseSystem{};
-        item_use_system.run_now(&self.ecs);
-
-        let mut drop_items = ItemDropSystem{};
-        drop_items.run_now(&self.ecs);
-
-        self.ecs.maintain();
+    if options.watch && options.replay.is_none() {
+        eprintln!("--watch only works with --replay, eg: --replay {} --watch", replay::REPLAY_FILE_NAME);
+        std::process::exit(1);
     }
 
-    fn entities_to_remove_on_level_change(&mut self) -> Vec<Entity> {
-        let entities = self.ecs.entities();
-        let player = self.ecs.read_storage::<Player>();
-        let backpack = self.ecs.read_storage::<InBackpack>();
-        let player_entity = self.ecs.fetch::<Entity>();
-
-        let mut to_delete: Vec<Entity> = Vec::new();
-        for entity in entities.join() {
-            let mut should_delete = true;
+    options
+}
 
-            let p = player.get(entity);
-            if let Some(_p) = p {
-                should_delete = false;
-            }
+fn main() {
+    use rltk::RltkBuilder;
 
-            let bp = backpack.get(entity);
-            if let Some(bp) = bp {
-                if bp.owner == *player_entity {
-                    should_delete = false;
-                }
-            }
+    let options = parse_args();
+    let raws = match raws::RawMaster::load(raws::RAWS_FILE_NAME) {
+        Ok(raws) => raws,
+        Err(e) => {
+            eprintln!("Unable to load raws: {}", e);
+            std::process::exit(1);
+        }
+    };
 
-            if should_delete {
-                to_delete.push(entity);
+    if let Some(replay_file) = options.replay {
+        let replay = match replay::load_replay(&replay_file) {
+            Ok(replay) => replay,
+            Err(e) => {
+                eprintln!("Unable to load replay: {}", e);
+                std::process::exit(1);
             }
+        };
+
+        let mut gs = State::new_game(replay.seed, raws, ReplayRecorder::disabled());
+        if options.watch {
+            let mut screen = TextConsole::screen();
+            gs.watch_replay(&replay.actions, |state| {
+                screen.cls();
+                state.render(&mut screen);
+                // Clear the terminal and draw from the top left.
+                print!("\x1b[2J\x1b[H{}", screen.to_ansi());
+                std::thread::sleep(std::time::Duration::from_millis(WATCH_FRAME_MS));
+            });
+        } else {
+            gs.run_replay(&replay.actions);
         }
-
-        to_delete
+        println!("Replayed {} actions from seed {}", replay.actions.len(), replay.seed);
+        println!("Final state hash: {:016x}", replay::state_hash(&gs.ecs));
+        return;
     }
 
-    fn goto_next_level(&mut self) {
-        // Remove current level stuff
-        let to_delete = self.entities_to_remove_on_level_change();
-        for target in to_delete {
-            self.ecs.delete_entity(target).expect("Unable to delete entity when changeing level");
-        }
-
-        // Gen new map
-        let worldmap;
-        let new_depth;
-        {
-            let worldmap_resource = self.ecs.write_resource::<Map>();
-            new_depth = worldmap_resource.depth + 1;
-        }
-        let new_map = Map::new_map_rooms_and_corridors(&mut self.ecs, new_depth);
-        worldmap = new_map.clone();
-
-        // Gen new stuff
-        for room in worldmap.rooms.iter().skip(1) {
-            spawner::spawn_room(&mut self.ecs, room, new_depth);
-        }
-
-        // Move the player to a position on the new map
-        let (player_x, player_y) = worldmap.rooms[0].center();
-        let mut player_position = self.ecs.write_resource::<Point>();
-        *player_position = Point::new(player_x, player_y);
-        let mut position_components = self.ecs.write_storage::<Position>();
-        let player_entity = self.ecs.fetch::<Entity>();
-        let player_pos_comp = position_components.get_mut(*player_entity);
-        if let Some(player_pos_comp) = player_pos_comp {
-            player_pos_comp.x = player_x;
-            player_pos_comp.y = player_y;
-        }
-
-        {
-            let mut worldmap_resource = self.ecs.write_resource::<Map>();
-            *worldmap_resource = worldmap;
-        }
+    let seed = options.seed.unwrap_or_else(|| rltk::RandomNumberGenerator::new().next_u64());
+    println!("Seed: {}", seed);
 
-        // Mark player viewshed as dirty
-        let mut viewshed_components = self.ecs.write_storage::<Viewshed>();
-        let vs = viewshed_components.get_mut(*player_entity);
-        if let Some(vs) = vs {
-            vs.dirty = true;
+    let keybindings = match keybindings::Keybindings::load(keybindings::KEYBINDINGS_FILE_NAME) {
+        Ok((keybindings, conflicts)) => {
+            for conflict in conflicts.iter() {
+                eprintln!("Key conflict: {}", conflict);
+            }
+            keybindings
         }
-
-        // Notify the player and give them some health
-        let mut gamelog = self.ecs.fetch_mut::<gamelog::GameLog>();
-        gamelog.info("You descend to the next level, and take a moment to heal.".to_string());
-        let mut player_health_store = self.ecs.write_storage::<CombatStats>();
-        let player_health = player_health_store.get_mut(*player_entity);
-        if let Some(player_health) = player_health {
-            player_health.hp = i32::max(player_health.hp, player_health.max_hp / 2);
+        Err(e) => {
+            eprintln!("Unable to load key bindings: {}", e);
+            std::process::exit(1);
         }
-    }
-}
+    };
 
-fn main() {
-    use rltk::RltkBuilder;
     let ctx = RltkBuilder::simple80x50()
         .with_title("Roguelike Tutorial")
         .build()
         .unwrap();
 
-    let mut gs = State {
-        ecs: World::new(),
-        debug_mode: false,
-    };
-    
-    register_components(&mut gs.ecs);
-    gs.ecs.insert(rltk::RandomNumberGenerator::new());
-    gs.ecs.insert(SimpleMarkerAllocator::<SerializeMe>::new());
-
-    let map = Map::new_map_rooms_and_corridors(&mut gs.ecs, 1);
-
-    // Start the player in the center of a room
-    let (player_x, player_y) = map.rooms[0].center();
-
-    let player_entity = spawner::player(&mut gs.ecs, player_x, player_y);
-
-    // Spawn some mobs
-    for room in map.rooms.iter().skip(1) {
-        spawner::spawn_room(&mut gs.ecs, room, map.depth);
-    }
-
-    gs.ecs.insert(map);
-    gs.ecs.insert(GameLog::new(&["Welcome to Rusty Roguelike".to_string()]));
-    gs.ecs.insert(Point::new(player_x, player_y));
-    gs.ecs.insert(player_entity);
-    gs.ecs.insert(RunState::MainMenu{ menu_selection: gui::MainMenuSelection::NewGame });
+    let recorder = ReplayRecorder::create(replay::REPLAY_FILE_NAME, seed);
+    let mut gs = State::new_game(seed, raws, recorder);
+    gs.ecs.insert(keybindings);
 
     rltk::main_loop(ctx, gs).unwrap();
 }
@@ -1,15 +1,36 @@
-use rltk::{Algorithm2D,BaseMap,Point,SmallVec,RGB,Rltk,RandomNumberGenerator};
+use rltk::{Algorithm2D,BaseMap,Point,SmallVec,RGB};
 use specs::prelude::*;
-use std::cmp::{min,max};
 use serde::{Serialize, Deserialize};
 use crate::constants::*;
 use crate::rect::*;
+use crate::camera::Camera;
+use crate::console::Console;
 
-#[derive(PartialEq, Copy, Clone, Serialize, Deserialize)]
+/// How bright a tile in sight but in total darkness is drawn, compared to one in full light.
+const DARK_TILE_BRIGHTNESS: f32 = 0.35;
+
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Serialize, Deserialize)]
 pub enum TileType {
     Wall,
     Floor,
     DownStairs,
+    UpStairs,
+    /// Closed. Anyone walking into it opens it instead of moving.
+    Door,
+    OpenDoor,
+    /// Needs a key to open.
+    LockedDoor,
+}
+
+impl TileType {
+    /// Whether anything can stand here. Closed doors count, until they're opened.
+    pub fn blocks_movement(self) -> bool {
+        matches!(self, TileType::Wall | TileType::Door | TileType::LockedDoor)
+    }
+
+    pub fn blocks_sight(self) -> bool {
+        matches!(self, TileType::Wall | TileType::Door | TileType::LockedDoor)
+    }
 }
 
 #[derive(Default, Serialize, Deserialize, Clone)]
@@ -26,6 +47,23 @@ pub struct Map {
     #[serde(skip_serializing)]
     #[serde(skip_deserializing)]
     pub tile_content: Vec<Vec<Entity>>,
+
+    /// How brightly lit each tile is, from 0 (pitch dark) to 1. Worked out by `LightingSystem`, so not saved.
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub light: Vec<f32>,
+
+    /// The light sources (x, y, range) `light` was worked out for. None if the level has changed since,
+    /// so it needs working out again.
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub lit_by: Option<Vec<(i32, i32, i32)>>,
+
+    /// Goes up each time a tile changes whether it can be walked or seen through (eg: a door opening), so
+    /// anything worked out from the layout, like the `ChaseMap`, can tell it needs working out again.
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub layout_changes: u32,
 }
 
 impl Map {
@@ -41,6 +79,11 @@ impl Map {
         x > 0 && x < self.width-1 &&  y > 0 && y < self.height-1
     }
 
+    /// Whether (x, y) is on the map at all, edges included.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && x < self.width && y >= 0 && y < self.height
+    }
+
     fn idx_in_bounds(&self, idx: usize) -> bool {
         idx > 0 && idx < (self.width*self.height) as usize
     }
@@ -78,94 +121,71 @@ impl Map {
         return false;
     }
 
+    /// Co-ords of the first tile of type `tile`, if there is one.
+    pub fn find_tile(&self, tile: TileType) -> Option<Point> {
+        self.tiles
+            .iter()
+            .position(|t| *t == tile)
+            .map(|idx| Point::new(idx as i32 % self.width, idx as i32 / self.width))
+    }
+
     pub fn populate_blocked(&mut self) {
         for (i,tile) in self.tiles.iter().enumerate() {
-            self.blocked_tiles[i] = *tile == TileType::Wall;
+            self.blocked_tiles[i] = tile.blocks_movement();
         }
     }
 
-    pub fn clear_content_index(&mut self) {
-        for content in self.tile_content.iter_mut() {
-            content.clear();
+    /// Open the door at `idx`, if there's a closed (not locked) one there. Returns true if it opened.
+    pub fn open_door(&mut self, idx: usize) -> bool {
+        if self.tiles[idx] != TileType::Door {
+            return false;
         }
+        self.tiles[idx] = TileType::OpenDoor;
+        self.blocked_tiles[idx] = false;
+        self.layout_changed();
+        true
     }
 
-    pub fn new_map_rooms_and_corridors(ecs: &mut World, new_depth: i32) -> Map {
-        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
-
-        let tile_count = WORLD_WIDTH * WORLD_HEIGHT;
-        let mut map = Map {
-            tiles: vec![TileType::Wall; tile_count],
-            rooms: Vec::new(),
-            width: WORLD_WIDTH as i32,
-            height: WORLD_HEIGHT as i32,
-            revealed_tiles: vec![false; tile_count],
-            visible_tiles: vec![false; tile_count],
-            blocked_tiles: vec![false; tile_count],
-            tile_content: vec![Vec::new(); tile_count],
-            depth: new_depth,
-        };
-
-        const MAX_ROOMS : i32 = 30;
-        const MIN_SIZE : i32 = 6;
-        const MAX_SIZE : i32 = 10;
-
-        for _ in 0..MAX_ROOMS {
-            let w = rng.range(MIN_SIZE, MAX_SIZE);
-            let h = rng.range(MIN_SIZE, MAX_SIZE);
-            let x = rng.roll_dice(1, WORLD_WIDTH as i32 - w - 1) - 1;
-            let y = rng.roll_dice(1, WORLD_HEIGHT as i32 - h - 1) - 1;
-            let new_room = Rect::new(x, y, w, h);
-    
-            let ok = map.rooms
-                .iter() // Ok if no other room intersects with this one.
-                .all(|other_room| !other_room.intersect(&new_room));
-    
-            if ok {
-                map.apply_room_to_map(&new_room);
-    
-                if !map.rooms.is_empty() {
-                    // Connect to existing rooms
-                    let (new_x, new_y) = new_room.center();
-                    let (prev_x, prev_y) = map.rooms[map.rooms.len() - 1].center();
-    
-                    if rng.range(0, 2) == 1 {
-                        map.apply_horizontal_tunnel(prev_x, new_x, prev_y);
-                        map.apply_vertical_tunnel(prev_y, new_y, new_x);
-                    } else {
-                        map.apply_vertical_tunnel(prev_y, new_y, prev_x);
-                        map.apply_horizontal_tunnel(prev_x, new_x, new_y);
-                    }
-                }
-    
-                map.rooms.push(new_room);
-            }
-        }
+    /// Unlock and open the locked door at `idx`.
+    pub fn unlock_door(&mut self, idx: usize) {
+        self.tiles[idx] = TileType::OpenDoor;
+        self.blocked_tiles[idx] = false;
+        self.layout_changed();
+    }
 
-        let stairs_position = map.rooms[map.rooms.len()-1].center();
-        let stairs_idx = map.xy_idx(stairs_position.0, stairs_position.1);
-        map.tiles[stairs_idx] = TileType::DownStairs;
-    
-        map
+    /// The light and anything else worked out from the layout is out of date.
+    fn layout_changed(&mut self) {
+        self.lit_by = None;
+        self.layout_changes += 1;
     }
-    
-    fn apply_room_to_map(&mut self, room: &Rect) {
-        for y in room.y1 + 1 ..= room.y2 {
-            for x in room.x1 + 1 ..= room.x2 {
-                self.set_tile(x, y, TileType::Floor);
-            }
-        }
+
+    /// How brightly lit tile `idx` is. Everywhere counts as fully lit until the light has been worked out.
+    pub fn light_at(&self, idx: usize) -> f32 {
+        self.light.get(idx).copied().unwrap_or(1.0)
     }
-    
-    fn apply_horizontal_tunnel(&mut self, x1:i32, x2:i32, y:i32) {
-        for x in min(x1,x2) ..= max(x1,x2) {
-            self.set_tile(x, y, TileType::Floor);
+
+    /// A solid block of wall, `width` x `height`, ready for a map builder to carve into.
+    pub fn new(new_depth: i32, width: i32, height: i32) -> Map {
+        let map_tile_count = (width * height) as usize;
+        Map {
+            tiles: vec![TileType::Wall; map_tile_count],
+            rooms: Vec::new(),
+            width,
+            height,
+            revealed_tiles: vec![false; map_tile_count],
+            visible_tiles: vec![false; map_tile_count],
+            blocked_tiles: vec![false; map_tile_count],
+            tile_content: vec![Vec::new(); map_tile_count],
+            depth: new_depth,
+            light: Vec::new(),
+            lit_by: None,
+            layout_changes: 0,
         }
     }
-    
-    fn apply_vertical_tunnel(&mut self, y1:i32, y2:i32, x:i32) {
-        for y in min(y1,y2) ..= max(y1,y2) {
-            self.set_tile(x, y, TileType::Floor);
+
+    pub fn clear_content_index(&mut self) {
+        for content in self.tile_content.iter_mut() {
+            content.clear();
         }
     }
 
@@ -175,7 +195,8 @@ impl Map {
         }
         let idx = self.xy_idx(x, y);
 
-        !self.blocked_tiles[idx]
+        // Paths can go through closed doors, since walking into one opens it.
+        !self.blocked_tiles[idx] || self.tiles[idx] == TileType::Door
     }
 }
 
@@ -187,7 +208,7 @@ impl Algorithm2D for Map {
 
 impl BaseMap for Map {
     fn is_opaque(&self, idx: usize) -> bool {
-        self.tiles[idx as usize] == TileType::Wall
+        self.tiles[idx as usize].blocks_sight()
     }
 
     fn get_pathing_distance(&self, idx1: usize, idx2: usize) -> f32 {
@@ -220,53 +241,87 @@ impl BaseMap for Map {
     }
 }
 
-pub fn draw_map(ecs: &World, ctx: &mut Rltk, debug_mode: bool) {
+pub fn draw_map(ecs: &World, ctx: &mut dyn Console, debug_mode: bool) {
     let map = ecs.fetch::<Map>();
+    let camera = Camera::for_player(ecs);
+
+    for y in 0..VIEW_HEIGHT {
+        for x in 0..VIEW_WIDTH {
+            let tile_pos = match camera.to_world(Point::new(x, y)) {
+                Some(tile_pos) if map.contains(tile_pos.x, tile_pos.y) => tile_pos,
+                _ => continue,
+            };
+            let idx = map.xy_idx(tile_pos.x, tile_pos.y);
+            draw_tile(&map, ctx, idx, x, y, debug_mode);
+        }
+    }
+}
 
-    let mut x = 0;
-    let mut y = 0;
-
-    for (idx,tile) in map.tiles.iter().enumerate() {
-        // If visible, then draw
-        if map.revealed_tiles[idx] {
-            let glyph;
-            let mut fg;
-            match tile {
-                TileType::Floor => {
-                    glyph = rltk::to_cp437('.');
-                    fg = RGB::from_f32(0.5, 0.5, 0.5);
-                }
-                TileType::Wall => {
-                    glyph = rltk::to_cp437('#');
-                    fg = RGB::from_f32(0.0, 1.0, 0.0);
-                }
-                TileType::DownStairs => {
-                    glyph = rltk::to_cp437('>');
-                    fg = RGB::from_f32(0.0, 1.0, 1.0);
-                }
+/// Draw map tile `idx` at (x, y) on the screen, if the player has seen it (or we're debugging).
+fn draw_tile(map: &Map, ctx: &mut dyn Console, idx: usize, x: i32, y: i32, debug_mode: bool) {
+    let tile = &map.tiles[idx];
+    // If visible, then draw
+    if map.revealed_tiles[idx] {
+        let glyph;
+        let mut fg;
+        match tile {
+            TileType::Floor => {
+                glyph = rltk::to_cp437('.');
+                fg = RGB::from_f32(0.5, 0.5, 0.5);
+            }
+            TileType::Wall => {
+                glyph = rltk::to_cp437('#');
+                fg = RGB::from_f32(0.0, 1.0, 0.0);
+            }
+            TileType::DownStairs => {
+                glyph = rltk::to_cp437('>');
+                fg = RGB::from_f32(0.0, 1.0, 1.0);
             }
-            if !map.visible_tiles[idx] {
-                fg = fg.to_greyscale();
+            TileType::UpStairs => {
+                glyph = rltk::to_cp437('<');
+                fg = RGB::from_f32(0.0, 1.0, 1.0);
             }
-            ctx.set(x, y, fg, RGB::from_f32(0., 0., 0.), glyph);
-        } else if debug_mode {
-            match tile {
-                TileType::Floor => {
-                    ctx.set(x, y, RGB::from_f32(0.1, 0.1, 0.1), RGB::from_f32(0., 0., 0.), rltk::to_cp437('.'));
-                }
-                TileType::Wall => {
-                    ctx.set(x, y, RGB::from_f32(0.0, 0.2, 0.0), RGB::from_f32(0., 0., 0.), rltk::to_cp437('#'));
-                }
-                TileType::DownStairs => {
-                    ctx.set(x, y, RGB::from_f32(0.0, 0.2, 0.0), RGB::from_f32(0., 0., 0.), rltk::to_cp437('>'));
-                }
+            TileType::Door => {
+                glyph = rltk::to_cp437('+');
+                fg = RGB::named(rltk::CHOCOLATE);
             }
+            TileType::OpenDoor => {
+                glyph = rltk::to_cp437('/');
+                fg = RGB::named(rltk::CHOCOLATE);
+            }
+            TileType::LockedDoor => {
+                glyph = rltk::to_cp437('+');
+                fg = RGB::named(rltk::GOLD);
+            }
+        }
+        if map.visible_tiles[idx] {
+            // Tiles in sight but out of the light are still there, just hard to make out.
+            let brightness = DARK_TILE_BRIGHTNESS + (1.0 - DARK_TILE_BRIGHTNESS) * map.light_at(idx);
+            fg = RGB::from_f32(fg.r * brightness, fg.g * brightness, fg.b * brightness);
+        } else {
+            fg = fg.to_greyscale();
         }
-        
-        x += 1;
-        if x > map.width - 1 {
-            x = 0;
-            y += 1;
+        ctx.set(x, y, fg, RGB::from_f32(0., 0., 0.), glyph);
+    } else if debug_mode {
+        match tile {
+            TileType::Floor => {
+                ctx.set(x, y, RGB::from_f32(0.1, 0.1, 0.1), RGB::from_f32(0., 0., 0.), rltk::to_cp437('.'));
+            }
+            TileType::Wall => {
+                ctx.set(x, y, RGB::from_f32(0.0, 0.2, 0.0), RGB::from_f32(0., 0., 0.), rltk::to_cp437('#'));
+            }
+            TileType::DownStairs => {
+                ctx.set(x, y, RGB::from_f32(0.0, 0.2, 0.0), RGB::from_f32(0., 0., 0.), rltk::to_cp437('>'));
+            }
+            TileType::UpStairs => {
+                ctx.set(x, y, RGB::from_f32(0.0, 0.2, 0.0), RGB::from_f32(0., 0., 0.), rltk::to_cp437('<'));
+            }
+            TileType::Door | TileType::LockedDoor => {
+                ctx.set(x, y, RGB::from_f32(0.2, 0.1, 0.0), RGB::from_f32(0., 0., 0.), rltk::to_cp437('+'));
+            }
+            TileType::OpenDoor => {
+                ctx.set(x, y, RGB::from_f32(0.2, 0.1, 0.0), RGB::from_f32(0., 0., 0.), rltk::to_cp437('/'));
+            }
         }
     }
 }
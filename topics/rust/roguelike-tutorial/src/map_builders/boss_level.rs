@@ -0,0 +1,77 @@
+use rltk::RandomNumberGenerator;
+use super::MapBuilder;
+use super::prefab::Prefab;
+use crate::components::Position;
+use crate::map::Map;
+
+/// The lair at the bottom of the dungeon, drawn by hand. The Lich King (`L`) guards the amulet (`A`)
+/// in the hall at the top; the player arrives at `@`. See `prefab::legend` for the rest.
+const BOSS_LAIR: &str = r#"
+#############################################
+#####*###########*#####*###########*#########
+####.......#.................#.......########
+####.!.....#........A........#.....$.########
+####.......+.................+.......########
+####.......#........L........#.......########
+########+###.................###+############
+#......#....##..#.......#..##....#......#####
+#..s...#.....#..............#....#..^...#####
+#......+........#.......#........+......#####
+#......#.....#..............#....#..!...#####
+#......#....##..#...o...#..##....#......#####
+####+###.......................o..###+#######
+#..........#..#.#.......#.#..#..........#####
+#..g.......*..................*......a..#####
+#..........#..#.#.......#.#..#..........#####
+#######.############+###########.############
+#######.#######...........######.############
+#######.#######.....@.....######.############
+#######.........................#############
+#############################################
+"#;
+
+/// The final level: always the same lair, set in solid rock in the middle of the map.
+pub struct BossLevelBuilder {
+    map: Map,
+    starting_position: Position,
+    spawns: Vec<(usize, String)>,
+}
+
+impl BossLevelBuilder {
+    pub fn new(new_depth: i32, width: i32, height: i32) -> BossLevelBuilder {
+        BossLevelBuilder {
+            map: Map::new(new_depth, width, height),
+            starting_position: Position{ x: 0, y: 0 },
+            spawns: Vec::new(),
+        }
+    }
+}
+
+impl MapBuilder for BossLevelBuilder {
+    fn build_map(&mut self, _rng: &mut RandomNumberGenerator) {
+        let lair = Prefab::parse(BOSS_LAIR);
+        let x = (self.map.width - lair.width) / 2;
+        let y = (self.map.height - lair.height) / 2;
+        self.spawns = lair.stamp(&mut self.map, x, y);
+
+        let (start_x, start_y) = lair.find('@').expect("The boss lair has nowhere for the player to start");
+        self.starting_position = Position{ x: x + start_x, y: y + start_y };
+    }
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position.clone()
+    }
+
+    /// Everything here is placed by hand.
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        Vec::new()
+    }
+
+    fn get_spawn_list(&self) -> Vec<(usize, String)> {
+        self.spawns.clone()
+    }
+}
@@ -0,0 +1,90 @@
+use rltk::RandomNumberGenerator;
+use super::MapBuilder;
+use super::common::*;
+use crate::components::Position;
+use crate::map::{Map,TileType};
+
+const SPAWN_CHUNK_SIZE: i32 = 12;
+
+/// Natural looking caves: start from noise, then smooth it out by counting neighbouring walls.
+pub struct CellularAutomataBuilder {
+    map: Map,
+    starting_position: Position,
+}
+
+impl CellularAutomataBuilder {
+    pub fn new(new_depth: i32, width: i32, height: i32) -> CellularAutomataBuilder {
+        CellularAutomataBuilder {
+            map: Map::new(new_depth, width, height),
+            starting_position: Position{ x: 0, y: 0 },
+        }
+    }
+}
+
+impl MapBuilder for CellularAutomataBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        // Deeper caves start out a little more cramped.
+        let wall_chance = i32::min(45 + self.map.depth, 55);
+
+        for y in 1 .. self.map.height - 1 {
+            for x in 1 .. self.map.width - 1 {
+                let idx = self.map.xy_idx(x, y);
+                if rng.roll_dice(1, 100) > wall_chance {
+                    self.map.tiles[idx] = TileType::Floor;
+                } else {
+                    self.map.tiles[idx] = TileType::Wall;
+                }
+            }
+        }
+
+        for _ in 0..15 {
+            let mut new_tiles = self.map.tiles.clone();
+            let w = self.map.width as usize;
+
+            for y in 1 .. self.map.height - 1 {
+                for x in 1 .. self.map.width - 1 {
+                    let idx = self.map.xy_idx(x, y);
+                    let neighbours = [
+                        idx - 1, idx + 1, idx - w, idx + w,
+                        idx - w - 1, idx - w + 1, idx + w - 1, idx + w + 1,
+                    ]
+                        .iter()
+                        .filter(|n| self.map.tiles[**n] == TileType::Wall)
+                        .count();
+
+                    if neighbours > 4 || neighbours == 0 {
+                        new_tiles[idx] = TileType::Wall;
+                    } else {
+                        new_tiles[idx] = TileType::Floor;
+                    }
+                }
+            }
+
+            self.map.tiles = new_tiles;
+        }
+
+        // Start in the middle, walking left until we find open floor.
+        self.starting_position = Position{ x: self.map.width / 2, y: self.map.height / 2 };
+        let mut start_idx = self.map.xy_idx(self.starting_position.x, self.starting_position.y);
+        while self.map.tiles[start_idx] != TileType::Floor && self.starting_position.x > 1 {
+            self.starting_position.x -= 1;
+            start_idx = self.map.xy_idx(self.starting_position.x, self.starting_position.y);
+        }
+        self.map.tiles[start_idx] = TileType::Floor;
+
+        let exit_idx = remove_unreachable_areas_returning_most_distant(&mut self.map, start_idx);
+        self.map.tiles[exit_idx] = TileType::DownStairs;
+    }
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position.clone()
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        chunk_spawn_regions(&self.map, &self.starting_position, SPAWN_CHUNK_SIZE)
+    }
+}
@@ -0,0 +1,270 @@
+use std::cmp::{min,max};
+use std::collections::BTreeMap;
+use rltk::RandomNumberGenerator;
+use crate::components::Position;
+use crate::map::{Map,TileType};
+use crate::rect::Rect;
+
+pub fn apply_room_to_map(map: &mut Map, room: &Rect) {
+    for y in room.y1 + 1 ..= room.y2 {
+        for x in room.x1 + 1 ..= room.x2 {
+            map.set_tile(x, y, TileType::Floor);
+        }
+    }
+}
+
+pub fn apply_horizontal_tunnel(map: &mut Map, x1:i32, x2:i32, y:i32) {
+    for x in min(x1,x2) ..= max(x1,x2) {
+        map.set_tile(x, y, TileType::Floor);
+    }
+}
+
+pub fn apply_vertical_tunnel(map: &mut Map, y1:i32, y2:i32, x:i32) {
+    for y in min(y1,y2) ..= max(y1,y2) {
+        map.set_tile(x, y, TileType::Floor);
+    }
+}
+
+/// Every room but the first (where the player starts) becomes its own spawn region.
+pub fn room_spawn_regions(map: &Map) -> Vec<Vec<usize>> {
+    map.rooms
+        .iter()
+        .skip(1)
+        .map(|room| {
+            let mut region = Vec::new();
+            for y in room.y1 + 1 ..= room.y2 {
+                for x in room.x1 + 1 ..= room.x2 {
+                    region.push(map.xy_idx(x, y));
+                }
+            }
+            region
+        })
+        .collect()
+}
+
+/// Chop the floor into square chunks for layouts without rooms. The chunk holding the start is left empty.
+pub fn chunk_spawn_regions(map: &Map, start: &Position, chunk_size: i32) -> Vec<Vec<usize>> {
+    let start_chunk = (start.x / chunk_size, start.y / chunk_size);
+
+    // BTreeMap so regions always come out in the same order for the same map.
+    let mut chunks: BTreeMap<(i32, i32), Vec<usize>> = BTreeMap::new();
+    for y in 1 .. map.height - 1 {
+        for x in 1 .. map.width - 1 {
+            let idx = map.xy_idx(x, y);
+            let chunk = (x / chunk_size, y / chunk_size);
+            if map.tiles[idx] == TileType::Floor && chunk != start_chunk {
+                chunks.entry(chunk).or_default().push(idx);
+            }
+        }
+    }
+
+    chunks.into_values().collect()
+}
+
+/// Wall off any floor the player can't walk to from `start_idx`, and return the reachable tile furthest from it.
+pub fn remove_unreachable_areas_returning_most_distant(map: &mut Map, start_idx: usize) -> usize {
+    map.populate_blocked();
+    let map_starts: Vec<usize> = vec![start_idx];
+    let dijkstra_map = rltk::DijkstraMap::new(map.width as usize, map.height as usize, &map_starts, &*map, 2000.0);
+
+    let mut exit_tile = (start_idx, 0.0f32);
+    for (i, tile) in map.tiles.iter_mut().enumerate() {
+        if *tile == TileType::Floor {
+            let distance_to_start = dijkstra_map.map[i];
+            if distance_to_start == f32::MAX {
+                *tile = TileType::Wall;
+            } else if distance_to_start > exit_tile.1 {
+                exit_tile = (i, distance_to_start);
+            }
+        }
+    }
+    map.populate_blocked();
+
+    exit_tile.0
+}
+
+/// A room sealed off with locked doors, and the tile its key should be left on.
+pub struct Vault {
+    pub room: Rect,
+    pub key_idx: usize,
+}
+
+/// Floor on the ring of wall around `room`: everywhere a corridor or another room breaks in.
+fn room_entrances(map: &Map, room: &Rect) -> Vec<usize> {
+    let mut entrances = Vec::new();
+    for y in room.y1 ..= room.y2 + 1 {
+        for x in room.x1 ..= room.x2 + 1 {
+            let on_ring = x == room.x1 || x == room.x2 + 1 || y == room.y1 || y == room.y2 + 1;
+            if on_ring && map.xy_in_map(x, y) && map.tiles[map.xy_idx(x, y)] == TileType::Floor {
+                entrances.push(map.xy_idx(x, y));
+            }
+        }
+    }
+    entrances
+}
+
+pub fn room_contains(room: &Rect, x: i32, y: i32) -> bool {
+    x > room.x1 && x <= room.x2 && y > room.y1 && y <= room.y2
+}
+
+/// A gap exactly one tile wide, with wall either side, where a door looks right.
+fn is_doorway(map: &Map, idx: usize) -> bool {
+    let x = idx as i32 % map.width;
+    let y = idx as i32 / map.width;
+    let wall = |x: i32, y: i32| map.tiles[map.xy_idx(x, y)] == TileType::Wall;
+    (wall(x - 1, y) && wall(x + 1, y)) || (wall(x, y - 1) && wall(x, y + 1))
+}
+
+/// Hang closed doors in most of the narrow entrances to rooms.
+pub fn add_doors(map: &mut Map, rng: &mut RandomNumberGenerator) {
+    let rooms = map.rooms.clone();
+    for room in rooms.iter() {
+        for idx in room_entrances(map, room) {
+            if is_doorway(map, idx) && rng.roll_dice(1, 3) > 1 {
+                map.tiles[idx] = TileType::Door;
+            }
+        }
+    }
+    map.populate_blocked();
+}
+
+/// Maybe turn one room into a vault, locking every way in. Only rooms that can be locked without cutting off
+/// any other part of the level are used, and the key goes somewhere reachable from `start`.
+pub fn add_vault(map: &mut Map, rng: &mut RandomNumberGenerator, start: &Position) -> Option<Vault> {
+    if map.rooms.len() < 3 || rng.roll_dice(1, 2) == 1 {
+        return None;
+    }
+
+    // Not where the player starts, and not anywhere with stairs.
+    let mut candidates: Vec<Rect> = map.rooms.iter()
+        .filter(|room| !room_contains(room, start.x, start.y) && !room_has_stairs(map, room))
+        .copied()
+        .collect();
+
+    while !candidates.is_empty() {
+        let room = candidates.remove((rng.roll_dice(1, candidates.len() as i32) - 1) as usize);
+        let entrances = room_entrances(map, &room);
+        if entrances.is_empty() {
+            continue;
+        }
+
+        for idx in entrances.iter() {
+            map.tiles[*idx] = TileType::LockedDoor;
+        }
+        map.populate_blocked();
+
+        if let Some(key_idx) = key_position(map, rng, start, &room) {
+            return Some(Vault{ room, key_idx });
+        }
+
+        for idx in entrances.iter() {
+            map.tiles[*idx] = TileType::Floor;
+        }
+        map.populate_blocked();
+    }
+
+    None
+}
+
+pub fn room_has_stairs(map: &Map, room: &Rect) -> bool {
+    map.tiles.iter().enumerate().any(|(idx, tile)| {
+        (*tile == TileType::DownStairs || *tile == TileType::UpStairs)
+            && room_contains(room, idx as i32 % map.width, idx as i32 / map.width)
+    })
+}
+
+/// Levels without rooms get a brazier for about every this many tiles.
+const TILES_PER_BRAZIER: i32 = 600;
+
+/// A wall tile with open floor beside it, where a brazier can hang.
+fn faces_floor(map: &Map, x: i32, y: i32) -> bool {
+    let floor = |x: i32, y: i32| map.contains(x, y) && map.tiles[map.xy_idx(x, y)] == TileType::Floor;
+    map.xy_in_map(x, y) && map.tiles[map.xy_idx(x, y)] == TileType::Wall
+        && (floor(x - 1, y) || floor(x + 1, y) || floor(x, y - 1) || floor(x, y + 1))
+}
+
+/// Wall tiles to hang braziers on: one on the wall of about every other room, or scattered about
+/// for layouts without rooms.
+pub fn pick_brazier_spots(map: &Map, rng: &mut RandomNumberGenerator) -> Vec<usize> {
+    let mut spots = Vec::new();
+
+    if map.rooms.is_empty() {
+        let mut walls: Vec<usize> = (0..map.tiles.len())
+            .filter(|idx| faces_floor(map, *idx as i32 % map.width, *idx as i32 / map.width))
+            .collect();
+        let count = map.width * map.height / TILES_PER_BRAZIER;
+        for _i in 0..count {
+            if walls.is_empty() {
+                break;
+            }
+            spots.push(walls.remove((rng.roll_dice(1, walls.len() as i32) - 1) as usize));
+        }
+        return spots;
+    }
+
+    for room in map.rooms.iter() {
+        if rng.roll_dice(1, 2) == 1 {
+            continue;
+        }
+        // The ring of wall around the room, on the side facing into it.
+        let mut walls = Vec::new();
+        for y in room.y1 ..= room.y2 + 1 {
+            for x in room.x1 ..= room.x2 + 1 {
+                let on_ring = x == room.x1 || x == room.x2 + 1 || y == room.y1 || y == room.y2 + 1;
+                let faces_in = room_contains(room, x - 1, y) || room_contains(room, x + 1, y)
+                    || room_contains(room, x, y - 1) || room_contains(room, x, y + 1);
+                if on_ring && faces_in && faces_floor(map, x, y) {
+                    walls.push(map.xy_idx(x, y));
+                }
+            }
+        }
+        if !walls.is_empty() {
+            spots.push(walls[(rng.roll_dice(1, walls.len() as i32) - 1) as usize]);
+        }
+    }
+    spots
+}
+
+/// Maybe pick a room for a shop. Never the start room, the vault or a room with stairs.
+pub fn pick_shop_room(map: &Map, rng: &mut RandomNumberGenerator, start: &Position, vault: Option<&Vault>) -> Option<Rect> {
+    if map.rooms.len() < 3 || rng.roll_dice(1, 3) > 1 {
+        return None;
+    }
+
+    let candidates: Vec<Rect> = map.rooms.iter()
+        .filter(|room| {
+            !room_contains(room, start.x, start.y) && !room_has_stairs(map, room) && vault.is_none_or(|v| v.room != **room)
+        })
+        .copied()
+        .collect();
+    if candidates.is_empty() {
+        return None;
+    }
+    Some(candidates[(rng.roll_dice(1, candidates.len() as i32) - 1) as usize])
+}
+
+/// A random floor tile outside `vault` for the key. None if locking the vault has cut anywhere else off.
+fn key_position(map: &Map, rng: &mut RandomNumberGenerator, start: &Position, vault: &Rect) -> Option<usize> {
+    let start_idx = map.xy_idx(start.x, start.y);
+    let dijkstra_map = rltk::DijkstraMap::new(map.width as usize, map.height as usize, &[start_idx], map, 2000.0);
+
+    let mut spots = Vec::new();
+    for (idx, tile) in map.tiles.iter().enumerate() {
+        let x = idx as i32 % map.width;
+        let y = idx as i32 / map.width;
+        if *tile == TileType::Wall || *tile == TileType::LockedDoor || room_contains(vault, x, y) {
+            continue;
+        }
+        if dijkstra_map.map[idx] == f32::MAX {
+            return None;
+        }
+        if *tile == TileType::Floor && idx != start_idx {
+            spots.push(idx);
+        }
+    }
+
+    if spots.is_empty() {
+        return None;
+    }
+    Some(spots[(rng.roll_dice(1, spots.len() as i32) - 1) as usize])
+}
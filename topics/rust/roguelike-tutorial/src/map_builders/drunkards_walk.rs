@@ -0,0 +1,76 @@
+use rltk::RandomNumberGenerator;
+use super::MapBuilder;
+use super::common::*;
+use crate::components::Position;
+use crate::map::{Map,TileType};
+
+const SPAWN_CHUNK_SIZE: i32 = 12;
+
+/// Let diggers stumble around at random, carving floor wherever they go, until enough of the map is open.
+pub struct DrunkardsWalkBuilder {
+    map: Map,
+    starting_position: Position,
+}
+
+impl DrunkardsWalkBuilder {
+    pub fn new(new_depth: i32, width: i32, height: i32) -> DrunkardsWalkBuilder {
+        DrunkardsWalkBuilder {
+            map: Map::new(new_depth, width, height),
+            starting_position: Position{ x: 0, y: 0 },
+        }
+    }
+}
+
+impl MapBuilder for DrunkardsWalkBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.starting_position = Position{ x: self.map.width / 2, y: self.map.height / 2 };
+        let start_idx = self.map.xy_idx(self.starting_position.x, self.starting_position.y);
+        self.map.tiles[start_idx] = TileType::Floor;
+
+        // Diggers live longer deeper down, giving longer, windier passages.
+        let drunken_lifetime = 200 + (self.map.depth * 25);
+        let desired_floor_tiles = self.map.tiles.len() / 2;
+        let mut floor_tile_count = 1;
+
+        while floor_tile_count < desired_floor_tiles {
+            // Every digger starts from somewhere that is already open, so the map stays connected.
+            let open_tiles: Vec<usize> = self.map.tiles
+                .iter()
+                .enumerate()
+                .filter(|(_idx, tile)| **tile == TileType::Floor)
+                .map(|(idx, _tile)| idx)
+                .collect();
+            let digger_start = open_tiles[(rng.roll_dice(1, open_tiles.len() as i32) - 1) as usize];
+            let mut drunk_x = digger_start as i32 % self.map.width;
+            let mut drunk_y = digger_start as i32 / self.map.width;
+
+            for _ in 0..drunken_lifetime {
+                self.map.set_tile(drunk_x, drunk_y, TileType::Floor);
+
+                match rng.roll_dice(1, 4) {
+                    1 => if drunk_x > 2 { drunk_x -= 1; }
+                    2 => if drunk_x < self.map.width - 2 { drunk_x += 1; }
+                    3 => if drunk_y > 2 { drunk_y -= 1; }
+                    _ => if drunk_y < self.map.height - 2 { drunk_y += 1; }
+                }
+            }
+
+            floor_tile_count = self.map.tiles.iter().filter(|tile| **tile == TileType::Floor).count();
+        }
+
+        let exit_idx = remove_unreachable_areas_returning_most_distant(&mut self.map, start_idx);
+        self.map.tiles[exit_idx] = TileType::DownStairs;
+    }
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position.clone()
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        chunk_spawn_regions(&self.map, &self.starting_position, SPAWN_CHUNK_SIZE)
+    }
+}
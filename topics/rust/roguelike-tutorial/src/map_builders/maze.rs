@@ -0,0 +1,75 @@
+use rltk::RandomNumberGenerator;
+use super::MapBuilder;
+use super::common::*;
+use crate::components::Position;
+use crate::map::{Map,TileType};
+
+const SPAWN_CHUNK_SIZE: i32 = 10;
+
+/// A perfect maze from a recursive backtracker. Cells sit on odd co-ords, with the walls between them on even ones.
+pub struct MazeBuilder {
+    map: Map,
+    starting_position: Position,
+}
+
+impl MazeBuilder {
+    pub fn new(new_depth: i32, width: i32, height: i32) -> MazeBuilder {
+        MazeBuilder {
+            map: Map::new(new_depth, width, height),
+            starting_position: Position{ x: 0, y: 0 },
+        }
+    }
+
+    fn carve_cell(&mut self, cell_x: i32, cell_y: i32) {
+        self.map.set_tile(cell_x * 2 + 1, cell_y * 2 + 1, TileType::Floor);
+    }
+}
+
+impl MapBuilder for MazeBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        let columns = (self.map.width - 2) / 2;
+        let rows = (self.map.height - 2) / 2;
+        let mut visited = vec![false; (columns * rows) as usize];
+        let mut stack: Vec<(i32, i32)> = vec![(0, 0)];
+        visited[0] = true;
+        self.carve_cell(0, 0);
+
+        while let Some(&(cell_x, cell_y)) = stack.last() {
+            let neighbours: Vec<(i32, i32)> = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                .iter()
+                .map(|(dx, dy)| (cell_x + dx, cell_y + dy))
+                .filter(|(x, y)| *x >= 0 && *x < columns && *y >= 0 && *y < rows)
+                .filter(|(x, y)| !visited[(y * columns + x) as usize])
+                .collect();
+
+            if neighbours.is_empty() {
+                stack.pop();
+                continue;
+            }
+
+            let (next_x, next_y) = neighbours[(rng.roll_dice(1, neighbours.len() as i32) - 1) as usize];
+            // Knock down the wall between the two cells.
+            self.map.set_tile(cell_x + next_x + 1, cell_y + next_y + 1, TileType::Floor);
+            self.carve_cell(next_x, next_y);
+            visited[(next_y * columns + next_x) as usize] = true;
+            stack.push((next_x, next_y));
+        }
+
+        self.starting_position = Position{ x: 1, y: 1 };
+        let start_idx = self.map.xy_idx(1, 1);
+        let exit_idx = remove_unreachable_areas_returning_most_distant(&mut self.map, start_idx);
+        self.map.tiles[exit_idx] = TileType::DownStairs;
+    }
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position.clone()
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        chunk_spawn_regions(&self.map, &self.starting_position, SPAWN_CHUNK_SIZE)
+    }
+}
@@ -0,0 +1,52 @@
+use rltk::RandomNumberGenerator;
+use crate::components::Position;
+use crate::map::Map;
+use crate::constants::FINAL_DEPTH;
+
+mod common;
+mod simple_map;
+mod bsp_dungeon;
+mod cellular_automata;
+mod drunkards_walk;
+mod maze;
+mod prefab;
+mod boss_level;
+
+use simple_map::SimpleMapBuilder;
+use bsp_dungeon::BspDungeonBuilder;
+use cellular_automata::CellularAutomataBuilder;
+use drunkards_walk::DrunkardsWalkBuilder;
+use maze::MazeBuilder;
+use boss_level::BossLevelBuilder;
+pub use common::{Vault,add_doors,add_vault,pick_shop_room,pick_brazier_spots};
+pub use prefab::{Prefab,PREFAB_VAULTS,add_prefab_vault};
+
+pub trait MapBuilder {
+    /// Generate the level. All randomness comes from `rng`, so a seeded rng gives the same level every time.
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator);
+    fn get_map(&self) -> Map;
+    fn get_starting_position(&self) -> Position;
+    /// Groups of map indices to populate with monsters and items, one spawn roll per group.
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>>;
+    /// Particular things in particular places, as (map index, raws name). Most levels have none.
+    fn get_spawn_list(&self) -> Vec<(usize, String)> {
+        Vec::new()
+    }
+}
+
+/// Pick a builder for a `width` x `height` level at `new_depth`. The final depth is always the boss's lair.
+pub fn random_builder(new_depth: i32, width: i32, height: i32, rng: &mut RandomNumberGenerator) -> Box<dyn MapBuilder> {
+    if new_depth >= FINAL_DEPTH {
+        return Box::new(BossLevelBuilder::new(new_depth, width, height));
+    }
+
+    // Keep the first couple of levels to classic rooms, open it up as the player goes deeper.
+    let choices = if new_depth < 3 { 2 } else { 5 };
+    match rng.roll_dice(1, choices) {
+        1 => Box::new(SimpleMapBuilder::new(new_depth, width, height)),
+        2 => Box::new(BspDungeonBuilder::new(new_depth, width, height)),
+        3 => Box::new(CellularAutomataBuilder::new(new_depth, width, height)),
+        4 => Box::new(DrunkardsWalkBuilder::new(new_depth, width, height)),
+        _ => Box::new(MazeBuilder::new(new_depth, width, height)),
+    }
+}
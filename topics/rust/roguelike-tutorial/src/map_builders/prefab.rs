@@ -0,0 +1,147 @@
+use rltk::RandomNumberGenerator;
+use super::common::{Vault,room_contains,room_has_stairs};
+use crate::components::Position;
+use crate::map::{Map,TileType};
+use crate::rect::Rect;
+
+/// Small hand-drawn rooms the generator can drop into a level. See `legend` for what the characters mean.
+/// Each needs a tile of space all round it, and everything in it must be reachable from that border.
+pub const PREFAB_VAULTS: [&str; 4] = [
+    // Treasure cell
+r#"
+##+##
+#.!.#
++$.$+
+#.g.#
+##+##
+"#,
+    // Spider nest
+r#"
+.^...^.
+.#s.s#.
+.#.$.#.
+.#####.
+.......
+"#,
+    // Pillared hall
+r#"
+.......
+.#.o.#.
+...!...
+.#...#.
+.......
+"#,
+    // Shrine
+r#"
+#*#*#
+..!..
+.g...
+"#,
+];
+
+/// What a character in a template stands for: the tile it leaves behind, and anything to spawn on it.
+/// `@` is where the player starts, on templates that have one.
+fn legend(glyph: char) -> (TileType, Option<&'static str>) {
+    match glyph {
+        '#' => (TileType::Wall, None),
+        '+' => (TileType::Door, None),
+        '*' => (TileType::Wall, Some("Brazier")),
+        'g' => (TileType::Floor, Some("Goblin")),
+        'o' => (TileType::Floor, Some("Orc")),
+        'a' => (TileType::Floor, Some("Goblin Archer")),
+        's' => (TileType::Floor, Some("Cave Spider")),
+        '!' => (TileType::Floor, Some("Health Potion")),
+        '$' => (TileType::Floor, Some("Gold")),
+        '^' => (TileType::Floor, Some("Spike Trap")),
+        'L' => (TileType::Floor, Some("Lich King")),
+        'A' => (TileType::Floor, Some("Amulet of the Ancients")),
+        _ => (TileType::Floor, None),
+    }
+}
+
+/// A text template for part of a level, one line per row.
+pub struct Prefab {
+    pub width: i32,
+    pub height: i32,
+    glyphs: Vec<char>,
+}
+
+impl Prefab {
+    /// Blank lines at either end are skipped. Every other row has to be the same length.
+    pub fn parse(template: &str) -> Prefab {
+        let lines: Vec<&str> = template.lines().collect();
+        let first = lines.iter().position(|line| !line.trim().is_empty()).unwrap_or(lines.len());
+        let last = lines.iter().rposition(|line| !line.trim().is_empty()).map_or(first, |idx| idx + 1);
+        let rows = &lines[first..last];
+        let width = rows.first().map_or(0, |row| row.chars().count());
+        for row in rows.iter() {
+            assert_eq!(row.chars().count(), width, "Prefab rows must all be the same length: {:?}", row);
+        }
+
+        Prefab {
+            width: width as i32,
+            height: rows.len() as i32,
+            glyphs: rows.iter().flat_map(|row| row.chars()).collect(),
+        }
+    }
+
+    pub fn glyph(&self, x: i32, y: i32) -> char {
+        self.glyphs[(y * self.width + x) as usize]
+    }
+
+    /// Where `glyph` first appears in the template, if it does.
+    pub fn find(&self, glyph: char) -> Option<(i32, i32)> {
+        self.glyphs.iter()
+            .position(|g| *g == glyph)
+            .map(|idx| (idx as i32 % self.width, idx as i32 / self.width))
+    }
+
+    /// Copy the template onto `map` with its top left corner at (x, y).
+    /// Returns what the template says to spawn, as (map index, raws name).
+    pub fn stamp(&self, map: &mut Map, x: i32, y: i32) -> Vec<(usize, String)> {
+        let mut spawns = Vec::new();
+        for ty in 0..self.height {
+            for tx in 0..self.width {
+                let (tile, spawn) = legend(self.glyph(tx, ty));
+                if map.set_tile(x + tx, y + ty, tile) {
+                    if let Some(name) = spawn {
+                        spawns.push((map.xy_idx(x + tx, y + ty), name.to_string()));
+                    }
+                }
+            }
+        }
+        map.populate_blocked();
+        spawns
+    }
+}
+
+/// Maybe build one of the `PREFAB_VAULTS` into a room big enough for it. Never the start room, a room with
+/// stairs, the shop, the vault or wherever its key was left. Returns what to spawn in it.
+pub fn add_prefab_vault(map: &mut Map, rng: &mut RandomNumberGenerator, start: &Position, vault: Option<&Vault>,
+                        shop: Option<&Rect>) -> Vec<(usize, String)> {
+    if map.rooms.len() < 3 || rng.roll_dice(1, 3) > 1 {
+        return Vec::new();
+    }
+
+    let prefab = Prefab::parse(PREFAB_VAULTS[(rng.roll_dice(1, PREFAB_VAULTS.len() as i32) - 1) as usize]);
+    let candidates: Vec<Rect> = map.rooms.iter()
+        .filter(|room| {
+            let key_inside = vault.is_some_and(|v| {
+                room_contains(room, v.key_idx as i32 % map.width, v.key_idx as i32 / map.width)
+            });
+            !room_contains(room, start.x, start.y) && !room_has_stairs(map, room) && !key_inside
+                && vault.is_none_or(|v| v.room != **room) && shop.is_none_or(|s| s != *room)
+                && room.x2 - room.x1 >= prefab.width + 2 && room.y2 - room.y1 >= prefab.height + 2
+        })
+        .copied()
+        .collect();
+    if candidates.is_empty() {
+        return Vec::new();
+    }
+
+    // Centred, leaving a ring of floor between it and the room's walls so every way in still leads somewhere.
+    let room = candidates[(rng.roll_dice(1, candidates.len() as i32) - 1) as usize];
+    let x = room.x1 + 1 + (room.x2 - room.x1 - prefab.width) / 2;
+    let y = room.y1 + 1 + (room.y2 - room.y1 - prefab.height) / 2;
+    prefab.stamp(map, x, y)
+}
@@ -1,34 +1,58 @@
 use specs::prelude::*;
-use super::{CombatStats, WantsToMelee, Name, SufferDamage, GameLog};
+use super::{CombatStats, WantsToMelee, Name, SufferDamage, GameLog, MeleePowerBonus, DefenseBonus, Equipped, InflictsStatus, StatusEffects, LastAttacker};
 
 pub struct MeleeCombatSystem {}
 
 impl<'a> System<'a> for MeleeCombatSystem {
+    #[allow(clippy::type_complexity)]
     type SystemData = ( Entities<'a>,
                         WriteExpect<'a, GameLog>,
                         WriteStorage<'a, WantsToMelee>,
                         ReadStorage<'a, Name>,
                         ReadStorage<'a, CombatStats>,
-                        WriteStorage<'a, SufferDamage>
+                        WriteStorage<'a, SufferDamage>,
+                        ReadStorage<'a, MeleePowerBonus>,
+                        ReadStorage<'a, DefenseBonus>,
+                        ReadStorage<'a, Equipped>,
+                        ReadStorage<'a, InflictsStatus>,
+                        WriteStorage<'a, StatusEffects>,
+                        WriteStorage<'a, LastAttacker>,
                       );
 
     fn run(&mut self, data : Self::SystemData) {
-        let (entities, mut log, mut wants_melee, names, combat_stats, mut inflict_damage) = data;
+        let (entities, mut log, mut wants_melee, names, combat_stats, mut inflict_damage, melee_power_bonuses, defense_bonuses, equipped, inflicts_status, mut statuses, mut last_attackers) = data;
 
         // Process round of hits
-        for (_entity, wants_melee, name, stats) in (&entities, &wants_melee, &names, &combat_stats).join() {
+        for (entity, wants_melee, name, stats) in (&entities, &wants_melee, &names, &combat_stats).join() {
             if stats.hp > 0 {
+                let offensive_bonus: i32 = (&melee_power_bonuses, &equipped).join()
+                    .filter(|(_bonus, worn)| worn.owner == entity)
+                    .map(|(bonus, _worn)| bonus.power)
+                    .sum();
+
                 let target_stats = combat_stats.get(wants_melee.target).unwrap();
 
                 if target_stats.hp > 0 {
                     let target_name = names.get(wants_melee.target).unwrap();
 
-                    let damage = i32::max(0, stats.power - target_stats.defense);
+                    let defensive_bonus: i32 = (&defense_bonuses, &equipped).join()
+                        .filter(|(_bonus, worn)| worn.owner == wants_melee.target)
+                        .map(|(bonus, _worn)| bonus.defense)
+                        .sum();
+
+                    let damage = i32::max(0, (stats.power + offensive_bonus) - (target_stats.defense + defensive_bonus));
                     if damage == 0 {
-                        log.info(format!("{} is unable to hurt {}", &name.name, &target_name.name));
+                        log.combat(format!("{} is unable to hurt {}", &name.name, &target_name.name));
                     } else {
-                        log.info(format!("{} hits {}, for {} hp.", &name.name, &target_name.name, damage));
-                        SufferDamage::new_damage(&mut inflict_damage, wants_melee.target, damage);
+                        log.combat(format!("{} hits {}, for {} hp.", &name.name, &target_name.name, damage));
+                        SufferDamage::new_damage(&mut inflict_damage, wants_melee.target, damage, &name.name);
+                        last_attackers.insert(wants_melee.target, LastAttacker{ attacker: entity }).expect("Unable to insert attacker");
+
+                        // Venomous bites and the like.
+                        if let Some(inflicts) = inflicts_status.get(entity) {
+                            StatusEffects::add(&mut statuses, wants_melee.target, inflicts.effect);
+                            log.combat(format!("{} is {}.", &target_name.name, inflicts.effect.kind.name()));
+                        }
                     }
                 }
             }
@@ -2,66 +2,249 @@ use specs::prelude::*;
 use crate::RunState;
 use crate::components::*;
 use crate::map::Map;
-use rltk::{Point};
+use crate::chase_map::ChaseMap;
+use crate::lighting_system::can_make_out;
+use rltk::{Point,RandomNumberGenerator,DistanceAlg};
+
+/// What a monster knows this turn, which is everything `think` needs to make up its mind.
+pub struct Perception {
+    pub position: Point,
+    pub player: Point,
+    pub can_see_player: bool,
+    /// Another monster has seen the player and shouted about it.
+    pub alerted: bool,
+    pub hp_percent: i32,
+    /// It has a ranged weapon. Without one, `attack_range` and `keep_distance` count for nothing.
+    pub can_shoot: bool,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum AiDecision {
+    Attack,
+    MoveTowards(Point),
+    MoveAwayFrom(Point),
+    Wander,
+    Stay,
+}
+
+/// Decide what a monster does this turn, updating its brain. No world access, so it can be tested on its own.
+pub fn think(brain: &mut MonsterBrain, behaviour: &AiBehaviour, perception: &Perception) -> AiDecision {
+    let player = perception.player;
+    let distance = DistanceAlg::Pythagoras.distance2d(perception.position, player);
+
+    if perception.can_see_player || perception.alerted {
+        brain.last_known_player = Some(player);
+    }
+
+    if perception.can_see_player && perception.hp_percent <= behaviour.flee_at_hp_percent {
+        brain.state = AiState::Fleeing;
+        return AiDecision::MoveAwayFrom(player);
+    }
+    if brain.state == AiState::Fleeing {
+        // Out of sight, so it's safe. Lie low until something else comes up.
+        brain.state = AiState::Idle;
+        brain.last_known_player = None;
+        return AiDecision::Stay;
+    }
+
+    if perception.can_see_player {
+        brain.state = AiState::Chasing;
+        if perception.can_shoot && distance >= 1.5 && distance < behaviour.keep_distance as f32 {
+            return AiDecision::MoveAwayFrom(player);
+        }
+        let reach = if perception.can_shoot { behaviour.attack_range as f32 + 0.5 } else { 1.5 };
+        if distance < reach {
+            return AiDecision::Attack;
+        }
+        return AiDecision::MoveTowards(player);
+    }
+
+    if perception.alerted {
+        brain.state = AiState::Chasing;
+    }
+    if brain.state == AiState::Chasing {
+        match brain.last_known_player {
+            Some(target) if target != perception.position => return AiDecision::MoveTowards(target),
+            _ => {
+                // Got there and nobody's around.
+                brain.last_known_player = None;
+            }
+        }
+    }
+
+    if behaviour.wanders {
+        brain.state = AiState::Wandering;
+        AiDecision::Wander
+    } else {
+        brain.state = AiState::Idle;
+        AiDecision::Stay
+    }
+}
 
 pub struct MonsterAI {}
 
 impl<'a> System<'a> for MonsterAI {
+    #[allow(clippy::type_complexity)]
     type SystemData = ( WriteExpect<'a, Map>,
                         ReadExpect<'a, Point>,
                         ReadExpect<'a, Entity>,
                         ReadExpect<'a, RunState>,
+                        WriteExpect<'a, RandomNumberGenerator>,
                         Entities<'a>,
                         WriteStorage<'a, Viewshed>,
                         ReadStorage<'a, Monster>,
                         WriteStorage<'a, Position>,
                         WriteStorage<'a, WantsToMelee>,
-                        WriteStorage<'a, Confusion>,
+                        ReadStorage<'a, StatusEffects>,
+                        WriteStorage<'a, MonsterBrain>,
+                        ReadStorage<'a, AiBehaviour>,
+                        ReadStorage<'a, CombatStats>,
+                        ReadStorage<'a, MyTurn>,
+                        ReadStorage<'a, RangedWeapon>,
+                        WriteStorage<'a, WantsToShoot>,
+                        WriteStorage<'a, EntityMoved>,
+                        WriteExpect<'a, ChaseMap>,
+                        WriteStorage<'a, SpecialAttacks>,
+                        WriteStorage<'a, WantsToUseSpecial>,
                     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut map, player_pos, player_entity, runstate, entities, mut viewshed, monster, mut position, mut wants_to_melee, mut confused) = data;
+        let (mut map, player_pos, player_entity, runstate, mut rng, entities, mut viewshed, monster, mut position,
+            mut wants_to_melee, statuses, mut brains, behaviours, combat_stats, turns, ranged_weapons, mut wants_to_shoot,
+            mut entity_moved, mut chase_map, mut special_attacks, mut wants_special) = data;
 
-        if *runstate != RunState::MonsterTurn {
-            // No thinking when its not your turn!
+        if *runstate != RunState::Ticking {
+            // Monsters only think on the clock.
             return;
         }
 
-        for (entity, viewshed,_monster, pos) in (&entities, &mut viewshed, &monster, &mut position).join() {
-            let mut can_act = true;
-            let is_confused = confused.get_mut(entity);
-            if let Some(i_am_confused) = is_confused {
-                i_am_confused.turns -= 1;
-                if i_am_confused.turns < 1 {
-                    confused.remove(entity);
-                }
-                can_act = false;
+        // One map of the way to the player for everybody, rather than a path each.
+        chase_map.update(&map, *player_pos);
+
+        // Anyone who can see the player shouts to those around them.
+        let mut shouts: Vec<(Point, i32)> = Vec::new();
+        for (entity, viewshed, _monster, pos) in (&entities, &viewshed, &monster, &position).join() {
+            let radius = behaviours.get(entity).map_or(0, |b| b.alert_radius);
+            if radius > 0 && sees_player(&map, viewshed, Point::new(pos.x, pos.y), *player_pos) {
+                shouts.push((Point::new(pos.x, pos.y), radius));
             }
+        }
 
-            if can_act {
-                let distance = rltk::DistanceAlg::Pythagoras.distance2d(Point::new(pos.x, pos.y), *player_pos);
-                if distance < 1.5 {
-                    wants_to_melee.insert(entity, WantsToMelee{ target: *player_entity }).expect("Unable to attack!");
-                } else if viewshed.visible_tiles.contains(&*player_pos) {
-                    let path = rltk::a_star_search(
-                        map.xy_idx(pos.x, pos.y),
-                        map.xy_idx(player_pos.x, player_pos.y),
-                        &mut *map,
-                    );
-    
-                    if path.success && path.steps.len() > 1 {
-                        let idx = map.xy_idx(pos.x, pos.y);
-                        map.blocked_tiles[idx] = false;
-    
-                        pos.x = path.steps[1] as i32 % map.width;
-                        pos.y = path.steps[1] as i32 / map.width;
-                        viewshed.dirty = true;
-    
-                        let new_idx = map.xy_idx(pos.x, pos.y);
-                        map.blocked_tiles[new_idx] = true;
+        let mut opened_door = false;
+
+        // No thinking when its not your turn!
+        for (entity, viewshed, _monster, pos, brain, _turn) in (&entities, &mut viewshed, &monster, &mut position, &mut brains, &turns).join() {
+            let behaviour = behaviours.get(entity).cloned().unwrap_or_default();
+            let confused = statuses.get(entity).is_some_and(|s| s.has(StatusEffectKind::Confusion));
+            let hp_percent = combat_stats.get(entity).map_or(100, |stats| stats.hp * 100 / i32::max(1, stats.max_hp));
+
+            if let Some(specials) = special_attacks.get_mut(entity) {
+                specials.recharge();
+            }
+
+            let here = Point::new(pos.x, pos.y);
+            let destination = if confused {
+                // Stagger about, too dazed to pick a fight.
+                Some(random_step(&mut rng, here))
+            } else {
+                let perception = Perception {
+                    position: here,
+                    player: *player_pos,
+                    can_see_player: sees_player(&map, viewshed, here, *player_pos),
+                    alerted: shouts.iter().any(|(from, radius)| {
+                        *from != here && DistanceAlg::Pythagoras.distance2d(*from, here) <= *radius as f32
+                    }),
+                    hp_percent,
+                    can_shoot: ranged_weapons.get(entity).is_some(),
+                };
+
+                let decision = think(brain, &behaviour, &perception);
+
+                // Closing in for a fight, it uses a special attack instead if one is ready and in range.
+                let special = match decision {
+                    AiDecision::Attack | AiDecision::MoveTowards(_) if perception.can_see_player => {
+                        let distance = DistanceAlg::Pythagoras.distance2d(here, *player_pos);
+                        special_attacks.get(entity).and_then(|specials| specials.ready(distance))
                     }
+                    _ => None,
+                };
+
+                if let Some(attack) = special {
+                    wants_special.insert(entity, WantsToUseSpecial{ target: *player_entity, attack }).expect("Unable to use special attack!");
+                    None
+                } else {
+                    match decision {
+                        AiDecision::Attack => {
+                            let adjacent = DistanceAlg::Pythagoras.distance2d(here, *player_pos) < 1.5;
+                            if !adjacent && ranged_weapons.get(entity).is_some() {
+                                wants_to_shoot.insert(entity, WantsToShoot{ target: *player_pos, ammo: None }).expect("Unable to shoot!");
+                            } else {
+                                wants_to_melee.insert(entity, WantsToMelee{ target: *player_entity }).expect("Unable to attack!");
+                            }
+                            None
+                        }
+                        AiDecision::MoveTowards(target) if target == *player_pos => chase_map.step_towards(&map, here),
+                        // Heading for where the player was last seen, which the chase map doesn't know about.
+                        AiDecision::MoveTowards(target) => step_towards(&map, here, target),
+                        // The only thing monsters run from is the player.
+                        AiDecision::MoveAwayFrom(_threat) => chase_map.step_away(&map, here),
+                        AiDecision::Wander => Some(random_step(&mut rng, here)),
+                        AiDecision::Stay => None,
+                    }
+                }
+            };
+
+            if let Some(destination) = destination {
+                if !map.point_in_map(&destination) {
+                    continue;
                 }
+                let new_idx = map.xy_idx(destination.x, destination.y);
+                if map.open_door(new_idx) {
+                    // That takes the turn, and opens up a new way through for everyone after.
+                    opened_door = true;
+                    chase_map.update(&map, *player_pos);
+                    continue;
+                }
+                if map.blocked_tiles[new_idx] {
+                    continue;
+                }
+
+                let idx = map.xy_idx(pos.x, pos.y);
+                map.blocked_tiles[idx] = false;
+
+                pos.x = destination.x;
+                pos.y = destination.y;
+                viewshed.dirty = true;
+
+                map.blocked_tiles[new_idx] = true;
+                entity_moved.insert(entity, EntityMoved{}).expect("Unable to insert marker");
+            }
+        }
+
+        if opened_door {
+            // Everyone can see through the doorway now.
+            for viewshed in (&mut viewshed).join() {
+                viewshed.dirty = true;
             }
         }
     }
 }
+
+/// The player is in sight, and either in the light or close enough to pick out in the dark.
+fn sees_player(map: &Map, viewshed: &Viewshed, here: Point, player: Point) -> bool {
+    viewshed.visible_tiles.contains(&player) && can_make_out(map, here, player)
+}
+
+/// First step of the A* path from `from` to `to`.
+fn step_towards(map: &Map, from: Point, to: Point) -> Option<Point> {
+    let path = rltk::a_star_search(map.xy_idx(from.x, from.y), map.xy_idx(to.x, to.y), map);
+    if path.success && path.steps.len() > 1 {
+        Some(Point::new(path.steps[1] as i32 % map.width, path.steps[1] as i32 / map.width))
+    } else {
+        None
+    }
+}
+
+fn random_step(rng: &mut RandomNumberGenerator, from: Point) -> Point {
+    Point::new(from.x + rng.range(-1, 2), from.y + rng.range(-1, 2))
+}
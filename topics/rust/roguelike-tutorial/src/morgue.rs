@@ -0,0 +1,210 @@
+use specs::prelude::*;
+use serde::{Serialize,Deserialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::time::{SystemTime,UNIX_EPOCH};
+use crate::components::*;
+use crate::gamelog::GameLog;
+use crate::gui::{self,InventoryView};
+use crate::inventory_system;
+use crate::map::Map;
+use crate::saveload_system;
+use crate::TurnCount;
+
+const MORGUE_DIR: &str = "./morgue";
+const HIGH_SCORES_FILE_NAME: &str = "./morgue/highscores.json";
+
+/// How many runs the high score table remembers.
+pub const MAX_HIGH_SCORES: usize = 10;
+/// How much of the game log goes in a morgue file.
+pub const MORGUE_LOG_LINES: usize = 20;
+/// Winning is worth this much on top of everything else.
+pub const VICTORY_BONUS: i32 = 1000;
+
+/// What happened over the course of a run, for the morgue file. Saved with the game.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct RunStats {
+    /// The deepest level the player has been to.
+    pub deepest: i32,
+    /// What the player has killed, by name.
+    pub kills: BTreeMap<String, i32>,
+    /// Consumables the player has used up, by name.
+    pub items_used: BTreeMap<String, i32>,
+    /// What landed the killing blow on the player, once something has.
+    pub cause_of_death: Option<String>,
+}
+
+impl RunStats {
+    pub fn record_kill(&mut self, name: &str) {
+        *self.kills.entry(name.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn record_item_used(&mut self, name: &str) {
+        *self.items_used.entry(name.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn reached_depth(&mut self, depth: i32) {
+        self.deepest = i32::max(self.deepest, depth);
+    }
+
+    pub fn total_kills(&self) -> i32 {
+        self.kills.values().sum()
+    }
+}
+
+/// One finished run on the high score table.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct HighScore {
+    pub name: String,
+    pub score: i32,
+    pub depth: i32,
+    pub turns: i32,
+    pub cause: String,
+    /// Seconds since the unix epoch.
+    pub timestamp: u64,
+    /// Got the amulet, rather than dying. Tables from before there was a way to win leave it out.
+    #[serde(default)]
+    pub won: bool,
+}
+
+impl HighScore {
+    /// How the run ended, for the high score table.
+    pub fn ending(&self) -> String {
+        if self.won {
+            "won!".to_string()
+        } else {
+            format!("killed by {}", self.cause)
+        }
+    }
+}
+
+/// 100 points for every level reached, 10 for every kill, and one for every gold piece still in the wallet.
+pub fn score(depth: i32, kills: i32, gold: i32) -> i32 {
+    depth * 100 + kills * 10 + gold
+}
+
+/// The current run as a high score entry, dated `timestamp`.
+pub fn high_score_for_run(ecs: &World, timestamp: u64) -> HighScore {
+    let player_entity = ecs.fetch::<Entity>();
+    let stats = ecs.fetch::<RunStats>();
+    let depth = deepest(ecs);
+    let gold = ecs.read_storage::<Wallet>().get(*player_entity).map_or(0, |wallet| wallet.gold);
+    let won = inventory_system::player_has_won(ecs);
+    let bonus = if won { VICTORY_BONUS } else { 0 };
+
+    HighScore {
+        name: ecs.read_storage::<Name>().get(*player_entity).map_or("Player".to_string(), |n| n.name.clone()),
+        score: score(depth, stats.total_kills(), gold) + bonus,
+        depth,
+        turns: ecs.fetch::<TurnCount>().0,
+        cause: if won { "victory".to_string() } else { cause_of_death(&stats) },
+        timestamp,
+        won,
+    }
+}
+
+/// Put `entry` on the table, best first, dropping whatever falls off the bottom.
+/// Returns where it ranks, or None if it didn't make the cut.
+pub fn add_high_score(scores: &mut Vec<HighScore>, entry: HighScore) -> Option<usize> {
+    // Ties go to whoever got there first.
+    let rank = scores.iter().position(|existing| existing.score < entry.score).unwrap_or(scores.len());
+    if rank >= MAX_HIGH_SCORES {
+        return None;
+    }
+    scores.insert(rank, entry);
+    scores.truncate(MAX_HIGH_SCORES);
+    Some(rank)
+}
+
+/// The high score table from earlier runs. Empty if there isn't one yet, or it can't be read.
+pub fn load_high_scores() -> Vec<HighScore> {
+    fs::read_to_string(HIGH_SCORES_FILE_NAME).ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+fn save_high_scores(scores: &[HighScore]) -> io::Result<()> {
+    fs::create_dir_all(MORGUE_DIR)?;
+    fs::write(HIGH_SCORES_FILE_NAME, serde_json::to_string_pretty(scores)?)
+}
+
+fn deepest(ecs: &World) -> i32 {
+    i32::max(ecs.fetch::<RunStats>().deepest, ecs.fetch::<Map>().depth)
+}
+
+fn cause_of_death(stats: &RunStats) -> String {
+    stats.cause_of_death.clone().unwrap_or_else(|| "unknown causes".to_string())
+}
+
+/// The story of the run, for the player to look back on.
+pub fn morgue_text(ecs: &World, timestamp: u64) -> String {
+    let entry = high_score_for_run(ecs, timestamp);
+    let stats = ecs.fetch::<RunStats>();
+
+    let mut text = String::new();
+    match inventory_system::goal_item_carried(ecs) {
+        Some(goal) => {
+            text += &format!("{} won on {}\n\n", entry.name, saveload_system::format_timestamp(timestamp));
+            text += &format!("Took the {} on depth {}.\n", goal, ecs.fetch::<Map>().depth);
+        }
+        None => {
+            text += &format!("{} died on {}\n\n", entry.name, saveload_system::format_timestamp(timestamp));
+            text += &format!("Killed by {}, on depth {}.\n", entry.cause, ecs.fetch::<Map>().depth);
+        }
+    }
+    text += &format!("Deepest level reached: {}\n", entry.depth);
+    text += &format!("Turns taken: {}\n", entry.turns);
+    text += &format!("Score: {}\n", entry.score);
+
+    text += &format!("\nKills ({}):\n", stats.total_kills());
+    if stats.kills.is_empty() {
+        text += "  Nothing\n";
+    }
+    for (name, count) in stats.kills.iter() {
+        text += &format!("  {} x{}\n", name, count);
+    }
+
+    text += "\nItems used:\n";
+    if stats.items_used.is_empty() {
+        text += "  Nothing\n";
+    }
+    for (name, count) in stats.items_used.iter() {
+        text += &format!("  {} x{}\n", name, count);
+    }
+
+    text += "\nInventory:\n";
+    let rows = gui::inventory_rows(ecs, &InventoryView::default(), true);
+    if rows.is_empty() {
+        text += "  Nothing\n";
+    }
+    for row in rows.iter() {
+        let worn = if row.worn { " (worn)" } else { "" };
+        text += &format!("  {}{}\n", row.label, worn);
+    }
+
+    text += "\nLast messages:\n";
+    let log = ecs.fetch::<GameLog>();
+    let entries = log.entries();
+    for entry in entries.iter().skip(entries.len().saturating_sub(MORGUE_LOG_LINES)) {
+        text += &format!("  {}\n", entry.display());
+    }
+
+    text
+}
+
+/// Write the morgue file for a run that's just ended, won or lost, and put it on the high score table.
+/// Returns where the morgue file went, and the table as it now stands.
+pub fn record_run(ecs: &World) -> io::Result<(String, Vec<HighScore>)> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+
+    fs::create_dir_all(MORGUE_DIR)?;
+    let path = format!("{}/morgue-{}.txt", MORGUE_DIR, timestamp);
+    fs::write(&path, morgue_text(ecs, timestamp))?;
+
+    let mut scores = load_high_scores();
+    add_high_score(&mut scores, high_score_for_run(ecs, timestamp));
+    save_high_scores(&scores)?;
+
+    Ok((path, scores))
+}
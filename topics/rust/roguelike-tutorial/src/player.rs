@@ -1,12 +1,22 @@
-use rltk::{Rltk,VirtualKeyCode,Point};
+use rltk::{Rltk,Point};
 use specs::prelude::*;
 use std::cmp::{min,max};
 use crate::gamelog::GameLog;
 use crate::{State,RunState};
 use crate::components::*;
 use crate::map::*;
+use crate::replay::{PlayerAction,ReplayRecorder};
+use crate::ranged_combat_system::shooting_weapon;
+use crate::keybindings::{Command,Keybindings};
+use crate::travel::{TravelGoal,start_travel};
+use crate::gui;
+use crate::shop;
 
 pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) {
+    if try_open_door(delta_x, delta_y, ecs) {
+        return;
+    }
+
     let mut positions = ecs.write_storage::<Position>();
     let mut players = ecs.write_storage::<Player>();
     let mut viewsheds = ecs.write_storage::<Viewshed>();
@@ -14,6 +24,7 @@ pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) {
     let combat_stats = ecs.read_storage::<CombatStats>();
     let map = ecs.fetch::<Map>();
     let mut wants_to_melee = ecs.write_storage::<WantsToMelee>();
+    let mut entity_moved = ecs.write_storage::<EntityMoved>();
 
     for (entity, _player, pos, viewshed) in (&entities, &mut players, &mut positions, &mut viewsheds).join() {
         let destination_idx = map.xy_idx(pos.x + delta_x, pos.y + delta_y);
@@ -36,6 +47,7 @@ pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) {
             pos.y = min(map.height-1, max(0, pos.y + delta_y));
 
             viewshed.dirty = true;
+            entity_moved.insert(entity, EntityMoved{}).expect("Unable to insert marker");
             let mut ppos = ecs.write_resource::<Point>();
             ppos.x = pos.x;
             ppos.y = pos.y;
@@ -43,36 +55,263 @@ pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) {
     }
 }
 
+/// Walking into a door opens it, using up a key if it's locked. Returns true if there was a door there.
+fn try_open_door(delta_x: i32, delta_y: i32, ecs: &mut World) -> bool {
+    let player_pos = *ecs.fetch::<Point>();
+    let player_entity = *ecs.fetch::<Entity>();
+    let mut map = ecs.fetch_mut::<Map>();
+    let mut gamelog = ecs.fetch_mut::<GameLog>();
+    let idx = map.xy_idx(player_pos.x + delta_x, player_pos.y + delta_y);
+
+    match map.tiles[idx] {
+        TileType::Door => {
+            map.open_door(idx);
+            gamelog.system("You open the door.".to_string());
+        }
+        TileType::LockedDoor => {
+            let entities = ecs.entities();
+            let keys = ecs.read_storage::<Key>();
+            let backpack = ecs.read_storage::<InBackpack>();
+            let names = ecs.read_storage::<Name>();
+            let key = (&entities, &keys, &backpack).join()
+                .find(|(_key, _k, pack)| pack.owner == player_entity)
+                .map(|(key, _k, _pack)| key);
+
+            match key {
+                None => {
+                    gamelog.system("The door is locked.".to_string());
+                    return true;
+                }
+                Some(key) => {
+                    map.unlock_door(idx);
+                    let key_name = names.get(key).map_or("key".to_string(), |n| n.name.clone());
+                    gamelog.item(format!("You unlock the door with the {}.", key_name));
+                    entities.delete(key).expect("Delete failed!");
+                }
+            }
+        }
+        _ => return false,
+    }
+
+    // Opening up a doorway changes what everyone can see.
+    for viewshed in (&mut ecs.write_storage::<Viewshed>()).join() {
+        viewshed.dirty = true;
+    }
+    true
+}
+
 pub fn player_input(gs: &mut State, ctx: &mut Rltk) -> RunState {
-    match ctx.key {
+    if let Some(target) = gui::clicked_map_tile(&gs.ecs, ctx) {
+        return start_travel(gs, TravelGoal::To(target));
+    }
+
+    let command = match ctx.key.and_then(|key| gs.ecs.fetch::<Keybindings>().command(key)) {
         None => { return RunState::AwaitingInput },
-        Some(key) => match key {
-            VirtualKeyCode::Left => try_move_player(-1, 0, &mut gs.ecs),
-            VirtualKeyCode::Right => try_move_player(1, 0, &mut gs.ecs),
-            VirtualKeyCode::Up => try_move_player(0, -1, &mut gs.ecs),
-            VirtualKeyCode::Down => try_move_player(0, 1, &mut gs.ecs),
-            VirtualKeyCode::I => return RunState::ShowInventory,
-            VirtualKeyCode::D => return RunState::ShowDropItem,
-            VirtualKeyCode::Escape => return RunState::SaveGame,
-            VirtualKeyCode::Period => {
-                if try_next_level(&mut gs.ecs) {
-                    return RunState::NextLevel;
-                }
+        Some(command) => command,
+    };
+
+    let action = match command {
+        Command::MoveNorth | Command::MoveSouth | Command::MoveEast | Command::MoveWest |
+        Command::MoveNorthEast | Command::MoveNorthWest | Command::MoveSouthEast | Command::MoveSouthWest => {
+            let (dx, dy) = command.direction().expect("Move without a direction");
+            PlayerAction::Move{ dx, dy }
+        }
+        Command::Inventory => return RunState::ShowInventory{ view: gui::InventoryView::default() },
+        Command::Drop => return RunState::ShowDropItem{ view: gui::InventoryView::default() },
+        Command::CharacterSheet => return RunState::CharacterSheet,
+        Command::Messages => return RunState::LogHistory{ offset: 0, filter: None },
+        Command::Fire => return start_firing(&mut gs.ecs, None),
+        Command::SaveGame => return RunState::SaveGame,
+        Command::Descend => PlayerAction::Descend,
+        Command::Ascend => PlayerAction::Ascend,
+        Command::ToggleDebug => {
+            gs.debug_mode = !gs.debug_mode;
+            return RunState::AwaitingInput;
+        }
+        Command::PickUp => PlayerAction::PickUp,
+        Command::Wait => PlayerAction::SkipTurn,
+        Command::AutoExplore => return start_travel(gs, TravelGoal::Explore),
+        Command::Search => PlayerAction::Search,
+        Command::Quit => {
+            ctx.quit();
+            return RunState::AwaitingInput;
+        }
+    };
+
+    perform_action(&mut gs.ecs, action)
+}
+
+/// Pick something to shoot and go to targeting. Without `ammo`, the first stack in the backpack that can be fired.
+pub fn start_firing(ecs: &mut World, ammo: Option<Entity>) -> RunState {
+    let player_entity = *ecs.fetch::<Entity>();
+    let entities = ecs.entities();
+    let backpack = ecs.read_storage::<InBackpack>();
+    let ammo_stacks = ecs.read_storage::<Ammo>();
+    let ranged_weapons = ecs.read_storage::<RangedWeapon>();
+    let equipped = ecs.read_storage::<Equipped>();
+
+    let shot = (&entities, &ammo_stacks, &backpack).join()
+        .filter(|(stack, _ammo, pack)| pack.owner == player_entity && ammo.is_none_or(|chosen| chosen == *stack))
+        .find_map(|(stack, _ammo, _pack)| {
+            shooting_weapon(&entities, &ranged_weapons, &equipped, player_entity, Some(stack)).map(|weapon| (stack, weapon.range))
+        });
+
+    match shot {
+        Some((stack, range)) => RunState::ShowFireTargeting{ range, ammo: stack },
+        None => {
+            let message = if ammo.is_some() { "You have nothing to fire that with." } else { "You have nothing to fire or throw." };
+            ecs.fetch_mut::<GameLog>().system(message.to_string());
+            RunState::AwaitingInput
+        }
+    }
+}
+
+/// The player has initiative: a paralysed player just loses the turn, otherwise spend any level ups first,
+/// then wait for input.
+pub fn begin_player_turn(ecs: &mut World) -> RunState {
+    let player_entity = *ecs.fetch::<Entity>();
+    let paralysed = ecs.read_storage::<StatusEffects>().get(player_entity).is_some_and(|s| s.has(StatusEffectKind::Paralysis));
+    if paralysed {
+        ecs.fetch_mut::<GameLog>().system("You are paralysed and can't move!".to_string());
+        return RunState::PlayerTurn;
+    }
+
+    let pending_boosts = ecs.read_storage::<Experience>().get(player_entity).map_or(0, |xp| xp.pending_boosts);
+    if pending_boosts > 0 {
+        RunState::LevelUp
+    } else {
+        RunState::AwaitingInput
+    }
+}
+
+/// Carry out the player's decision for this turn and record it to the replay log.
+/// Live input and headless replays both go through here, so they can't drift apart.
+pub fn perform_action(ecs: &mut World, action: PlayerAction) -> RunState {
+    ecs.fetch_mut::<ReplayRecorder>().record(&action);
+
+    match action {
+        PlayerAction::Move{ dx, dy } => {
+            let (dx, dy) = stagger_if_confused(ecs, dx, dy);
+            if let Some(vendor) = vendor_in_the_way(ecs, dx, dy) {
+                return RunState::ConfirmAttack{ target: vendor };
+            }
+            try_move_player(dx, dy, ecs)
+        }
+        PlayerAction::Attack{ target } => {
+            let player_entity = *ecs.fetch::<Entity>();
+            let target = ecs.entities().entity(target);
+            let mut intent = ecs.write_storage::<WantsToMelee>();
+            intent.insert(player_entity, WantsToMelee{ target }).expect("Unable to insert intent");
+        }
+        PlayerAction::Buy{ vendor, item } => {
+            let vendor = ecs.entities().entity(vendor);
+            let item = ecs.entities().entity(item);
+            shop::buy(ecs, vendor, item);
+            return RunState::Shop{ vendor, mode: gui::ShopMode::Buy };
+        }
+        PlayerAction::Sell{ vendor, item } => {
+            let vendor = ecs.entities().entity(vendor);
+            let item = ecs.entities().entity(item);
+            shop::sell(ecs, vendor, item);
+            return RunState::Shop{ vendor, mode: gui::ShopMode::Sell };
+        }
+        PlayerAction::PickUp => get_item(ecs),
+        PlayerAction::SkipTurn => return skip_turn(ecs),
+        PlayerAction::Search => search(ecs),
+        PlayerAction::Descend => {
+            if try_next_level(ecs) {
+                return RunState::NextLevel;
             }
-            VirtualKeyCode::Grave => {
-                gs.debug_mode = !gs.debug_mode;
-                return RunState::AwaitingInput;
+        }
+        PlayerAction::Ascend => {
+            if try_previous_level(ecs) {
+                return RunState::PreviousLevel;
             }
-            VirtualKeyCode::G => get_item(&mut gs.ecs),
-            VirtualKeyCode::Space => { return skip_turn(&mut gs.ecs) },    // Pass turn
-            VirtualKeyCode::Q => ctx.quit(),
-            _ => { return RunState::AwaitingInput },
+        }
+        PlayerAction::UseItem{ item, target } => {
+            let player_entity = *ecs.fetch::<Entity>();
+            let item = ecs.entities().entity(item);
+            let mut intent = ecs.write_storage::<WantsToUseItem>();
+            intent.insert(player_entity, WantsToUseItem{ item, target }).expect("Unable to insert intent");
+        }
+        PlayerAction::LevelUp{ boost } => {
+            apply_stat_boost(ecs, boost);
+            return begin_player_turn(ecs);
+        }
+        PlayerAction::Fire{ ammo, target } => {
+            let player_entity = *ecs.fetch::<Entity>();
+            let ammo = ecs.entities().entity(ammo);
+            let mut intent = ecs.write_storage::<WantsToShoot>();
+            intent.insert(player_entity, WantsToShoot{ target, ammo: Some(ammo) }).expect("Unable to insert intent");
+        }
+        PlayerAction::DropItem{ item } => {
+            let player_entity = *ecs.fetch::<Entity>();
+            let item = ecs.entities().entity(item);
+            let mut intent = ecs.write_storage::<WantsToDropItem>();
+            intent.insert(player_entity, WantsToDropItem{ item }).expect("Unable to insert intent");
         }
     }
 
     RunState::PlayerTurn
 }
 
+/// A vendor on the tile the player is about to walk into, who would get attacked if they just carried on.
+fn vendor_in_the_way(ecs: &World, dx: i32, dy: i32) -> Option<Entity> {
+    let player_pos = *ecs.fetch::<Point>();
+    let entities = ecs.entities();
+    let vendors = ecs.read_storage::<Vendor>();
+    let positions = ecs.read_storage::<Position>();
+
+    // Straight from the positions, since the map's index of who's where can lag behind a fresh spawn.
+    let destination = Point::new(player_pos.x + dx, player_pos.y + dy);
+    (&entities, &vendors, &positions).join()
+        .find(|(_entity, _vendor, pos)| pos.x == destination.x && pos.y == destination.y)
+        .map(|(entity, _vendor, _pos)| entity)
+}
+
+/// A confused player goes off in a random direction instead of the one they picked.
+fn stagger_if_confused(ecs: &mut World, dx: i32, dy: i32) -> (i32, i32) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let confused = ecs.read_storage::<StatusEffects>().get(player_entity).is_some_and(|s| s.has(StatusEffectKind::Confusion));
+    if !confused {
+        return (dx, dy);
+    }
+
+    let directions = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)];
+    let mut rng = ecs.write_resource::<rltk::RandomNumberGenerator>();
+    directions[rng.range(0, directions.len() as i32) as usize]
+}
+
+fn apply_stat_boost(ecs: &mut World, boost: StatBoost) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let mut experience = ecs.write_storage::<Experience>();
+    let mut combat_stats = ecs.write_storage::<CombatStats>();
+    let mut gamelog = ecs.fetch_mut::<GameLog>();
+
+    match experience.get_mut(player_entity) {
+        Some(xp) if xp.pending_boosts > 0 => xp.pending_boosts -= 1,
+        _ => return,
+    }
+
+    if let Some(stats) = combat_stats.get_mut(player_entity) {
+        match boost {
+            StatBoost::Strength => {
+                stats.power += 1;
+                gamelog.level("You feel stronger.".to_string());
+            }
+            StatBoost::Toughness => {
+                stats.defense += 1;
+                gamelog.level("You feel tougher.".to_string());
+            }
+            StatBoost::Vitality => {
+                stats.max_hp += 5;
+                stats.hp += 5;
+                gamelog.level("You feel healthier.".to_string());
+            }
+        }
+    }
+}
+
 fn get_item(ecs: &mut World) {
     let player_pos = ecs.fetch::<Point>();
     let player_entity = ecs.fetch::<Entity>();
@@ -89,7 +328,7 @@ fn get_item(ecs: &mut World) {
     }
 
     match target_item {
-        None => gamelog.info("There is nothing here to pick up.".to_string()),
+        None => gamelog.item("There is nothing here to pick up.".to_string()),
         Some(item) => {
             let mut pickup = ecs.write_storage::<WantsToPickupItem>();
             pickup.insert(*player_entity, WantsToPickupItem{ collected_by: *player_entity, item }).expect("Unable to insert want to pickup item");
@@ -115,6 +354,14 @@ fn skip_turn(ecs: &mut World) -> RunState {
             Some(_) => false
         });
 
+    // Resting only heals on a full stomach.
+    let hunger_clocks = ecs.read_storage::<HungerClock>();
+    if let Some(clock) = hunger_clocks.get(*player_entity) {
+        if clock.state == HungerState::Hungry || clock.state == HungerState::Starving {
+            return RunState::PlayerTurn;
+        }
+    }
+
     if can_heal {
         let mut health_components = ecs.write_storage::<CombatStats>();
         let player_hp = health_components.get_mut(*player_entity).unwrap();
@@ -124,6 +371,42 @@ fn skip_turn(ecs: &mut World) -> RunState {
     RunState::PlayerTurn
 }
 
+/// How far away searching can turn up hidden things.
+pub const SEARCH_RADIUS: f32 = 2.0;
+
+/// Look for hidden traps nearby. Each one in sight and in range is found on a roll of 3 or more on a d6.
+fn search(ecs: &mut World) {
+    let player_pos = ecs.fetch::<Point>();
+    let map = ecs.fetch::<Map>();
+    let entities = ecs.entities();
+    let positions = ecs.read_storage::<Position>();
+    let names = ecs.read_storage::<Name>();
+    let mut hidden = ecs.write_storage::<Hidden>();
+    let mut rng = ecs.write_resource::<rltk::RandomNumberGenerator>();
+    let mut gamelog = ecs.fetch_mut::<GameLog>();
+
+    let nearby: Vec<Entity> = (&entities, &hidden, &positions).join()
+        .filter(|(_entity, _hidden, pos)| {
+            map.visible_tiles[map.xy_idx(pos.x, pos.y)]
+                && rltk::DistanceAlg::Pythagoras.distance2d(*player_pos, Point::new(pos.x, pos.y)) <= SEARCH_RADIUS
+        })
+        .map(|(entity, _hidden, _pos)| entity)
+        .collect();
+
+    let mut found = false;
+    for entity in nearby.iter() {
+        if rng.roll_dice(1, 6) >= 3 {
+            hidden.remove(*entity);
+            found = true;
+            let name = names.get(*entity).map_or("something".to_string(), |n| n.name.clone());
+            gamelog.system(format!("You find a {}!", name));
+        }
+    }
+    if !found {
+        gamelog.system("You search, but find nothing.".to_string());
+    }
+}
+
 fn try_next_level(ecs: &mut World) -> bool {
     let player_pos = ecs.fetch::<Point>();
     let map = ecs.fetch::<Map>();
@@ -132,7 +415,20 @@ fn try_next_level(ecs: &mut World) -> bool {
         true
     } else {
         let mut gamelog = ecs.fetch_mut::<GameLog>();
-        gamelog.info("There is no way down from here.".to_string());
+        gamelog.system("There is no way down from here.".to_string());
+        false
+    }
+}
+
+fn try_previous_level(ecs: &mut World) -> bool {
+    let player_pos = ecs.fetch::<Point>();
+    let map = ecs.fetch::<Map>();
+    let player_idx = map.xy_idx(player_pos.x, player_pos.y);
+    if map.tiles[player_idx] == TileType::UpStairs {
+        true
+    } else {
+        let mut gamelog = ecs.fetch_mut::<GameLog>();
+        gamelog.system("There is no way up from here.".to_string());
         false
     }
 }
@@ -0,0 +1,132 @@
+use specs::prelude::*;
+use rltk::{Point,RandomNumberGenerator};
+use crate::components::*;
+use crate::gamelog::GameLog;
+use crate::map::Map;
+use crate::inventory_system::use_up_one;
+
+/// The tiles a shot from `from` to `to` passes through, in order, ending where it stops:
+/// on the first tile with something blocking in it, on the last open tile before a wall or closed door, or at `to`.
+pub fn line_of_fire(map: &Map, from: Point, to: Point) -> Vec<Point> {
+    let mut path = Vec::new();
+    for point in rltk::line2d(rltk::LineAlg::Bresenham, from, to).into_iter().filter(|p| *p != from) {
+        if !map.point_in_map(&point) {
+            break;
+        }
+        let idx = map.xy_idx(point.x, point.y);
+        if map.tiles[idx].blocks_movement() {
+            break;
+        }
+        path.push(point);
+        if map.blocked_tiles[idx] || point == to {
+            break;
+        }
+    }
+    path
+}
+
+/// Resolves `WantsToShoot`. The shot flies along the line of fire and lands on whatever blocks it first.
+/// Whether it hits depends on the shooter's power against the target's defense, and damage is
+/// the weapon's power less the target's defense.
+pub struct RangedCombatSystem {}
+
+impl<'a> System<'a> for RangedCombatSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = ( Entities<'a>,
+                        ReadExpect<'a, Map>,
+                        WriteExpect<'a, GameLog>,
+                        WriteExpect<'a, RandomNumberGenerator>,
+                        WriteStorage<'a, WantsToShoot>,
+                        ReadStorage<'a, Position>,
+                        ReadStorage<'a, Name>,
+                        ReadStorage<'a, CombatStats>,
+                        ReadStorage<'a, RangedWeapon>,
+                        WriteStorage<'a, Stackable>,
+                        ReadStorage<'a, Equipped>,
+                        ReadStorage<'a, DefenseBonus>,
+                        WriteStorage<'a, SufferDamage>,
+                        WriteStorage<'a, LastAttacker>,
+                      );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, map, mut log, mut rng, mut wants_shoot, positions, names, combat_stats, ranged_weapons,
+            mut stacks, equipped, defense_bonuses, mut inflict_damage, mut last_attackers) = data;
+
+        for (entity, shot, pos, name, stats) in (&entities, &wants_shoot, &positions, &names, &combat_stats).join() {
+            if stats.hp < 1 {
+                continue;
+            }
+
+            let weapon = match shooting_weapon(&entities, &ranged_weapons, &equipped, entity, shot.ammo) {
+                None => continue,
+                Some(weapon) => weapon,
+            };
+
+            let projectile = match shot.ammo {
+                None => "shot".to_string(),
+                Some(stack) => {
+                    let projectile = names.get(stack).map_or("shot".to_string(), |n| n.name.clone());
+                    if stacks.get(stack).is_none() {
+                        continue;
+                    }
+                    use_up_one(&entities, &mut stacks, stack);
+                    projectile
+                }
+            };
+
+            let path = line_of_fire(&map, Point::new(pos.x, pos.y), shot.target);
+            let victim = path.last()
+                .map(|impact| map.xy_idx(impact.x, impact.y))
+                .and_then(|idx| map.tile_content[idx].iter().find(|e| **e != entity && combat_stats.get(**e).is_some()))
+                .copied();
+
+            let victim = match victim {
+                None => {
+                    log.combat(format!("{}'s {} hits nothing.", &name.name, projectile));
+                    continue;
+                }
+                Some(victim) => victim,
+            };
+            let target_stats = combat_stats.get(victim).unwrap();
+            let target_name = names.get(victim).map_or("something".to_string(), |n| n.name.clone());
+
+            let defense = target_stats.defense + (&defense_bonuses, &equipped).join()
+                .filter(|(_bonus, worn)| worn.owner == victim)
+                .map(|(bonus, _worn)| bonus.defense)
+                .sum::<i32>();
+
+            // A natural 1 always misses.
+            let roll = rng.roll_dice(1, 20);
+            if roll == 1 || roll + stats.power < 10 + defense {
+                log.combat(format!("{}'s {} misses {}.", &name.name, projectile, target_name));
+                continue;
+            }
+
+            let damage = i32::max(0, weapon.power - defense);
+            if damage == 0 {
+                log.combat(format!("{}'s {} bounces off {}.", &name.name, projectile, target_name));
+            } else {
+                log.combat(format!("{}'s {} hits {}, for {} hp.", &name.name, projectile, target_name, damage));
+                SufferDamage::new_damage(&mut inflict_damage, victim, damage, &format!("{}'s {}", name.name, projectile));
+                last_attackers.insert(victim, LastAttacker{ attacker: entity }).expect("Unable to insert attacker");
+            }
+        }
+
+        wants_shoot.clear();
+    }
+}
+
+/// What `shooter` is firing with: thrown ammo is its own weapon, other ammo needs an equipped launcher,
+/// and without ammo it's a monster's own weapon.
+pub fn shooting_weapon(entities: &Entities<'_>, ranged_weapons: &ReadStorage<RangedWeapon>, equipped: &ReadStorage<Equipped>,
+                       shooter: Entity, ammo: Option<Entity>) -> Option<RangedWeapon> {
+    match ammo {
+        None => ranged_weapons.get(shooter).cloned(),
+        Some(stack) => match ranged_weapons.get(stack) {
+            Some(thrown) => Some(thrown.clone()),
+            None => (entities, ranged_weapons, equipped).join()
+                .find(|(_item, _weapon, worn)| worn.owner == shooter && worn.slot == EquipmentSlot::Ranged)
+                .map(|(_item, weapon, _worn)| weapon.clone()),
+        }
+    }
+}
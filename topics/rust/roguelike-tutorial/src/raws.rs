@@ -0,0 +1,316 @@
+use rltk::{RGB,RandomNumberGenerator};
+use specs::prelude::*;
+use specs::saveload::{SimpleMarker,MarkedBuilder};
+use serde::{Deserialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use crate::components::*;
+use crate::random_table::RandomTable;
+use crate::initiative_system;
+
+pub const RAWS_FILE_NAME: &str = "./raws/spawns.json";
+
+/// Everything that can go wrong turning the raws file into spawnable templates.
+#[derive(Debug)]
+pub enum RawsError {
+    Io(String, std::io::Error),
+    Parse(String, serde_json::Error),
+    DuplicateName(String),
+    BadGlyph { entity: String, glyph: String },
+    BadColour { entity: String, colour: String },
+    UnknownSpawn(String),
+    BadWeight { entity: String, weight: i32 },
+    BadGold { entity: String, amount: i32 },
+}
+
+impl fmt::Display for RawsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RawsError::Io(path, e) => write!(f, "unable to read {}: {}", path, e),
+            RawsError::Parse(path, e) => write!(f, "{} is not a valid raws file: {}", path, e),
+            RawsError::DuplicateName(name) => write!(f, "entity \"{}\" is defined more than once", name),
+            RawsError::BadGlyph{ entity, glyph } => write!(f, "entity \"{}\" has glyph \"{}\", expected exactly one character", entity, glyph),
+            RawsError::BadColour{ entity, colour } => write!(f, "entity \"{}\" has colour \"{}\", expected a hex colour like \"#FF00FF\"", entity, colour),
+            RawsError::UnknownSpawn(name) => write!(f, "spawn table references \"{}\", which is not a defined entity", name),
+            RawsError::BadWeight{ entity, weight } => write!(f, "spawn table gives \"{}\" a weight of {}, weights must not be negative", entity, weight),
+            RawsError::BadGold{ entity, amount } => write!(f, "entity \"{}\" drops up to {} gold, it must be at least 1", entity, amount),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RawFile {
+    entities: Vec<RawEntity>,
+    spawn_table: Vec<RawSpawn>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawEntity {
+    name: String,
+    glyph: String,
+    fg: String,
+    #[serde(default = "default_bg")]
+    bg: String,
+    render_order: i32,
+    #[serde(default)]
+    components: Vec<RawComponent>,
+}
+
+fn default_bg() -> String { "#000000".to_string() }
+
+/// One component on an entity template, tagged by its component name in the raws file.
+#[derive(Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum RawComponent {
+    Monster,
+    Vendor,
+    AiBehaviour(AiBehaviour),
+    BlocksTile,
+    Viewshed { range: i32 },
+    LightSource { range: i32 },
+    /// Energy gained per tick, see `initiative_system`. Monsters without one move at normal speed.
+    Speed { speed: i32 },
+    CombatStats { max_hp: i32, defense: i32, power: i32 },
+    GivesExperience { xp: i32 },
+    Item,
+    Consumable,
+    Key,
+    GoalItem,
+    /// A pile of 1 to `amount` gold, rolled when it spawns.
+    Gold { amount: i32 },
+    Price { gold: i32 },
+    ProvidesHealing { heal_amount: i32 },
+    ProvidesFood,
+    Ranged { range: i32 },
+    RangedWeapon { range: i32, power: i32 },
+    SpecialAttacks { attacks: Vec<SpecialAttack> },
+    Ammo,
+    /// How many are in the stack when it spawns.
+    Stackable {
+        #[serde(default = "default_stack_count")]
+        count: i32,
+    },
+    MagicItem { kind: MagicItemKind },
+    InflictsDamage { damage: i32 },
+    AreaOfEffect { radius: i32 },
+    InflictsStatus {
+        effect: StatusEffectKind,
+        turns: i32,
+        #[serde(default)]
+        magnitude: i32,
+    },
+    Hidden,
+    EntryTrigger,
+    Teleports,
+    Equippable { slot: EquipmentSlot },
+    MeleePowerBonus { power: i32 },
+    DefenseBonus { defense: i32 },
+}
+
+fn default_stack_count() -> i32 { 1 }
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSpawn {
+    name: String,
+    weight: i32,
+    #[serde(default = "default_min_depth")]
+    min_depth: i32,
+    #[serde(default)]
+    max_depth: Option<i32>,
+    #[serde(default)]
+    add_depth_to_weight: bool,
+}
+
+fn default_min_depth() -> i32 { 1 }
+
+/// A validated entity definition, ready to be stamped into the world.
+#[derive(Clone)]
+pub struct EntityTemplate {
+    pub name: String,
+    pub renderable: Renderable,
+    pub components: Vec<RawComponent>,
+}
+
+/// Entity templates and spawn weights loaded from the raws file. Lives in the ECS as a resource.
+pub struct RawMaster {
+    templates: Vec<EntityTemplate>,
+    spawns: Vec<RawSpawn>,
+}
+
+impl RawMaster {
+    pub fn load(path: &str) -> Result<RawMaster, RawsError> {
+        let data = fs::read_to_string(path).map_err(|e| RawsError::Io(path.to_string(), e))?;
+        RawMaster::from_json(path, &data)
+    }
+
+    pub fn from_json(path: &str, data: &str) -> Result<RawMaster, RawsError> {
+        let raws: RawFile = serde_json::from_str(data).map_err(|e| RawsError::Parse(path.to_string(), e))?;
+
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut templates = Vec::new();
+        for raw in raws.entities.into_iter() {
+            if !seen.insert(raw.name.clone()) {
+                return Err(RawsError::DuplicateName(raw.name));
+            }
+
+            let mut glyph_chars = raw.glyph.chars();
+            let glyph = match (glyph_chars.next(), glyph_chars.next()) {
+                (Some(c), None) => rltk::to_cp437(c),
+                _ => return Err(RawsError::BadGlyph{ entity: raw.name, glyph: raw.glyph }),
+            };
+            let fg = parse_colour(&raw.name, &raw.fg)?;
+            let bg = parse_colour(&raw.name, &raw.bg)?;
+            for component in raw.components.iter() {
+                if let RawComponent::Gold{ amount } = component {
+                    if *amount < 1 {
+                        return Err(RawsError::BadGold{ entity: raw.name, amount: *amount });
+                    }
+                }
+            }
+
+            templates.push(EntityTemplate{
+                name: raw.name,
+                renderable: Renderable{ glyph, fg, bg, render_order: raw.render_order },
+                components: raw.components,
+            });
+        }
+
+        for spawn in raws.spawn_table.iter() {
+            if !seen.contains(&spawn.name) {
+                return Err(RawsError::UnknownSpawn(spawn.name.clone()));
+            }
+            if spawn.weight < 0 {
+                return Err(RawsError::BadWeight{ entity: spawn.name.clone(), weight: spawn.weight });
+            }
+        }
+
+        Ok(RawMaster{ templates, spawns: raws.spawn_table })
+    }
+
+    pub fn template(&self, name: &str) -> Option<&EntityTemplate> {
+        self.templates.iter().find(|t| t.name == name)
+    }
+
+    /// Whether `name` is something that can be picked up, rather than a monster or a trap.
+    pub fn is_item(&self, name: &str) -> bool {
+        self.template(name).is_some_and(|t| t.components.iter().any(|c| matches!(c, RawComponent::Item)))
+    }
+
+    /// Whether a vendor would deal in it: an item with a price.
+    pub fn is_for_sale(&self, name: &str) -> bool {
+        self.template(name).is_some_and(|t| t.components.iter().any(|c| matches!(c, RawComponent::Price{ .. })))
+    }
+
+    /// The name and kind of every scroll and potion, in the order they're defined.
+    pub fn magic_items(&self) -> Vec<(String, MagicItemKind)> {
+        let mut items = Vec::new();
+        for template in self.templates.iter() {
+            for component in template.components.iter() {
+                if let RawComponent::MagicItem{ kind } = component {
+                    items.push((template.name.clone(), *kind));
+                }
+            }
+        }
+        items
+    }
+
+    /// Spawn weights for everything allowed at `depth`.
+    pub fn spawn_table(&self, depth: i32) -> RandomTable {
+        let mut table = RandomTable::new();
+        for spawn in self.spawns.iter() {
+            if depth < spawn.min_depth || spawn.max_depth.is_some_and(|max| depth > max) {
+                continue;
+            }
+
+            let mut weight = spawn.weight;
+            if spawn.add_depth_to_weight {
+                weight += depth;
+            }
+            if weight > 0 {
+                table = table.add(&spawn.name, weight);
+            }
+        }
+
+        table
+    }
+}
+
+fn parse_colour(entity: &str, colour: &str) -> Result<RGB, RawsError> {
+    RGB::from_hex(colour).map_err(|_| RawsError::BadColour{ entity: entity.to_string(), colour: colour.to_string() })
+}
+
+/// Create the entity defined in the raws under `name` at (x, y). Returns None if there is no such template.
+pub fn spawn_named_entity(ecs: &mut World, name: &str, x: i32, y: i32) -> Option<Entity> {
+    let template = {
+        let raws = ecs.fetch::<RawMaster>();
+        match raws.template(name) {
+            None => return None,
+            Some(template) => template.clone(),
+        }
+    };
+
+    // Rolled up front, the builder has the world borrowed.
+    let gold = template.components.iter().find_map(|c| match c {
+        RawComponent::Gold{ amount } => Some(ecs.write_resource::<RandomNumberGenerator>().roll_dice(1, *amount)),
+        _ => None,
+    });
+
+    let mut eb = ecs.create_entity()
+        .with(Position{ x, y })
+        .with(template.renderable)
+        .with(Name{ name: template.name.clone() });
+
+    // Everything that takes turns needs initiative.
+    let takes_turns = template.components.iter().any(|c| matches!(c, RawComponent::Monster));
+    let speed = template.components.iter().find_map(|c| match c {
+        RawComponent::Speed{ speed } => Some(*speed),
+        _ => None,
+    });
+    if takes_turns || speed.is_some() {
+        eb = eb.with(Initiative{ energy: 0, speed: speed.unwrap_or(initiative_system::NORMAL_SPEED) });
+    }
+
+    for component in template.components.iter() {
+        eb = match component {
+            RawComponent::Monster => eb.with(Monster{}).with(MonsterBrain::default()),
+            RawComponent::Vendor => eb.with(Vendor{}),
+            RawComponent::Speed{ .. } => eb,
+            RawComponent::AiBehaviour(behaviour) => eb.with(behaviour.clone()),
+            RawComponent::BlocksTile => eb.with(BlocksTile{}),
+            RawComponent::Viewshed{ range } => eb.with(Viewshed{ visible_tiles: Vec::new(), range: *range, dirty: true }),
+            RawComponent::LightSource{ range } => eb.with(LightSource{ range: *range }),
+            RawComponent::CombatStats{ max_hp, defense, power } => eb.with(CombatStats{ max_hp: *max_hp, hp: *max_hp, defense: *defense, power: *power }),
+            RawComponent::GivesExperience{ xp } => eb.with(GivesExperience{ xp: *xp }),
+            RawComponent::Item => eb.with(Item{}),
+            RawComponent::Consumable => eb.with(Consumable{}),
+            RawComponent::Key => eb.with(Key{}),
+            RawComponent::GoalItem => eb.with(GoalItem{}),
+            RawComponent::Gold{ .. } => eb.with(Gold{ amount: gold.unwrap_or(0) }),
+            RawComponent::Price{ gold } => eb.with(Price{ gold: *gold }),
+            RawComponent::ProvidesHealing{ heal_amount } => eb.with(ProvidesHealing{ heal_amount: *heal_amount }),
+            RawComponent::ProvidesFood => eb.with(ProvidesFood{}),
+            RawComponent::Ranged{ range } => eb.with(Ranged{ range: *range }),
+            RawComponent::RangedWeapon{ range, power } => eb.with(RangedWeapon{ range: *range, power: *power }),
+            RawComponent::SpecialAttacks{ attacks } => eb.with(SpecialAttacks{ attacks: attacks.clone() }),
+            RawComponent::Ammo => eb.with(Ammo{}),
+            RawComponent::Stackable{ count } => eb.with(Stackable{ count: *count }),
+            RawComponent::MagicItem{ kind } => eb.with(MagicItem{ kind: *kind }),
+            RawComponent::InflictsDamage{ damage } => eb.with(InflictsDamage{ damage: *damage }),
+            RawComponent::AreaOfEffect{ radius } => eb.with(AreaOfEffect{ radius: *radius }),
+            RawComponent::InflictsStatus{ effect, turns, magnitude } => {
+                eb.with(InflictsStatus{ effect: StatusEffect{ kind: *effect, turns: *turns, magnitude: *magnitude } })
+            }
+            RawComponent::Hidden => eb.with(Hidden{}),
+            RawComponent::EntryTrigger => eb.with(EntryTrigger{}),
+            RawComponent::Teleports => eb.with(Teleports{}),
+            RawComponent::Equippable{ slot } => eb.with(Equippable{ slot: *slot }),
+            RawComponent::MeleePowerBonus{ power } => eb.with(MeleePowerBonus{ power: *power }),
+            RawComponent::DefenseBonus{ defense } => eb.with(DefenseBonus{ defense: *defense }),
+        };
+    }
+
+    Some(eb.marked::<SimpleMarker<SerializeMe>>().build())
+}
@@ -0,0 +1,149 @@
+use serde::{Serialize,Deserialize};
+use specs::prelude::*;
+use rltk::Point;
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self,File};
+use std::hash::{Hash,Hasher};
+use std::io::Write;
+use crate::components::*;
+use crate::gamelog::GameLog;
+use crate::map::Map;
+
+pub const REPLAY_FILE_NAME: &str = "./replay.jsonl";
+
+/// Everything the player can decide to do on their turn. Recorded so a seeded run can be replayed exactly.
+/// Items are referred to by entity id, which is stable between runs with the same seed and actions.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum PlayerAction {
+    Move { dx: i32, dy: i32 },
+    PickUp,
+    SkipTurn,
+    /// Look around for hidden traps.
+    Search,
+    Descend,
+    Ascend,
+    UseItem { item: u32, target: Option<Point> },
+    DropItem { item: u32 },
+    /// Shoot or throw from the ammo stack `ammo`.
+    Fire { ammo: u32, target: Point },
+    /// Spend a level up. Doesn't take a turn.
+    LevelUp { boost: StatBoost },
+    /// Melee someone walking into them wouldn't attack (a shopkeeper, once the player has confirmed it).
+    Attack { target: u32 },
+    /// Trade with a vendor. Neither takes a turn.
+    Buy { vendor: u32, item: u32 },
+    Sell { vendor: u32, item: u32 },
+}
+
+#[derive(Serialize, Deserialize)]
+struct ReplayHeader {
+    seed: u64,
+}
+
+pub struct Replay {
+    pub seed: u64,
+    pub actions: Vec<PlayerAction>,
+}
+
+/// Appends each action to the replay file as it happens, so a crash still leaves a usable log.
+/// The first line of the file holds the seed, every line after is one action.
+pub struct ReplayRecorder {
+    file: Option<File>,
+}
+
+impl ReplayRecorder {
+    pub fn create(path: &str, seed: u64) -> ReplayRecorder {
+        let header = serde_json::to_string(&ReplayHeader{ seed }).expect("Unable to serialize replay header");
+        let file = File::create(path).and_then(|mut f| {
+            writeln!(f, "{}", header)?;
+            Ok(f)
+        });
+
+        match file {
+            Ok(file) => ReplayRecorder{ file: Some(file) },
+            Err(e) => {
+                eprintln!("Unable to record replay to {}: {}", path, e);
+                ReplayRecorder::disabled()
+            }
+        }
+    }
+
+    pub fn disabled() -> ReplayRecorder {
+        ReplayRecorder{ file: None }
+    }
+
+    pub fn record(&mut self, action: &PlayerAction) {
+        let mut failed = false;
+        if let Some(file) = self.file.as_mut() {
+            let line = serde_json::to_string(action).expect("Unable to serialize action");
+            failed = writeln!(file, "{}", line).is_err();
+        }
+
+        if failed {
+            eprintln!("Unable to write to replay file, recording stopped");
+            self.file = None;
+        }
+    }
+}
+
+pub fn load_replay(path: &str) -> Result<Replay, String> {
+    let data = fs::read_to_string(path).map_err(|e| format!("unable to read {}: {}", path, e))?;
+    let mut lines = data.lines().enumerate().filter(|(_n, line)| !line.trim().is_empty());
+
+    let header: ReplayHeader = match lines.next() {
+        None => return Err(format!("{} is empty", path)),
+        Some((_n, line)) => serde_json::from_str(line).map_err(|e| format!("{}:1 is not a replay header: {}", path, e))?,
+    };
+
+    let mut actions = Vec::new();
+    for (n, line) in lines {
+        let action = serde_json::from_str(line).map_err(|e| format!("{}:{} is not a player action: {}", path, n + 1, e))?;
+        actions.push(action);
+    }
+
+    Ok(Replay{ seed: header.seed, actions })
+}
+
+/// Hash of the parts of the world a replay should reproduce: the map, where everything is and how hurt it is,
+/// and the game log. Two runs of the same replay on the same build give the same hash. Entity ids don't count,
+/// since loading a save renumbers everything, so a loaded game hashes the same as the one that was saved.
+pub fn state_hash(ecs: &World) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    let map = ecs.fetch::<Map>();
+    map.depth.hash(&mut hasher);
+    map.tiles.hash(&mut hasher);
+    map.revealed_tiles.hash(&mut hasher);
+
+    let entities = ecs.entities();
+    let names = ecs.read_storage::<Name>();
+    let positions = ecs.read_storage::<Position>();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    let backpack = ecs.read_storage::<InBackpack>();
+    let wallets = ecs.read_storage::<Wallet>();
+    let mut entity_hashes = Vec::new();
+    for (entity, name) in (&entities, &names).join() {
+        let mut entity_hasher = DefaultHasher::new();
+        name.name.hash(&mut entity_hasher);
+        if let Some(pos) = positions.get(entity) {
+            (pos.x, pos.y).hash(&mut entity_hasher);
+        }
+        if let Some(stats) = combat_stats.get(entity) {
+            (stats.hp, stats.max_hp).hash(&mut entity_hasher);
+        }
+        if let Some(pack) = backpack.get(entity) {
+            names.get(pack.owner).map(|owner| &owner.name).hash(&mut entity_hasher);
+        }
+        if let Some(wallet) = wallets.get(entity) {
+            wallet.gold.hash(&mut entity_hasher);
+        }
+        entity_hashes.push(entity_hasher.finish());
+    }
+    entity_hashes.sort_unstable();
+    entity_hashes.hash(&mut hasher);
+
+    let log = ecs.fetch::<GameLog>();
+    log.entries().hash(&mut hasher);
+
+    hasher.finish()
+}
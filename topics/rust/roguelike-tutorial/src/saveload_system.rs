@@ -2,71 +2,458 @@ use specs::prelude::*;
 use specs::saveload::*;
 use specs::error::NoError;
 use specs::World;
-use std::fs;
+use serde::{Serialize,Deserialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self,File};
+use std::io::{BufWriter,Write};
 use std::path::Path;
+use std::time::{SystemTime,UNIX_EPOCH};
 use crate::map::Map;
 use crate::components::*;
-use crate::constants::MAPCOUNT;
+use crate::TurnCount;
+use crate::dungeon::MasterDungeonMap;
+use crate::gamelog::GameLog;
+use crate::identification::Identification;
+use crate::morgue::RunStats;
+use crate::hunger_system;
+use crate::initiative_system;
+use crate::spawner;
 
-const SAVEGAME_FILE_NAME: &str = "./savegame.json";
+const SAVE_DIR: &str = "./saves";
+
+/// Bump this whenever a saved component changes shape, and add a migration below to upgrade older saves.
+/// Components that are new since a save was made just load as empty, so adding one doesn't need a bump.
+pub const SAVE_FORMAT_VERSION: u32 = 12;
+
+pub const AUTOSAVE_SLOT: &str = "Autosave";
+pub const SAVE_SLOTS: [&str; 3] = ["Slot 1", "Slot 2", "Slot 3"];
+
+/// Upgrades, in order. `MIGRATIONS[n]` takes a version `n` save to version `n + 1`.
+const MIGRATIONS: [fn(&mut Streams); 12] = [
+    migrate_v0_to_v1, migrate_v1_to_v2, migrate_v2_to_v3, migrate_v3_to_v4, migrate_v4_to_v5, migrate_v5_to_v6,
+    migrate_v6_to_v7, migrate_v7_to_v8, migrate_v8_to_v9, migrate_v9_to_v10, migrate_v10_to_v11, migrate_v11_to_v12,
+];
+
+/// Each component's saved data, by component name.
+type Streams = HashMap<String, Value>;
+
+/// Before version 2, streams had no names and were written in a fixed order. Saves from the original game,
+/// before versioning, have this order...
+const BASELINE_STREAMS: [&str; 22] = [
+    "Position", "Renderable", "Player", "Viewshed", "Monster", "Name", "BlocksTile", "CombatStats", "SufferDamage",
+    "WantsToMelee", "Item", "Consumable", "Ranged", "InflictsDamage", "AreaOfEffect", "Confusion", "ProvidesHealing",
+    "InBackpack", "WantsToPickupItem", "WantsToUseItem", "WantsToDropItem", "SerializationHelper",
+];
+
+/// ...and once equipment was added, this one. Which it is can be told from how many streams there are.
+const POSITIONAL_STREAMS: [&str; 26] = [
+    "Position", "Renderable", "Player", "Viewshed", "Monster", "Name", "BlocksTile", "CombatStats", "SufferDamage",
+    "WantsToMelee", "Item", "Consumable", "Ranged", "InflictsDamage", "AreaOfEffect", "Confusion", "ProvidesHealing",
+    "InBackpack", "WantsToPickupItem", "WantsToUseItem", "WantsToDropItem", "Equippable", "Equipped",
+    "MeleePowerBonus", "DefenseBonus", "SerializationHelper",
+];
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    NoSave(String),
+    UnsupportedVersion(u32),
+    Corrupt(String),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "unable to access save: {}", e),
+            SaveError::Json(e) => write!(f, "save is damaged: {}", e),
+            SaveError::NoSave(slot) => write!(f, "there is no save in {}", slot),
+            SaveError::UnsupportedVersion(v) => write!(f, "save is from a newer version of the game (format {})", v),
+            SaveError::Corrupt(reason) => write!(f, "save is damaged: {}", reason),
+        }
+    }
+}
+
+impl From<std::io::Error> for SaveError {
+    fn from(e: std::io::Error) -> SaveError { SaveError::Io(e) }
+}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(e: serde_json::Error) -> SaveError { SaveError::Json(e) }
+}
+
+/// What the load menu shows about a save, without reading the whole file.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SaveMetadata {
+    pub character_name: String,
+    pub depth: i32,
+    pub turns: i32,
+    /// Seconds since the unix epoch.
+    pub timestamp: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveHeader {
+    version: u32,
+    metadata: SaveMetadata,
+}
 
 macro_rules! serialize_individually {
     ($ecs:expr, $ser:expr, $data:expr, $( $type:ty),*) => {
         $(
+        stringify!($type).serialize(&mut $ser)?;
         SerializeComponents::<NoError, SimpleMarker<SerializeMe>>::serialize(
             &( $ecs.read_storage::<$type>(), ),
             &$data.0,
             &$data.1,
             &mut $ser,
-        )
-        .unwrap();
+        )?;
         )*
     };
 }
 
 macro_rules! deserialize_individually {
-    ($ecs:expr, $de:expr, $data:expr, $( $type:ty),*) => {
+    ($ecs:expr, $streams:expr, $data:expr, $( $type:ty),*) => {
         $(
+        let stream = $streams.remove(stringify!($type)).unwrap_or_else(|| Value::Array(Vec::new()));
         DeserializeComponents::<NoError, _>::deserialize(
             &mut ( &mut $ecs.write_storage::<$type>(), ),
             &mut $data.0, // entities
             &mut $data.1, // marker
             &mut $data.2, // allocater
-            &mut $de,
-        )
-        .unwrap();
+            stream,
+        )?;
         )*
     };
 }
 
-pub fn save_game(ecs: &mut World) {
-    let mapcopy = ecs.get_mut::<Map>().unwrap().clone();
+/// The file `slot` is saved in.
+pub fn slot_path(slot: &str) -> String {
+    format!("{}/{}.json", SAVE_DIR, slot.to_lowercase().replace(' ', "-"))
+}
+
+pub fn save_game(ecs: &mut World, slot: &str) -> Result<(), SaveError> {
+    let metadata = current_metadata(ecs);
+    let mapcopy = (*ecs.fetch::<Map>()).clone();
+    let turns = ecs.fetch::<TurnCount>().0;
+    let dungeon = (*ecs.fetch::<MasterDungeonMap>()).clone();
+    let log = (*ecs.fetch::<GameLog>()).clone();
+    let identification = (*ecs.fetch::<Identification>()).clone();
+    let stats = (*ecs.fetch::<RunStats>()).clone();
     let savehelper = ecs
         .create_entity()
-        .with(SerializationHelper{ map: mapcopy })
+        .with(SerializationHelper{ map: mapcopy, turns, dungeon, log, identification, stats })
         .marked::<SimpleMarker<SerializeMe>>()
         .build();
 
-    {
-        let data = ( ecs.entities(), ecs.read_storage::<SimpleMarker<SerializeMe>>() );
+    let result = write_save(ecs, slot, metadata);
+
+    ecs.delete_entity(savehelper).expect("Crash on cleanup");
+    result
+}
+
+fn current_metadata(ecs: &World) -> SaveMetadata {
+    let player_entity = ecs.fetch::<Entity>();
+    let names = ecs.read_storage::<Name>();
+    let character_name = names.get(*player_entity).map_or("Player".to_string(), |n| n.name.clone());
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+
+    SaveMetadata {
+        character_name,
+        depth: ecs.fetch::<Map>().depth,
+        turns: ecs.fetch::<TurnCount>().0,
+        timestamp,
+    }
+}
+
+fn write_save(ecs: &World, slot: &str, metadata: SaveMetadata) -> Result<(), SaveError> {
+    fs::create_dir_all(SAVE_DIR)?;
+    let path = slot_path(slot);
 
-        let writer = fs::File::create(SAVEGAME_FILE_NAME).unwrap();
+    // Write next to the real save and swap it in at the end, so a failed save never clobbers a good one.
+    let partial_path = format!("{}.partial", path);
+    {
+        let writer = BufWriter::new(File::create(&partial_path)?);
         let mut serializer = serde_json::Serializer::new(writer);
-        serialize_individually!(ecs, serializer, data, Position, Renderable, Player, Viewshed, Monster, 
-            Name, BlocksTile, CombatStats, SufferDamage, WantsToMelee, Item, Consumable, Ranged, InflictsDamage, 
-            AreaOfEffect, Confusion, ProvidesHealing, InBackpack, WantsToPickupItem, WantsToUseItem,
-            WantsToDropItem, SerializationHelper
+
+        SaveHeader{ version: SAVE_FORMAT_VERSION, metadata }.serialize(&mut serializer)?;
+
+        let data = ( ecs.entities(), ecs.read_storage::<SimpleMarker<SerializeMe>>() );
+        serialize_individually!(ecs, serializer, data,
+            Position, OtherLevelPosition, Renderable, Player, Viewshed, LightSource, Monster, Vendor, MonsterBrain,
+            AiBehaviour, Initiative, MyTurn, Name, BlocksTile, CombatStats, SufferDamage, WantsToMelee, Item,
+            Consumable, Key, GoalItem, Gold, Price, Ranged, RangedWeapon, Ammo, Stackable, MagicItem,
+            SpecialAttacks, WantsToUseSpecial, InflictsDamage, AreaOfEffect, StatusEffects, InflictsStatus, Hidden,
+            EntryTrigger, Teleports, Experience, Wallet, GivesExperience, ProvidesHealing, InBackpack,
+            WantsToPickupItem, WantsToUseItem, WantsToDropItem, HungerClock, ProvidesFood, Equippable, Equipped,
+            MeleePowerBonus, DefenseBonus, SerializationHelper
         );
+
+        serializer.into_inner().flush()?;
     }
+    fs::rename(&partial_path, &path)?;
 
-    ecs.delete_entity(savehelper).expect("Crash on cleanup");
+    Ok(())
+}
+
+pub fn does_save_exist(slot: &str) -> bool {
+    Path::new(&slot_path(slot)).exists()
+}
+
+pub fn does_any_save_exist() -> bool {
+    does_save_exist(AUTOSAVE_SLOT) || SAVE_SLOTS.iter().any(|slot| does_save_exist(slot))
+}
+
+/// Just the header of a save. `Ok(None)` if the slot is empty.
+pub fn read_metadata(slot: &str) -> Result<Option<SaveMetadata>, SaveError> {
+    if !does_save_exist(slot) {
+        return Ok(None);
+    }
+
+    let file = File::open(slot_path(slot))?;
+    let mut stream = serde_json::Deserializer::from_reader(std::io::BufReader::new(file)).into_iter::<Value>();
+    match stream.next() {
+        None => Err(SaveError::Corrupt("file is empty".to_string())),
+        Some(first) => match parse_header(first?)? {
+            Some(header) => Ok(Some(header.metadata)),
+            None => {
+                // Saves from before metadata existed still load, we just can't describe them.
+                Ok(Some(SaveMetadata{ character_name: "Unknown".to_string(), depth: 0, turns: 0, timestamp: 0 }))
+            }
+        }
+    }
+}
+
+/// The header if `value` is one. Saves from before versioning start straight in on component data.
+fn parse_header(value: Value) -> Result<Option<SaveHeader>, SaveError> {
+    if value.is_object() && value.get("version").is_some() {
+        let header: SaveHeader = serde_json::from_value(value)?;
+        if header.version > SAVE_FORMAT_VERSION {
+            return Err(SaveError::UnsupportedVersion(header.version));
+        }
+        Ok(Some(header))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Read the component streams out of a save, upgraded to the current format.
+fn read_streams(slot: &str) -> Result<Streams, SaveError> {
+    if !does_save_exist(slot) {
+        return Err(SaveError::NoSave(slot.to_string()));
+    }
+
+    let data = fs::read_to_string(slot_path(slot))?;
+    let mut values = Vec::new();
+    for value in serde_json::Deserializer::from_str(&data).into_iter::<Value>() {
+        values.push(value?);
+    }
+    if values.is_empty() {
+        return Err(SaveError::Corrupt("file is empty".to_string()));
+    }
+
+    let version = match parse_header(values[0].clone())? {
+        None => 0,
+        Some(header) => {
+            values.remove(0);
+            header.version
+        }
+    };
+
+    let mut streams = Streams::new();
+    if version < 2 {
+        let layout: &[&str] = match values.len() {
+            n if n == BASELINE_STREAMS.len() => &BASELINE_STREAMS,
+            n if n == POSITIONAL_STREAMS.len() => &POSITIONAL_STREAMS,
+            n => return Err(SaveError::Corrupt(format!("expected {} or {} components, found {}",
+                BASELINE_STREAMS.len(), POSITIONAL_STREAMS.len(), n))),
+        };
+        for (name, stream) in layout.iter().zip(values) {
+            streams.insert(name.to_string(), stream);
+        }
+    } else {
+        for pair in values.chunks(2) {
+            match pair {
+                [Value::String(name), stream] => { streams.insert(name.clone(), stream.clone()); }
+                _ => return Err(SaveError::Corrupt("component data without a name".to_string())),
+            }
+        }
+    }
+
+    for migration in MIGRATIONS.iter().skip(version as usize) {
+        migration(&mut streams);
+    }
+
+    Ok(streams)
+}
+
+/// Version 1 added the turn count alongside the map.
+fn migrate_v0_to_v1(streams: &mut Streams) {
+    if let Some(helper_stream) = streams.get_mut("SerializationHelper") {
+        add_field_next_to(helper_stream, "map", "turns", Value::from(0));
+    }
+}
+
+/// Version 2 named the streams (handled when reading) and added the levels the player has left.
+fn migrate_v1_to_v2(streams: &mut Streams) {
+    if let Some(helper_stream) = streams.get_mut("SerializationHelper") {
+        add_field_next_to(helper_stream, "map", "dungeon", serde_json::json!({ "maps": {} }));
+    }
+}
+
+/// Version 3 replaced `Confusion` with general status effects. On a scroll it becomes `InflictsStatus`,
+/// on a monster it was confusing it becomes a `StatusEffects` entry.
+fn migrate_v2_to_v3(streams: &mut Streams) {
+    let confusion = match streams.remove("Confusion") {
+        Some(Value::Array(entries)) => entries,
+        _ => return,
+    };
+    let item_markers: Vec<Value> = match streams.get("Item") {
+        Some(Value::Array(items)) => items.iter().filter_map(|entry| entry.get("marker").cloned()).collect(),
+        _ => Vec::new(),
+    };
+
+    let mut inflicts = Vec::new();
+    let mut affected = Vec::new();
+    for entry in confusion.iter() {
+        let marker = entry.get("marker").cloned().unwrap_or(Value::Null);
+        let turns = entry.pointer("/components/0/turns").cloned().unwrap_or_else(|| Value::from(0));
+        let effect = serde_json::json!({ "kind": "Confusion", "turns": turns, "magnitude": 0 });
+        if item_markers.contains(&marker) {
+            inflicts.push(serde_json::json!({ "marker": marker, "components": [{ "effect": effect }] }));
+        } else {
+            affected.push(serde_json::json!({ "marker": marker, "components": [{ "effects": [effect] }] }));
+        }
+    }
+
+    streams.insert("InflictsStatus".to_string(), Value::Array(inflicts));
+    streams.insert("StatusEffects".to_string(), Value::Array(affected));
+}
+
+/// Version 4 added hunger. Players from older saves start it with a normal appetite.
+fn migrate_v3_to_v4(streams: &mut Streams) {
+    give_each(streams, "Player", "HungerClock", serde_json::json!({ "state": "Normal", "duration": hunger_system::NORMAL_DURATION }));
+}
+
+/// Version 5 added experience. Older characters start again from level 1.
+fn migrate_v4_to_v5(streams: &mut Streams) {
+    give_each(streams, "Player", "Experience", serde_json::json!({ "level": 1, "xp": 0, "pending_boosts": 0 }));
+}
+
+/// Version 6 gave monsters a memory. Older ones start out idle.
+fn migrate_v5_to_v6(streams: &mut Streams) {
+    give_each(streams, "Monster", "MonsterBrain", serde_json::json!({ "state": "Idle", "last_known_player": null }));
+}
+
+/// Version 7 replaced the fixed turn order with initiative. Everything starts at normal speed.
+fn migrate_v6_to_v7(streams: &mut Streams) {
+    let initiative = serde_json::json!({ "energy": 0, "speed": initiative_system::NORMAL_SPEED });
+    give_each(streams, "Player", "Initiative", initiative.clone());
+    give_each(streams, "Monster", "Initiative", initiative);
+}
+
+/// Version 8 saved the game log. Older saves start with an empty one.
+fn migrate_v7_to_v8(streams: &mut Streams) {
+    if let Some(helper_stream) = streams.get_mut("SerializationHelper") {
+        add_field_next_to(helper_stream, "map", "log", serde_json::json!({ "entries": [], "turn": 0 }));
+    }
+}
+
+/// Version 9 moved ammo counts into `Stackable`, and added item identification. Everything in an older
+/// save counts as identified, since the player knew it all by name already.
+fn migrate_v8_to_v9(streams: &mut Streams) {
+    let mut stacks = Vec::new();
+    if let Some(Value::Array(ammo)) = streams.get_mut("Ammo") {
+        for entry in ammo.iter_mut() {
+            let marker = entry.get("marker").cloned().unwrap_or(Value::Null);
+            let count = entry.pointer("/components/0/count").cloned().unwrap_or_else(|| Value::from(1));
+            stacks.push(serde_json::json!({ "marker": marker, "components": [{ "count": count }] }));
+            *entry = serde_json::json!({ "marker": marker, "components": [{}] });
+        }
+    }
+    if let Some(Value::Array(existing)) = streams.remove("Stackable") {
+        stacks.splice(0..0, existing);
+    }
+    streams.insert("Stackable".to_string(), Value::Array(stacks));
+
+    if let Some(helper_stream) = streams.get_mut("SerializationHelper") {
+        add_field_next_to(helper_stream, "map", "identification", serde_json::json!({ "aliases": {}, "known": [] }));
+    }
+}
+
+/// Version 10 gave the player a wallet. Older characters start out broke.
+fn migrate_v9_to_v10(streams: &mut Streams) {
+    give_each(streams, "Player", "Wallet", serde_json::json!({ "gold": 0 }));
+}
+
+/// Version 11 kept statistics on the run, and what last hurt everyone. Older runs count from here,
+/// except for the deepest level, which is at least the one the player is on.
+fn migrate_v10_to_v11(streams: &mut Streams) {
+    if let Some(Value::Array(helpers)) = streams.get_mut("SerializationHelper") {
+        for entry in helpers.iter_mut() {
+            let depth = entry.pointer("/components/0/map/depth").cloned().unwrap_or_else(|| Value::from(0));
+            let stats = serde_json::json!({ "deepest": depth, "kills": {}, "items_used": {}, "cause_of_death": null });
+            add_field_next_to(entry, "map", "stats", stats);
+        }
+    }
+    if let Some(damage_stream) = streams.get_mut("SufferDamage") {
+        add_field_next_to(damage_stream, "amount", "cause", Value::from(""));
+    }
+}
+
+/// Version 12 added lighting. Players from older saves are handed a torch.
+fn migrate_v11_to_v12(streams: &mut Streams) {
+    give_each(streams, "Player", "LightSource", serde_json::json!({ "range": spawner::PLAYER_TORCH_RANGE }));
+}
+
+/// Add `component` to `stream` for every entity in the `owners` stream.
+fn give_each(streams: &mut Streams, owners: &str, stream: &str, component: Value) {
+    let mut entries: Vec<Value> = match streams.get(owners) {
+        Some(Value::Array(players)) => players.iter()
+            .filter_map(|entry| entry.get("marker").cloned())
+            .map(|marker| serde_json::json!({ "marker": marker, "components": [component.clone()] }))
+            .collect(),
+        _ => Vec::new(),
+    };
+    if let Some(Value::Array(existing)) = streams.remove(stream) {
+        entries.splice(0..0, existing);
+    }
+    streams.insert(stream.to_string(), Value::Array(entries));
 }
 
-pub fn does_save_exist() -> bool {
-    Path::new(&SAVEGAME_FILE_NAME).exists()
+/// Find every object in `value` with a `sibling` field, and give it `field` if it doesn't have one.
+fn add_field_next_to(value: &mut Value, sibling: &str, field: &str, default: Value) {
+    match value {
+        Value::Object(fields) => {
+            if fields.contains_key(sibling) && !fields.contains_key(field) {
+                fields.insert(field.to_string(), default.clone());
+            }
+            for child in fields.values_mut() {
+                add_field_next_to(child, sibling, field, default.clone());
+            }
+        }
+        Value::Array(items) => {
+            for child in items.iter_mut() {
+                add_field_next_to(child, sibling, field, default.clone());
+            }
+        }
+        _ => {}
+    }
 }
 
-pub fn load_game(ecs: &mut World) {
+pub fn load_game(ecs: &mut World, slot: &str) -> Result<(), SaveError> {
+    let streams = read_streams(slot)?;
+
+    // Try it on a scratch world first, so a damaged save can't leave the real one half loaded.
+    {
+        let mut scratch = World::new();
+        register_components(&mut scratch);
+        scratch.insert(SimpleMarkerAllocator::<SerializeMe>::new());
+        deserialize_world(&mut scratch, streams.clone())?;
+    }
+
     {
         // Clear the world, so we don't blur loaded data with current data.
         let mut to_delete = Vec::new();
@@ -78,48 +465,82 @@ pub fn load_game(ecs: &mut World) {
         }
     }
 
-    let data = fs::read_to_string(SAVEGAME_FILE_NAME).unwrap();
-    let mut de = serde_json::Deserializer::from_str(&data);
+    deserialize_world(ecs, streams)
+}
 
+fn deserialize_world(ecs: &mut World, mut streams: Streams) -> Result<(), SaveError> {
     {
         let mut d = (
-            &mut ecs.entities(), 
-            &mut ecs.write_storage::<SimpleMarker<SerializeMe>>(), 
+            &mut ecs.entities(),
+            &mut ecs.write_storage::<SimpleMarker<SerializeMe>>(),
             &mut ecs.write_resource::<SimpleMarkerAllocator<SerializeMe>>()
         );
 
-        deserialize_individually!(ecs, de, d, Position, Renderable, Player, Viewshed, Monster, 
-            Name, BlocksTile, CombatStats, SufferDamage, WantsToMelee, Item, Consumable, Ranged, InflictsDamage, 
-            AreaOfEffect, Confusion, ProvidesHealing, InBackpack, WantsToPickupItem, WantsToUseItem,
-            WantsToDropItem, SerializationHelper
+        deserialize_individually!(ecs, streams, d,
+            Position, OtherLevelPosition, Renderable, Player, Viewshed, LightSource, Monster, Vendor, MonsterBrain,
+            AiBehaviour, Initiative, MyTurn, Name, BlocksTile, CombatStats, SufferDamage, WantsToMelee, Item,
+            Consumable, Key, GoalItem, Gold, Price, Ranged, RangedWeapon, Ammo, Stackable, MagicItem,
+            SpecialAttacks, WantsToUseSpecial, InflictsDamage, AreaOfEffect, StatusEffects, InflictsStatus, Hidden,
+            EntryTrigger, Teleports, Experience, Wallet, GivesExperience, ProvidesHealing, InBackpack,
+            WantsToPickupItem, WantsToUseItem, WantsToDropItem, HungerClock, ProvidesFood, Equippable, Equipped,
+            MeleePowerBonus, DefenseBonus, SerializationHelper
         );
     }
 
-    let mut deleteme: Option<Entity> = None;
+    let mut helper_entity: Option<Entity> = None;
+    let mut loaded_map: Option<(Map, i32, MasterDungeonMap, GameLog, Identification, RunStats)> = None;
+    let mut loaded_player: Option<(Entity, rltk::Point)> = None;
     {
         let entities = ecs.entities();
         let helper = ecs.read_storage::<SerializationHelper>();
         let player = ecs.read_storage::<Player>();
         let position = ecs.read_storage::<Position>();
         for (e, h) in (&entities, &helper).join() {
-            let mut worldmap = ecs.write_resource::<Map>();
-            *worldmap = h.map.clone();
-            worldmap.tile_content = vec![Vec::new(); MAPCOUNT];
-            deleteme = Some(e);
+            let mut worldmap = h.map.clone();
+            worldmap.tile_content = vec![Vec::new(); worldmap.tiles.len()];
+            loaded_map = Some((worldmap, h.turns, h.dungeon.clone(), h.log.clone(), h.identification.clone(), h.stats.clone()));
+            helper_entity = Some(e);
         }
 
         for (e,_p,pos) in (&entities, &player, &position).join() {
-            let mut ppos = ecs.write_resource::<rltk::Point>();
-            *ppos = rltk::Point::new(pos.x, pos.y);
-            let mut player_resource = ecs.write_resource::<Entity>();
-            *player_resource = e;
+            loaded_player = Some((e, rltk::Point::new(pos.x, pos.y)));
         }
     }
-    ecs.delete_entity(deleteme.unwrap()).expect("Unable to delete helper");
-}
 
-pub fn delete_save() {
-    if does_save_exist() {
-        fs::remove_file(SAVEGAME_FILE_NAME).expect("Unable to delete file");
+    let (worldmap, turns, dungeon, mut log, identification, stats) = loaded_map.ok_or_else(|| SaveError::Corrupt("no map".to_string()))?;
+    let (player_entity, player_pos) = loaded_player.ok_or_else(|| SaveError::Corrupt("no player".to_string()))?;
+    ecs.insert(worldmap);
+    ecs.insert(TurnCount(turns));
+    ecs.insert(dungeon);
+    log.set_turn(turns);
+    ecs.insert(log);
+    ecs.insert(identification);
+    ecs.insert(stats);
+    ecs.insert(player_pos);
+    ecs.insert(player_entity);
+
+    if let Some(helper_entity) = helper_entity {
+        ecs.delete_entity(helper_entity).expect("Unable to delete helper");
     }
+
+    Ok(())
+}
+
+/// `timestamp` (seconds since the unix epoch) as "YYYY-MM-DD HH:MM" in UTC.
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let seconds_of_day = timestamp % 86400;
+
+    // Civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, seconds_of_day / 3600, (seconds_of_day % 3600) / 60)
 }
@@ -0,0 +1,87 @@
+use specs::prelude::*;
+use crate::components::*;
+use crate::gamelog::GameLog;
+use crate::identification::Identification;
+use crate::inventory_system::stow_item;
+
+/// What a vendor asks for `item`, the whole stack if it's one. None if it has no price.
+pub fn buy_price(ecs: &World, item: Entity) -> Option<i32> {
+    let count = ecs.read_storage::<Stackable>().get(item).map_or(1, |stack| stack.count);
+    ecs.read_storage::<Price>().get(item).map(|price| price.gold * count)
+}
+
+/// What a vendor pays for `item`: half price, but never nothing.
+pub fn sell_price(ecs: &World, item: Entity) -> Option<i32> {
+    let count = ecs.read_storage::<Stackable>().get(item).map_or(1, |stack| stack.count);
+    ecs.read_storage::<Price>().get(item).map(|price| i32::max(1, price.gold / 2) * count)
+}
+
+/// Buy `item` from `vendor`, if the player can afford it and has room for it.
+pub fn buy(ecs: &mut World, vendor: Entity, item: Entity) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let on_sale = ecs.read_storage::<InBackpack>().get(item).is_some_and(|pack| pack.owner == vendor);
+    let price = match buy_price(ecs, item) {
+        Some(price) if on_sale => price,
+        _ => return,
+    };
+    let name = item_name(ecs, item);
+
+    let gold = ecs.read_storage::<Wallet>().get(player_entity).map_or(0, |wallet| wallet.gold);
+    if gold < price {
+        ecs.fetch_mut::<GameLog>().item(format!("You can't afford the {}.", name));
+        return;
+    }
+    if !hand_over(ecs, item, player_entity) {
+        ecs.fetch_mut::<GameLog>().item("Your pack is full.".to_string());
+        return;
+    }
+
+    if let Some(wallet) = ecs.write_storage::<Wallet>().get_mut(player_entity) {
+        wallet.gold -= price;
+    }
+    ecs.fetch_mut::<GameLog>().item(format!("You buy the {} for {} gold.", name, price));
+    ecs.maintain();
+}
+
+/// Sell `item` from the player's backpack to `vendor`.
+pub fn sell(ecs: &mut World, vendor: Entity, item: Entity) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let carried = ecs.read_storage::<InBackpack>().get(item).is_some_and(|pack| pack.owner == player_entity);
+    if !carried {
+        return;
+    }
+    let name = item_name(ecs, item);
+    let vendor_name = ecs.read_storage::<Name>().get(vendor).map_or("vendor".to_string(), |n| n.name.clone());
+
+    let price = match sell_price(ecs, item) {
+        None => {
+            ecs.fetch_mut::<GameLog>().item(format!("The {} isn't interested in the {}.", vendor_name, name));
+            return;
+        }
+        Some(price) => price,
+    };
+    if !hand_over(ecs, item, vendor) {
+        ecs.fetch_mut::<GameLog>().item(format!("The {} has no room for the {}.", vendor_name, name));
+        return;
+    }
+
+    if let Some(wallet) = ecs.write_storage::<Wallet>().get_mut(player_entity) {
+        wallet.gold += price;
+    }
+    ecs.fetch_mut::<GameLog>().item(format!("You sell the {} for {} gold.", name, price));
+    ecs.maintain();
+}
+
+/// Move `item` into `owner`'s backpack. False if there's no room.
+fn hand_over(ecs: &mut World, item: Entity, owner: Entity) -> bool {
+    let entities = ecs.entities();
+    let mut backpack = ecs.write_storage::<InBackpack>();
+    let names = ecs.read_storage::<Name>();
+    let mut stacks = ecs.write_storage::<Stackable>();
+    stow_item(&entities, &mut backpack, &names, &mut stacks, owner, item)
+}
+
+fn item_name(ecs: &World, item: Entity) -> String {
+    let identification = ecs.fetch::<Identification>();
+    ecs.read_storage::<Name>().get(item).map_or("item".to_string(), |n| identification.name(&n.name))
+}
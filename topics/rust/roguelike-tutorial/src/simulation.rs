@@ -0,0 +1,136 @@
+use specs::prelude::*;
+use rltk::Point;
+use crate::{State,RunState};
+use crate::components::*;
+use crate::console::TextConsole;
+use crate::gamelog::GameLog;
+use crate::map::Map;
+use crate::player::perform_action;
+use crate::raws::{self,RawMaster};
+use crate::replay::{PlayerAction,ReplayRecorder};
+use crate::saveload_system::{self,SaveError};
+use crate::travel::{self,TravelGoal};
+
+/// Runs the game without an rltk window: script the player's actions, then inspect the world.
+/// Every turn goes through the same run-state machine as the real game, so systems behave exactly as they do in play.
+pub struct Simulation {
+    pub state: State,
+}
+
+impl Simulation {
+    /// A normal new game, the same one the window would start with this seed.
+    pub fn new_game(seed: u64, raws: RawMaster) -> Simulation {
+        let state = State::new_game(seed, raws, ReplayRecorder::disabled());
+        Simulation::start(state)
+    }
+
+    /// A hand-built level with just the player on it at (x, y). Use `spawn` to add monsters and items.
+    pub fn with_map(seed: u64, raws: RawMaster, map: Map, player_x: i32, player_y: i32) -> Simulation {
+        let mut state = State::new_world(seed, raws, ReplayRecorder::disabled());
+        state.ecs.insert(map);
+        state.place_player(player_x, player_y);
+        Simulation::start(state)
+    }
+
+    fn start(mut state: State) -> Simulation {
+        state.settle(RunState::PreRun);
+        Simulation{ state }
+    }
+
+    /// Create a raws entity at (x, y). Takes effect from the next turn, like anything spawned mid-game.
+    pub fn spawn(&mut self, name: &str, x: i32, y: i32) -> Entity {
+        raws::spawn_named_entity(&mut self.state.ecs, name, x, y)
+            .unwrap_or_else(|| panic!("No raws entity called {}", name))
+    }
+
+    /// Take one player action, then run everything that happens before the player gets to act again.
+    pub fn act(&mut self, action: PlayerAction) {
+        let runstate = perform_action(&mut self.state.ecs, action);
+        let runstate = self.state.settle(runstate);
+
+        let mut runwriter = self.state.ecs.write_resource::<RunState>();
+        *runwriter = runstate;
+    }
+
+    pub fn act_all(&mut self, actions: &[PlayerAction]) {
+        for action in actions.iter() {
+            self.act(*action);
+        }
+    }
+
+    /// Auto-explore or travel, one turn per step, until it arrives or something interrupts it.
+    pub fn travel(&mut self, goal: TravelGoal) {
+        let mut runstate = travel::start_travel(&mut self.state, goal);
+        loop {
+            runstate = self.state.settle(runstate);
+            match self.state.travel.take() {
+                None => break,
+                Some(plan) => runstate = travel::keep_travelling(&mut self.state, plan),
+            }
+        }
+
+        let mut runwriter = self.state.ecs.write_resource::<RunState>();
+        *runwriter = runstate;
+    }
+
+    pub fn save(&mut self, slot: &str) -> Result<(), SaveError> {
+        saveload_system::save_game(&mut self.state.ecs, slot)
+    }
+
+    /// Load `slot` the way the load menu does, ready for the player's next action.
+    pub fn load(&mut self, slot: &str) -> Result<(), SaveError> {
+        self.state.load_game(slot)?;
+        let mut runwriter = self.state.ecs.write_resource::<RunState>();
+        *runwriter = RunState::AwaitingInput;
+        Ok(())
+    }
+
+    pub fn world(&self) -> &World {
+        &self.state.ecs
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.state.ecs
+    }
+
+    pub fn player(&self) -> Entity {
+        *self.state.ecs.fetch::<Entity>()
+    }
+
+    pub fn position(&self, entity: Entity) -> Option<Point> {
+        self.state.ecs.read_storage::<Position>().get(entity).map(|pos| Point::new(pos.x, pos.y))
+    }
+
+    pub fn hp(&self, entity: Entity) -> Option<i32> {
+        self.state.ecs.read_storage::<CombatStats>().get(entity).map(|stats| stats.hp)
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.state.ecs.is_alive(entity)
+    }
+
+    /// Every living entity with this name.
+    pub fn find(&self, name: &str) -> Vec<Entity> {
+        let entities = self.state.ecs.entities();
+        let names = self.state.ecs.read_storage::<Name>();
+        (&entities, &names).join()
+            .filter(|(_entity, n)| n.name == name)
+            .map(|(entity, _n)| entity)
+            .collect()
+    }
+
+    pub fn log(&self) -> Vec<String> {
+        self.state.ecs.fetch::<GameLog>().entries().iter().map(|entry| entry.display()).collect()
+    }
+
+    pub fn log_contains(&self, text: &str) -> bool {
+        self.log().iter().any(|entry| entry.contains(text))
+    }
+
+    /// What the window would show right now, map and UI, drawn into a text console.
+    pub fn screen(&self) -> TextConsole {
+        let mut screen = TextConsole::screen();
+        self.state.render(&mut screen);
+        screen
+    }
+}
@@ -1,11 +1,17 @@
 use rltk::{RGB,RandomNumberGenerator};
 use specs::prelude::*;
 use specs::saveload::{SimpleMarker,MarkedBuilder};
-use std::collections::HashMap;
 use crate::components::*;
 use crate::constants::*;
+use crate::raws::{self,RawMaster};
+use crate::hunger_system;
+use crate::initiative_system;
+use crate::map::Map;
+use crate::map_builders::Vault;
 use crate::rect::Rect;
-use crate::random_table::RandomTable;
+
+/// How far the player's torch lights up. Less than they can see, so the edge of their view is in the dark.
+pub const PLAYER_TORCH_RANGE: i32 = 6;
 
 pub fn player(ecs: &mut World, player_x: i32, player_y: i32) -> Entity {
     ecs
@@ -24,193 +30,119 @@ pub fn player(ecs: &mut World, player_x: i32, player_y: i32) -> Entity {
             range: 8,
             dirty: true,
         })
+        .with(LightSource{ range: PLAYER_TORCH_RANGE })
         .with(CombatStats {
             max_hp: 30,
             hp: 30,
             defense: 2,
             power: 5
         })
+        .with(Initiative{ energy: 0, speed: initiative_system::NORMAL_SPEED })
+        .with(Experience{ level: 1, xp: 0, pending_boosts: 0 })
+        .with(HungerClock{ state: HungerState::WellFed, duration: hunger_system::WELL_FED_DURATION })
+        .with(Wallet{ gold: 0 })
         .marked::<SimpleMarker<SerializeMe>>()
         .build()
 }
 
-pub fn spawn_room(ecs: &mut World, room: &Rect, depth: i32) {
-    let spawn_table = room_table(depth);
-    let mut spawn_points: HashMap<usize, String> = HashMap::new();
+/// Roll the depth's spawn table for a handful of random tiles in `area`.
+pub fn spawn_region(ecs: &mut World, area: &[usize], depth: i32) {
+    let spawn_table = ecs.fetch::<RawMaster>().spawn_table(depth);
+    let mut spawn_points: Vec<(usize, String)> = Vec::new();
+    let mut areas: Vec<usize> = Vec::from(area);
 
     {
         let mut rng = ecs.write_resource::<RandomNumberGenerator>();
-        let num_spawns = rng.roll_dice(1, MAX_MONSTERS + 3) - 2;
+        let num_spawns = i32::min(areas.len() as i32, rng.roll_dice(1, MAX_MONSTERS + 3) - 2);
 
         for _i in 0..num_spawns {
-            let mut added = false;
-            let mut tries = 0;
-            while !added && tries < 20 {
-                let x = (room.x1 + rng.roll_dice(1, i32::abs(room.x2 - room.x1))) as usize;
-                let y = (room.y1 + rng.roll_dice(1, i32::abs(room.y2 - room.y1))) as usize;
-
-                let idx = (y * WORLD_WIDTH) + x;
-                if !spawn_points.contains_key(&idx) {
-                    spawn_points.insert(idx, spawn_table.roll(&mut rng));
-                    added = true;
-                } else {
-                    tries += 1;
-                }
-            }
+            let array_index = (rng.roll_dice(1, areas.len() as i32) - 1) as usize;
+            let map_idx = areas.remove(array_index);
+            spawn_points.push((map_idx, spawn_table.roll(&mut rng)));
         }
     }
 
-    spawn_points
-        .iter()
-        .for_each(|(idx, sp)| {
-            let x = (*idx % WORLD_WIDTH) as i32;
-            let y = (*idx / WORLD_WIDTH) as i32;
-            match sp.as_ref() {
-                "Goblin" => goblin(ecs, x, y),
-                "Orc" => orc(ecs, x, y),
-                "Health Potion" => health_potion(ecs, x, y),
-                "Fireball Scroll" => fireball_scroll(ecs, x, y),
-                "Confusion Scroll" => confusion_scroll(ecs, x, y),
-                "Magic Missile Scroll" => magic_missile_scroll(ecs, x, y),
-                "Dagger" => dagger(ecs, x, y),
-                "Shield" => shield(ecs, x, y),
-                _ => {}
-            }
-        });
+    let width = ecs.fetch::<Map>().width as usize;
+    for (idx, name) in spawn_points.iter() {
+        let x = (*idx % width) as i32;
+        let y = (*idx / width) as i32;
+        raws::spawn_named_entity(ecs, name, x, y);
+    }
 }
 
-fn orc(ecs: &mut World, x: i32, y: i32) { monster(ecs, x, y, rltk::to_cp437('o'), "Orc"); }
-fn goblin(ecs: &mut World, x: i32, y: i32) { monster(ecs, x, y, rltk::to_cp437('g'), "Goblin"); }
-
-fn monster<S : ToString>(ecs: &mut World, x: i32, y: i32, glyph : u16, name : S) {
-    ecs.create_entity()
-        .with(Position{ x, y })
-        .with(Renderable{
-            glyph,
-            fg: RGB::named(rltk::RED),
-            bg: RGB::named(rltk::BLACK),
-            render_order: 1,
-        })
-        .with(Viewshed{ visible_tiles : Vec::new(), range: 8, dirty: true })
-        .with(Monster{})
-        .with(Name{ name : name.to_string() })
-        .with(BlocksTile{})
-        .with(CombatStats{ max_hp: 16, hp: 16, defense: 1, power: 4 })
-        .marked::<SimpleMarker<SerializeMe>>()
-        .build();
+/// Hang a brazier on each of the wall tiles in `spots`.
+pub fn spawn_braziers(ecs: &mut World, spots: &[usize]) {
+    let width = ecs.fetch::<Map>().width as usize;
+    for idx in spots.iter() {
+        raws::spawn_named_entity(ecs, "Brazier", (*idx % width) as i32, (*idx / width) as i32);
+    }
 }
 
-fn room_table(depth: i32) -> RandomTable {
-    RandomTable::new()
-        .add("Goblin", 10)
-        .add("Orc", 1 + depth)
-        .add("Health Potion", 7)
-        .add("Fireball Scroll", 2 + depth)
-        .add("Confusion Scroll", 2 + depth)
-        .add("Magic Missile Scroll", 4)
-        .add("Dagger", 3)
-        .add("Shield", 3)
-}
+/// How many items are waiting in a vault.
+const VAULT_TREASURE: i32 = 3;
 
-fn health_potion(ecs: &mut World, x: i32, y: i32) {
-    ecs.create_entity()
-        .with(Position{ x, y })
-        .with(Renderable{ 
-            glyph: rltk::to_cp437('¡'),
-            fg: RGB::named(rltk::MAGENTA),
-            bg: RGB::named(rltk::BLACK),
-            render_order: 2,
-        })
-        .with(Name{ name: "Health Potion".to_string() })
-        .with(Item{})
-        .with(Consumable{})
-        .with(ProvidesHealing{ heal_amount: 8 })
-        .marked::<SimpleMarker<SerializeMe>>()
-        .build();
-}
+/// Leave the key to `vault` where the generator chose, and fill the vault with items from the depth's spawn table.
+pub fn spawn_vault(ecs: &mut World, vault: &Vault, depth: i32) {
+    let width = ecs.fetch::<Map>().width as usize;
+    let key_x = (vault.key_idx % width) as i32;
+    let key_y = (vault.key_idx / width) as i32;
+    raws::spawn_named_entity(ecs, "Vault Key", key_x, key_y);
 
-fn magic_missile_scroll(ecs: &mut World, x: i32, y: i32) {
-    ecs.create_entity()
-        .with(Position{ x, y })
-        .with(Renderable{
-            glyph: rltk::to_cp437(')'),
-            fg: RGB::named(rltk::CYAN),
-            bg: RGB::named(rltk::BLACK),
-            render_order: 2,
-        })
-        .with(Name{ name: "Magic Missile Scroll".to_string() })
-        .with(Item{})
-        .with(Consumable{})
-        .with(Ranged{ range: 6 })
-        .with(InflictsDamage{ damage: 8 })
-        .marked::<SimpleMarker<SerializeMe>>()
-        .build();
-}
+    let mut treasure: Vec<(i32, i32, String)> = Vec::new();
+    {
+        let raws = ecs.fetch::<RawMaster>();
+        let spawn_table = raws.spawn_table(depth);
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        // Reroll monsters and traps, but don't keep at it forever.
+        for _try in 0..VAULT_TREASURE * 10 {
+            if treasure.len() as i32 >= VAULT_TREASURE {
+                break;
+            }
+            let name = spawn_table.roll(&mut rng);
+            if raws.is_item(&name) {
+                let x = rng.range(vault.room.x1 + 1, vault.room.x2 + 1);
+                let y = rng.range(vault.room.y1 + 1, vault.room.y2 + 1);
+                treasure.push((x, y, name));
+            }
+        }
+    }
 
-fn fireball_scroll(ecs: &mut World, x: i32, y: i32) {
-    ecs.create_entity()
-        .with(Position{ x, y })
-        .with(Renderable{
-            glyph: rltk::to_cp437(')'),
-            fg: RGB::named(rltk::ORANGE),
-            bg: RGB::named(rltk::BLACK),
-            render_order: 2,
-        })
-        .with(Name{ name: "Fireball Scroll".to_string() })
-        .with(Item{})
-        .with(Consumable{})
-        .with(Ranged{ range: 6 })
-        .with(InflictsDamage{ damage: 20 })
-        .with(AreaOfEffect{ radius: 3 })
-        .marked::<SimpleMarker<SerializeMe>>()
-        .build();
+    for (x, y, name) in treasure.iter() {
+        raws::spawn_named_entity(ecs, name, *x, *y);
+    }
 }
 
-fn confusion_scroll(ecs: &mut World, x: i32, y: i32) {
-    ecs.create_entity()
-        .with(Position{ x, y })
-        .with(Renderable{
-            glyph: rltk::to_cp437(')'),
-            fg: RGB::named(rltk::PINK),
-            bg: RGB::named(rltk::BLACK),
-            render_order: 2,
-        })
-        .with(Name{ name: "Confusion Scroll".to_string() })
-        .with(Item{})
-        .with(Consumable{})
-        .with(Ranged{ range: 6 })
-        .with(Confusion{ turns: 4 })
-        .marked::<SimpleMarker<SerializeMe>>()
-        .build();
-}
+/// How many things a shopkeeper has for sale.
+const VENDOR_STOCK: i32 = 5;
 
-fn dagger(ecs: &mut World, x: i32, y: i32) {
-    ecs.create_entity()
-        .with(Position{ x, y })
-        .with(Renderable{ 
-            glyph: rltk::to_cp437('/'),
-            fg: RGB::named(rltk::CYAN),
-            bg: RGB::named(rltk::BLACK),
-            render_order: 2,
-        })
-        .with(Name{ name: "Dagger".to_string() })
-        .with(Item{})
-        .marked::<SimpleMarker<SerializeMe>>()
-        .build();
-}
+/// Put a shopkeeper in the middle of `room`, with a few items from the depth's spawn table to sell.
+pub fn spawn_shop(ecs: &mut World, room: &Rect, depth: i32) {
+    let (x, y) = room.center();
+    let vendor = match raws::spawn_named_entity(ecs, "Shopkeeper", x, y) {
+        None => return,
+        Some(vendor) => vendor,
+    };
 
+    let mut stock: Vec<String> = Vec::new();
+    {
+        let raws = ecs.fetch::<RawMaster>();
+        let spawn_table = raws.spawn_table(depth);
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        for _try in 0..VENDOR_STOCK * 10 {
+            if stock.len() as i32 >= VENDOR_STOCK {
+                break;
+            }
+            let name = spawn_table.roll(&mut rng);
+            if raws.is_for_sale(&name) {
+                stock.push(name);
+            }
+        }
+    }
 
-fn shield(ecs: &mut World, x: i32, y: i32) {
-    ecs.create_entity()
-        .with(Position{ x, y })
-        .with(Renderable{
-            glyph: rltk::to_cp437('('),
-            fg: RGB::named(rltk::CYAN),
-            bg: RGB::named(rltk::BLACK),
-            render_order: 2
-        })
-        .with(Name{ name : "Shield".to_string() })
-        .with(Item{})
-        .marked::<SimpleMarker<SerializeMe>>()
-        .build();
+    for name in stock.iter() {
+        if let Some(item) = raws::spawn_named_entity(ecs, name, x, y) {
+            ecs.write_storage::<Position>().remove(item);
+            ecs.write_storage::<InBackpack>().insert(item, InBackpack{ owner: vendor }).expect("Unable to stock vendor");
+        }
+    }
 }
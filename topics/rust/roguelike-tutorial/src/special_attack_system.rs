@@ -0,0 +1,64 @@
+use specs::prelude::*;
+use rltk::{Point,DistanceAlg};
+use crate::components::*;
+use crate::gamelog::GameLog;
+
+/// Resolves `WantsToUseSpecial`, then starts the attack recharging.
+pub struct SpecialAttackSystem {}
+
+impl<'a> System<'a> for SpecialAttackSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = ( Entities<'a>,
+                        WriteExpect<'a, GameLog>,
+                        WriteStorage<'a, WantsToUseSpecial>,
+                        WriteStorage<'a, SpecialAttacks>,
+                        ReadStorage<'a, Name>,
+                        ReadStorage<'a, Position>,
+                        WriteStorage<'a, CombatStats>,
+                        WriteStorage<'a, SufferDamage>,
+                        WriteStorage<'a, LastAttacker>,
+                      );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, mut log, mut wants_special, mut special_attacks, names, positions, mut combat_stats,
+            mut inflict_damage, mut last_attackers) = data;
+
+        for (entity, wants, specials, name) in (&entities, &wants_special, &mut special_attacks, &names).join() {
+            let attack = match specials.attacks.get_mut(wants.attack) {
+                None => continue,
+                Some(attack) => attack,
+            };
+            attack.recharge = attack.cooldown;
+            log.combat(format!("{} uses {}!", &name.name, &attack.name));
+
+            match attack.kind {
+                SpecialAttackKind::Breath{ radius, damage } => {
+                    let centre = match positions.get(wants.target) {
+                        None => continue,
+                        Some(pos) => Point::new(pos.x, pos.y),
+                    };
+                    for (victim, pos, _stats) in (&entities, &positions, &combat_stats).join() {
+                        if victim == entity || DistanceAlg::Pythagoras.distance2d(centre, Point::new(pos.x, pos.y)) > radius as f32 {
+                            continue;
+                        }
+                        let victim_name = names.get(victim).map_or("something", |n| n.name.as_str());
+                        log.combat(format!("{} hits {}, for {} hp.", &attack.name, victim_name, damage));
+                        SufferDamage::new_damage(&mut inflict_damage, victim, damage, &name.name);
+                        last_attackers.insert(victim, LastAttacker{ attacker: entity }).expect("Unable to insert attacker");
+                    }
+                }
+                SpecialAttackKind::Drain{ damage } => {
+                    let target_name = names.get(wants.target).map_or("something", |n| n.name.as_str());
+                    log.combat(format!("{} drains {} hp from {}.", &name.name, damage, target_name));
+                    SufferDamage::new_damage(&mut inflict_damage, wants.target, damage, &name.name);
+                    last_attackers.insert(wants.target, LastAttacker{ attacker: entity }).expect("Unable to insert attacker");
+                    if let Some(stats) = combat_stats.get_mut(entity) {
+                        stats.hp = i32::min(stats.max_hp, stats.hp + damage);
+                    }
+                }
+            }
+        }
+
+        wants_special.clear();
+    }
+}
@@ -0,0 +1,124 @@
+use specs::prelude::*;
+use crate::RunState;
+use crate::components::*;
+use crate::gamelog::GameLog;
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum TurnPhase {
+    Start,
+    End,
+}
+
+/// Ticks status effects for whoever has a `MyTurn` and is acting now: monsters while `Ticking`, the player on
+/// `PlayerTurn`. At the start of a turn effects do their thing (poison hurts, regeneration heals), at the end
+/// they count down and wear off. An effect applied for N turns covers the owner's next N turns.
+pub struct StatusEffectSystem {
+    pub phase: TurnPhase,
+}
+
+impl<'a> System<'a> for StatusEffectSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = ( ReadExpect<'a, Entity>,
+                        ReadExpect<'a, RunState>,
+                        WriteExpect<'a, GameLog>,
+                        Entities<'a>,
+                        WriteStorage<'a, StatusEffects>,
+                        WriteStorage<'a, MyTurn>,
+                        WriteStorage<'a, CombatStats>,
+                        WriteStorage<'a, SufferDamage>,
+                        WriteStorage<'a, Viewshed>,
+                      );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (player_entity, runstate, mut gamelog, entities, mut statuses, mut turns, mut combat_stats, mut suffer_damage, mut viewsheds) = data;
+
+        let players_turn = match *runstate {
+            RunState::PlayerTurn => true,
+            RunState::Ticking => false,
+            _ => return,
+        };
+
+        let mut worn_off: Vec<Entity> = Vec::new();
+        let mut lost_turn: Vec<Entity> = Vec::new();
+        for (entity, status, _turn) in (&entities, &mut statuses, &turns).join() {
+            let is_player = entity == *player_entity;
+            if is_player != players_turn {
+                continue;
+            }
+
+            match self.phase {
+                TurnPhase::Start => {
+                    let mut paralysed = false;
+                    for effect in status.effects.iter() {
+                        match effect.kind {
+                            StatusEffectKind::Poison => {
+                                SufferDamage::new_damage(&mut suffer_damage, entity, effect.magnitude, "poison");
+                                if is_player {
+                                    gamelog.combat(format!("You take {} poison damage.", effect.magnitude));
+                                }
+                            }
+                            StatusEffectKind::Regeneration => {
+                                if let Some(stats) = combat_stats.get_mut(entity) {
+                                    stats.hp = i32::min(stats.max_hp, stats.hp + effect.magnitude);
+                                }
+                            }
+                            StatusEffectKind::Blindness => {
+                                // Keep the view cut down while it lasts, however it was applied.
+                                if let Some(viewshed) = viewsheds.get_mut(entity) {
+                                    viewshed.dirty = true;
+                                }
+                            }
+                            StatusEffectKind::Paralysis => {
+                                paralysed = true;
+                            }
+                            _ => {}
+                        }
+                    }
+
+                    // The player's lost turns are passed in `begin_player_turn`. A monster's turn is over as soon as
+                    // it starts, so it ends here rather than in the end phase.
+                    if paralysed && !is_player {
+                        lost_turn.push(entity);
+                        if end_turn(status, entity, is_player, &mut gamelog, &mut viewsheds) {
+                            worn_off.push(entity);
+                        }
+                    }
+                }
+                TurnPhase::End => {
+                    if end_turn(status, entity, is_player, &mut gamelog, &mut viewsheds) {
+                        worn_off.push(entity);
+                    }
+                }
+            }
+        }
+
+        for entity in worn_off.iter() {
+            statuses.remove(*entity);
+        }
+        for entity in lost_turn.iter() {
+            turns.remove(*entity);
+        }
+    }
+}
+
+/// Count `status` down by a turn and take off anything that has run out. Returns true if nothing is left.
+fn end_turn(status: &mut StatusEffects, entity: Entity, is_player: bool, gamelog: &mut GameLog,
+            viewsheds: &mut WriteStorage<Viewshed>) -> bool {
+    for effect in status.effects.iter_mut() {
+        effect.turns -= 1;
+    }
+
+    for effect in status.effects.iter().filter(|e| e.turns < 1) {
+        if effect.kind == StatusEffectKind::Blindness {
+            if let Some(viewshed) = viewsheds.get_mut(entity) {
+                viewshed.dirty = true;
+            }
+        }
+        if is_player {
+            gamelog.system(format!("You are no longer {}.", effect.kind.name()));
+        }
+    }
+    status.effects.retain(|e| e.turns > 0);
+
+    status.effects.is_empty()
+}
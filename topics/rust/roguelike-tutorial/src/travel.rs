@@ -0,0 +1,175 @@
+use rltk::{Point,Rltk,DijkstraMap};
+use specs::prelude::*;
+use crate::{State,RunState};
+use crate::components::*;
+use crate::gamelog::GameLog;
+use crate::identification::Identification;
+use crate::map::{Map,TileType};
+use crate::lighting_system::can_make_out;
+use crate::player::perform_action;
+use crate::replay::PlayerAction;
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum TravelGoal {
+    /// Head for the nearest tile that hasn't been seen yet.
+    Explore,
+    /// Walk to a tile that was clicked on.
+    To(Point),
+}
+
+/// A walk over several turns, taken a step each frame. Every step is an ordinary move,
+/// so it's recorded and replayed like any other.
+pub struct Travel {
+    goal: TravelGoal,
+    /// Items already in view, which don't count as finding something.
+    seen_items: Vec<Entity>,
+    hp: i32,
+}
+
+impl Travel {
+    fn new(ecs: &World, goal: TravelGoal) -> Travel {
+        Travel{ goal, seen_items: visible_items(ecs), hp: player_hp(ecs) }
+    }
+}
+
+/// Set off towards `goal`, taking the first step straight away.
+pub fn start_travel(gs: &mut State, goal: TravelGoal) -> RunState {
+    let travel = Travel::new(&gs.ecs, goal);
+    keep_travelling(gs, travel)
+}
+
+/// Take the next step of a walk already under way. Any key or click stops it.
+pub fn continue_travel(gs: &mut State, ctx: &mut Rltk, travel: Travel) -> RunState {
+    if ctx.key.is_some() || ctx.left_click {
+        gs.ecs.fetch_mut::<GameLog>().system("You stop.".to_string());
+        return RunState::AwaitingInput;
+    }
+    keep_travelling(gs, travel)
+}
+
+/// Move a step closer, unless something has come up or there's nowhere to go.
+/// Leaves the plan in `gs.travel` if there's more walking to do.
+pub fn keep_travelling(gs: &mut State, mut travel: Travel) -> RunState {
+    if let Some(reason) = interruption(&gs.ecs, &mut travel) {
+        gs.ecs.fetch_mut::<GameLog>().system(reason);
+        return RunState::AwaitingInput;
+    }
+
+    let here = *gs.ecs.fetch::<Point>();
+    let step = match travel.goal {
+        TravelGoal::Explore => {
+            let step = explore_step(&gs.ecs);
+            if step.is_none() {
+                gs.ecs.fetch_mut::<GameLog>().system("There's nowhere left to explore.".to_string());
+            }
+            step
+        }
+        TravelGoal::To(target) if target == here => None,
+        TravelGoal::To(target) => {
+            let step = travel_step(&gs.ecs, target);
+            if step.is_none() {
+                gs.ecs.fetch_mut::<GameLog>().system("You can't find a way there.".to_string());
+            }
+            step
+        }
+    };
+
+    match step {
+        None => RunState::AwaitingInput,
+        Some(step) => {
+            gs.travel = Some(travel);
+            perform_action(&mut gs.ecs, PlayerAction::Move{ dx: step.x - here.x, dy: step.y - here.y })
+        }
+    }
+}
+
+/// Why travelling should stop: a monster in view, a new item in view, or getting hurt.
+fn interruption(ecs: &World, travel: &mut Travel) -> Option<String> {
+    let player_entity = ecs.fetch::<Entity>();
+    let viewsheds = ecs.read_storage::<Viewshed>();
+    let monsters = ecs.read_storage::<Monster>();
+    let names = ecs.read_storage::<Name>();
+    let identification = ecs.fetch::<Identification>();
+    let map = ecs.fetch::<Map>();
+
+    let name_of = |entity: Entity| names.get(entity).map_or("something".to_string(), |n| identification.name(&n.name));
+
+    let player_pos = *ecs.fetch::<Point>();
+    if let Some(viewshed) = viewsheds.get(*player_entity) {
+        for tile in viewshed.visible_tiles.iter() {
+            if !can_make_out(&map, player_pos, *tile) {
+                continue;
+            }
+            let idx = map.xy_idx(tile.x, tile.y);
+            if let Some(monster) = map.tile_content[idx].iter().find(|e| monsters.get(**e).is_some()) {
+                return Some(format!("You stop: a {} is in view.", name_of(*monster)));
+            }
+        }
+    }
+
+    if let Some(item) = visible_items(ecs).into_iter().find(|item| !travel.seen_items.contains(item)) {
+        travel.seen_items.push(item);
+        return Some(format!("You stop: you see a {}.", name_of(item)));
+    }
+
+    let hp = player_hp(ecs);
+    if hp < travel.hp {
+        return Some("You stop: you've been hurt.".to_string());
+    }
+    travel.hp = hp;
+
+    None
+}
+
+fn visible_items(ecs: &World) -> Vec<Entity> {
+    let map = ecs.fetch::<Map>();
+    let player_pos = *ecs.fetch::<Point>();
+    let entities = ecs.entities();
+    let items = ecs.read_storage::<Item>();
+    let positions = ecs.read_storage::<Position>();
+
+    (&entities, &items, &positions).join()
+        .filter(|(_entity, _item, pos)| {
+            map.visible_tiles[map.xy_idx(pos.x, pos.y)] && can_make_out(&map, player_pos, Point::new(pos.x, pos.y))
+        })
+        .map(|(entity, _item, _pos)| entity)
+        .collect()
+}
+
+fn player_hp(ecs: &World) -> i32 {
+    let player_entity = ecs.fetch::<Entity>();
+    ecs.read_storage::<CombatStats>().get(*player_entity).map_or(0, |stats| stats.hp)
+}
+
+/// Next step downhill on a Dijkstra map flowing out from every unseen open tile.
+fn explore_step(ecs: &World) -> Option<Point> {
+    let map = ecs.fetch::<Map>();
+    let player_pos = ecs.fetch::<Point>();
+
+    let unexplored: Vec<usize> = (0..map.tiles.len())
+        .filter(|idx| !map.revealed_tiles[*idx] && map.tiles[*idx] != TileType::Wall)
+        .collect();
+    if unexplored.is_empty() {
+        return None;
+    }
+
+    let dijkstra = DijkstraMap::new(map.width, map.height, &unexplored, &*map, 1000.0);
+    let idx = map.xy_idx(player_pos.x, player_pos.y);
+    if dijkstra.map[idx] == f32::MAX {
+        return None;
+    }
+    DijkstraMap::find_lowest_exit(&dijkstra, idx, &*map).map(|exit| Point::new(exit as i32 % map.width, exit as i32 / map.width))
+}
+
+/// First step of the A* path to `target`.
+fn travel_step(ecs: &World, target: Point) -> Option<Point> {
+    let map = ecs.fetch::<Map>();
+    let player_pos = ecs.fetch::<Point>();
+
+    let path = rltk::a_star_search(map.xy_idx(player_pos.x, player_pos.y), map.xy_idx(target.x, target.y), &*map);
+    if path.success && path.steps.len() > 1 {
+        Some(Point::new(path.steps[1] as i32 % map.width, path.steps[1] as i32 / map.width))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,108 @@
+use specs::prelude::*;
+use rltk::{Point,RandomNumberGenerator};
+use crate::components::*;
+use crate::gamelog::GameLog;
+use crate::map::{Map,TileType};
+
+/// Sets off traps under anything that moved this turn. A trap that goes off is found, hidden or not.
+pub struct TriggerSystem {}
+
+impl<'a> System<'a> for TriggerSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = ( WriteExpect<'a, Map>,
+                        ReadExpect<'a, Entity>,
+                        WriteExpect<'a, Point>,
+                        WriteExpect<'a, GameLog>,
+                        WriteExpect<'a, RandomNumberGenerator>,
+                        Entities<'a>,
+                        WriteStorage<'a, EntityMoved>,
+                        WriteStorage<'a, Position>,
+                        ReadStorage<'a, EntryTrigger>,
+                        WriteStorage<'a, Hidden>,
+                        ReadStorage<'a, Name>,
+                        ReadStorage<'a, InflictsDamage>,
+                        ReadStorage<'a, InflictsStatus>,
+                        ReadStorage<'a, Teleports>,
+                        ReadStorage<'a, BlocksTile>,
+                        WriteStorage<'a, SufferDamage>,
+                        WriteStorage<'a, StatusEffects>,
+                        WriteStorage<'a, Viewshed>,
+                      );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut map, player_entity, mut player_pos, mut log, mut rng, entities, mut moved, mut positions, triggers,
+            mut hidden, names, inflicts_damage, inflicts_status, teleports, blockers, mut suffer_damage, mut statuses,
+            mut viewsheds) = data;
+
+        let arrivals: Vec<(Entity, usize)> = (&entities, &moved, &positions).join()
+            .map(|(entity, _moved, pos)| (entity, map.xy_idx(pos.x, pos.y)))
+            .collect();
+
+        let mut teleported: Vec<Entity> = Vec::new();
+        for (entity, idx) in arrivals.iter() {
+            for trap in map.tile_content[*idx].iter().filter(|e| triggers.get(**e).is_some()) {
+                hidden.remove(*trap);
+
+                let trap_name = names.get(*trap).map_or("trap".to_string(), |n| n.name.clone());
+                if *entity == *player_entity {
+                    log.combat(format!("You set off a {}!", trap_name));
+                } else if map.visible_tiles[*idx] {
+                    let victim_name = names.get(*entity).map_or("Something".to_string(), |n| n.name.clone());
+                    log.combat(format!("{} sets off a {}!", victim_name, trap_name));
+                }
+
+                if let Some(damage) = inflicts_damage.get(*trap) {
+                    SufferDamage::new_damage(&mut suffer_damage, *entity, damage.damage, &trap_name);
+                }
+                if let Some(inflicts) = inflicts_status.get(*trap) {
+                    StatusEffects::add(&mut statuses, *entity, inflicts.effect);
+                }
+                if teleports.get(*trap).is_some() {
+                    teleported.push(*entity);
+                }
+            }
+        }
+        moved.clear();
+
+        for entity in teleported.iter() {
+            let destination = match random_open_tile(&map, &mut rng) {
+                None => continue,
+                Some(destination) => destination,
+            };
+            if let Some(pos) = positions.get_mut(*entity) {
+                // Move it in the index too, so the rest of this tick sees it there and nobody else lands on it.
+                let from_idx = map.xy_idx(pos.x, pos.y);
+                let to_idx = map.xy_idx(destination.x, destination.y);
+                map.tile_content[from_idx].retain(|e| e != entity);
+                map.tile_content[to_idx].push(*entity);
+                if blockers.get(*entity).is_some() {
+                    map.blocked_tiles[from_idx] = false;
+                    map.blocked_tiles[to_idx] = true;
+                }
+
+                pos.x = destination.x;
+                pos.y = destination.y;
+            }
+            if let Some(viewshed) = viewsheds.get_mut(*entity) {
+                viewshed.dirty = true;
+            }
+            if *entity == *player_entity {
+                *player_pos = destination;
+                log.combat("You are whisked away!".to_string());
+            }
+        }
+    }
+}
+
+/// A floor tile nobody's standing on, if one turns up in a reasonable number of tries.
+fn random_open_tile(map: &Map, rng: &mut RandomNumberGenerator) -> Option<Point> {
+    for _try in 0..100 {
+        let x = rng.roll_dice(1, map.width - 2);
+        let y = rng.roll_dice(1, map.height - 2);
+        let idx = map.xy_idx(x, y);
+        if map.tiles[idx] == TileType::Floor && !map.blocked_tiles[idx] && map.tile_content[idx].is_empty() {
+            return Some(Point::new(x, y));
+        }
+    }
+    None
+}
@@ -1,6 +1,6 @@
 use specs::prelude::*;
 use rltk::{field_of_view,Point};
-use crate::components::{Viewshed, Position, Player};
+use crate::components::{Viewshed, Position, Player, StatusEffects, StatusEffectKind};
 use crate::map::Map;
 
 pub struct VisibilitySystem {}
@@ -10,9 +10,10 @@ impl<'a> System<'a> for VisibilitySystem {
                         Entities<'a>,
                         WriteStorage<'a, Viewshed>,
                         WriteStorage<'a, Position>,
-                        ReadStorage<'a, Player>);
+                        ReadStorage<'a, Player>,
+                        ReadStorage<'a, StatusEffects>);
     
-    fn run(&mut self, (mut map, entities, mut viewshed, pos, player): Self::SystemData) {
+    fn run(&mut self, (mut map, entities, mut viewshed, pos, player, statuses): Self::SystemData) {
         for (entity,viewshed,pos) in (&entities, &mut viewshed, &pos).join() {
             // Only update dirty viewshed's.
             if !viewshed.dirty {
@@ -20,8 +21,12 @@ impl<'a> System<'a> for VisibilitySystem {
             }
             viewshed.dirty = false;
 
+            // Blindness only lets you see what's right next to you.
+            let blind = statuses.get(entity).is_some_and(|s| s.has(StatusEffectKind::Blindness));
+            let range = if blind { 1 } else { viewshed.range };
+
             viewshed.visible_tiles.clear();
-            viewshed.visible_tiles = field_of_view(Point::new(pos.x, pos.y), viewshed.range, &*map);
+            viewshed.visible_tiles = field_of_view(Point::new(pos.x, pos.y), range, &*map);
             viewshed.visible_tiles.retain(|p| p.x >= 0 && p.x <= map.width && p.y >= 0 && p.y <= map.height);
 
             // If a player, add to revealed tiles
@@ -0,0 +1,48 @@
+use rltk::Point;
+use roguelike_tutorial::camera::Camera;
+use roguelike_tutorial::constants::{VIEW_WIDTH,VIEW_HEIGHT};
+
+#[test]
+fn keeps_the_player_in_the_middle() {
+    let camera = Camera::new(200, 100, Point::new(100, 50));
+
+    let screen = camera.to_screen(Point::new(100, 50)).unwrap();
+    assert_eq!(screen, Point::new(VIEW_WIDTH / 2, VIEW_HEIGHT / 2));
+}
+
+#[test]
+fn stops_at_the_edges_of_the_map() {
+    let top_left = Camera::new(200, 100, Point::new(3, 2));
+    assert_eq!(top_left.origin, Point::new(0, 0));
+
+    let bottom_right = Camera::new(200, 100, Point::new(198, 97));
+    assert_eq!(bottom_right.origin, Point::new(200 - VIEW_WIDTH, 100 - VIEW_HEIGHT));
+    assert_eq!(bottom_right.to_screen(Point::new(199, 99)), Some(Point::new(VIEW_WIDTH - 1, VIEW_HEIGHT - 1)));
+}
+
+#[test]
+fn centres_a_map_smaller_than_the_view() {
+    let width = VIEW_WIDTH - 20;
+    let height = VIEW_HEIGHT - 10;
+    let camera = Camera::new(width, height, Point::new(1, 1));
+
+    assert_eq!(camera.to_screen(Point::new(0, 0)), Some(Point::new(10, 5)));
+}
+
+#[test]
+fn screen_and_world_coordinates_round_trip() {
+    let camera = Camera::new(200, 100, Point::new(120, 60));
+    let tile = Point::new(130, 55);
+
+    let screen = camera.to_screen(tile).unwrap();
+    assert_eq!(camera.to_world(screen), Some(tile));
+}
+
+#[test]
+fn nothing_outside_the_view() {
+    let camera = Camera::new(200, 100, Point::new(100, 50));
+
+    assert_eq!(camera.to_screen(Point::new(10, 50)), None);
+    // The UI panel under the map isn't part of it.
+    assert_eq!(camera.to_world(Point::new(5, VIEW_HEIGHT + 2)), None);
+}
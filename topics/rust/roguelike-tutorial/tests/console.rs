@@ -0,0 +1,30 @@
+use rltk::RGB;
+use roguelike_tutorial::console::{Console,TextConsole};
+
+#[test]
+fn draws_boxes_and_text() {
+    let mut console = TextConsole::new(10, 4);
+    console.draw_box(0, 0, 9, 3, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
+    console.print(2, 1, "Hi");
+
+    assert_eq!(console.to_text(), "┌────────┐\n│ Hi     │\n│        │\n└────────┘");
+}
+
+#[test]
+fn ignores_anything_off_the_edge() {
+    let mut console = TextConsole::new(10, 1);
+    console.print(8, 0, "long text");
+    console.set(-1, 5, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437('@'));
+
+    assert_eq!(console.line(0), "        lo");
+}
+
+#[test]
+fn colours_the_terminal_output() {
+    let mut console = TextConsole::new(3, 1);
+    console.print_color(1, 0, RGB::named(rltk::RED), RGB::named(rltk::BLACK), "!");
+
+    let ansi = console.to_ansi();
+    assert!(ansi.contains("\x1b[38;2;255;0;0m\x1b[48;2;0;0;0m!"));
+    assert!(ansi.ends_with("\x1b[0m\n"));
+}
@@ -0,0 +1,29 @@
+use roguelike_tutorial::gamelog::{GameLog,LogCategory,MAX_LOG_ENTRIES};
+
+#[test]
+fn repeats_are_counted_on_one_entry() {
+    let mut log = GameLog::new(&[]);
+    log.set_turn(3);
+    log.combat("Goblin hits Player, for 1 hp.".to_string());
+    log.set_turn(4);
+    log.combat("Goblin hits Player, for 1 hp.".to_string());
+    log.combat("Goblin hits Player, for 1 hp.".to_string());
+    log.item("You pick up the Arrow.".to_string());
+
+    let entries: Vec<_> = log.entries().iter().collect();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].display(), "Goblin hits Player, for 1 hp. x3");
+    assert_eq!((entries[0].category, entries[0].turn), (LogCategory::Combat, 4));
+    assert_eq!(entries[1].category, LogCategory::Item);
+}
+
+#[test]
+fn history_is_capped() {
+    let mut log = GameLog::new(&["Welcome".to_string()]);
+    for n in 0..MAX_LOG_ENTRIES + 10 {
+        log.system(format!("Message {}", n));
+    }
+
+    assert_eq!(log.entries().len(), MAX_LOG_ENTRIES);
+    assert_eq!(log.entries().back().unwrap().text, format!("Message {}", MAX_LOG_ENTRIES + 9));
+}
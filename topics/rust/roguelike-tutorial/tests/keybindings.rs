@@ -0,0 +1,38 @@
+use rltk::VirtualKeyCode;
+use roguelike_tutorial::keybindings::{Command,Keybindings};
+
+#[test]
+fn defaults_cover_arrows_numpad_and_vi_keys() {
+    let keys = Keybindings::defaults();
+
+    assert_eq!(keys.command(VirtualKeyCode::Up), Some(Command::MoveNorth));
+    assert_eq!(keys.command(VirtualKeyCode::Numpad7), Some(Command::MoveNorthWest));
+    assert_eq!(keys.command(VirtualKeyCode::N), Some(Command::MoveSouthEast));
+    assert_eq!(Command::MoveSouthWest.direction(), Some((-1, 1)));
+}
+
+#[test]
+fn config_rebinds_commands_and_reports_conflicts() {
+    let (keys, conflicts) = Keybindings::from_json(r#"{ "Wait": ["Period"], "Descend": ["Return"] }"#).unwrap();
+    assert_eq!(keys.command(VirtualKeyCode::Period), Some(Command::Wait));
+    assert_eq!(keys.command(VirtualKeyCode::Return), Some(Command::Descend));
+    assert_eq!(keys.command(VirtualKeyCode::Space), None);
+    assert!(conflicts.is_empty());
+
+    let (keys, conflicts) = Keybindings::from_json(r#"{ "Quit": ["I"] }"#).unwrap();
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(keys.command(VirtualKeyCode::I), Some(Command::Inventory));
+
+    assert!(Keybindings::from_json(r#"{ "Quit": ["NoSuchKey"] }"#).is_err());
+}
+
+#[test]
+fn names_the_keys_bound_to_a_command() {
+    assert_eq!(Keybindings::defaults().key_names(Command::Fire), vec!["F"]);
+
+    let (keys, _conflicts) = Keybindings::from_json(r#"{ "Fire": ["T", "Key1"] }"#).unwrap();
+    assert_eq!(keys.key_names(Command::Fire), vec!["Key1", "T"]);
+
+    let (keys, _conflicts) = Keybindings::from_json(r#"{ "Fire": [] }"#).unwrap();
+    assert!(keys.key_names(Command::Fire).is_empty());
+}
@@ -0,0 +1,183 @@
+use rltk::{Point,DistanceAlg};
+use roguelike_tutorial::chase_map::ChaseMap;
+use roguelike_tutorial::components::{AiBehaviour,AiState,MonsterBrain};
+use roguelike_tutorial::map::{Map,TileType};
+use roguelike_tutorial::monster_ai_system::{think,AiDecision,Perception};
+
+fn sees_player_at(distance: i32) -> Perception {
+    Perception {
+        position: Point::new(10, 10),
+        player: Point::new(10 + distance, 10),
+        can_see_player: true,
+        alerted: false,
+        hp_percent: 100,
+        can_shoot: false,
+    }
+}
+
+#[test]
+fn attacks_when_adjacent_and_chases_otherwise() {
+    let mut brain = MonsterBrain::default();
+    let behaviour = AiBehaviour::default();
+
+    assert_eq!(think(&mut brain, &behaviour, &sees_player_at(1)), AiDecision::Attack);
+    assert_eq!(think(&mut brain, &behaviour, &sees_player_at(4)), AiDecision::MoveTowards(Point::new(14, 10)));
+    assert_eq!(brain.state, AiState::Chasing);
+}
+
+#[test]
+fn heads_for_last_known_position_after_losing_sight() {
+    let mut brain = MonsterBrain::default();
+    let behaviour = AiBehaviour::default();
+    think(&mut brain, &behaviour, &sees_player_at(4));
+
+    let mut lost = sees_player_at(6);
+    lost.can_see_player = false;
+    assert_eq!(think(&mut brain, &behaviour, &lost), AiDecision::MoveTowards(Point::new(14, 10)));
+
+    // Once it's there and there's nobody around, it gives up.
+    lost.position = Point::new(14, 10);
+    assert_eq!(think(&mut brain, &behaviour, &lost), AiDecision::Stay);
+    assert_eq!(brain.state, AiState::Idle);
+}
+
+#[test]
+fn wounded_monsters_flee() {
+    let mut brain = MonsterBrain::default();
+    let behaviour = AiBehaviour{ flee_at_hp_percent: 25, ..AiBehaviour::default() };
+    let mut perception = sees_player_at(1);
+    perception.hp_percent = 20;
+
+    assert_eq!(think(&mut brain, &behaviour, &perception), AiDecision::MoveAwayFrom(Point::new(11, 10)));
+    assert_eq!(brain.state, AiState::Fleeing);
+}
+
+#[test]
+fn ranged_attackers_keep_their_distance() {
+    let mut brain = MonsterBrain::default();
+    let behaviour = AiBehaviour{ attack_range: 5, keep_distance: 3, ..AiBehaviour::default() };
+    let at = |distance| Perception{ can_shoot: true, ..sees_player_at(distance) };
+
+    assert_eq!(think(&mut brain, &behaviour, &at(2)), AiDecision::MoveAwayFrom(Point::new(12, 10)));
+    assert_eq!(think(&mut brain, &behaviour, &at(4)), AiDecision::Attack);
+    assert_eq!(think(&mut brain, &behaviour, &at(7)), AiDecision::MoveTowards(Point::new(17, 10)));
+}
+
+#[test]
+fn would_be_archers_without_a_weapon_close_in() {
+    let mut brain = MonsterBrain::default();
+    let behaviour = AiBehaviour{ attack_range: 5, keep_distance: 3, ..AiBehaviour::default() };
+
+    assert_eq!(think(&mut brain, &behaviour, &sees_player_at(2)), AiDecision::MoveTowards(Point::new(12, 10)));
+    assert_eq!(think(&mut brain, &behaviour, &sees_player_at(4)), AiDecision::MoveTowards(Point::new(14, 10)));
+    assert_eq!(think(&mut brain, &behaviour, &sees_player_at(1)), AiDecision::Attack);
+}
+
+#[test]
+fn alerted_monsters_come_looking() {
+    let mut brain = MonsterBrain::default();
+    let behaviour = AiBehaviour{ wanders: true, ..AiBehaviour::default() };
+    let mut perception = sees_player_at(12);
+    perception.can_see_player = false;
+
+    assert_eq!(think(&mut brain, &behaviour, &perception), AiDecision::Wander);
+
+    perception.alerted = true;
+    assert_eq!(think(&mut brain, &behaviour, &perception), AiDecision::MoveTowards(Point::new(22, 10)));
+    assert_eq!(brain.state, AiState::Chasing);
+}
+
+/// A 20x12 room with a wall down the middle, open only at the bottom.
+fn walled_room() -> Map {
+    let mut map = Map::new(1, 20, 12);
+    for y in 1..11 {
+        for x in 1..19 {
+            map.set_tile(x, y, TileType::Floor);
+        }
+    }
+    for y in 1..9 {
+        map.set_tile(10, y, TileType::Wall);
+    }
+    map.populate_blocked();
+    map
+}
+
+#[test]
+fn chasers_go_round_walls_to_the_player() {
+    let map = walled_room();
+    let mut chase_map = ChaseMap::new();
+    chase_map.update(&map, Point::new(15, 2));
+
+    // Straight at the player is into the wall; the way round is down through the gap.
+    let mut here = Point::new(5, 2);
+    for _ in 0..20 {
+        match chase_map.step_towards(&map, here) {
+            Some(next) => here = next,
+            None => break,
+        }
+    }
+    assert!(DistanceAlg::Pythagoras.distance2d(here, Point::new(15, 2)) < 1.5, "ended up at {:?}", here);
+}
+
+#[test]
+fn fleeing_monsters_get_further_away() {
+    let map = walled_room();
+    let mut chase_map = ChaseMap::new();
+    let player = Point::new(4, 5);
+    chase_map.update(&map, player);
+
+    let start = Point::new(5, 5);
+    let mut here = start;
+    for _ in 0..5 {
+        here = chase_map.step_away(&map, here).expect("there's room to run");
+    }
+    let before = chase_map.towards[map.xy_idx(start.x, start.y)];
+    let after = chase_map.towards[map.xy_idx(here.x, here.y)];
+    assert!(after > before + 3.0, "only got from {} to {} away", before, after);
+}
+
+#[test]
+fn chasers_do_not_step_onto_occupied_tiles() {
+    let mut map = walled_room();
+    let mut chase_map = ChaseMap::new();
+    chase_map.update(&map, Point::new(15, 5));
+
+    let best = chase_map.step_towards(&map, Point::new(12, 5)).unwrap();
+    assert_eq!(best, Point::new(13, 5));
+
+    // Another monster got there first this turn, so take the next best.
+    let idx = map.xy_idx(13, 5);
+    map.blocked_tiles[idx] = true;
+    let next_best = chase_map.step_towards(&map, Point::new(12, 5)).unwrap();
+    assert_ne!(next_best, Point::new(13, 5));
+    assert!(chase_map.towards[map.xy_idx(next_best.x, next_best.y)] < chase_map.towards[map.xy_idx(12, 5)]);
+
+    // Nobody steps onto the player, however close they are.
+    assert_ne!(chase_map.step_towards(&map, Point::new(14, 5)), Some(Point::new(15, 5)));
+}
+
+#[test]
+fn chase_map_is_only_rebuilt_when_the_player_or_layout_changes() {
+    let map = walled_room();
+    let mut chase_map = ChaseMap::new();
+
+    assert!(chase_map.update(&map, Point::new(15, 5)));
+    assert!(!chase_map.update(&map, Point::new(15, 5)));
+    assert!(chase_map.update(&map, Point::new(15, 6)));
+}
+
+#[test]
+fn unlocking_a_door_opens_a_shorter_way_through() {
+    let mut map = walled_room();
+    map.set_tile(10, 2, TileType::LockedDoor);
+    map.populate_blocked();
+    let mut chase_map = ChaseMap::new();
+    chase_map.update(&map, Point::new(15, 2));
+    let the_long_way = chase_map.towards[map.xy_idx(5, 2)];
+
+    let door = map.xy_idx(10, 2);
+    map.unlock_door(door);
+    assert!(chase_map.update(&map, Point::new(15, 2)));
+    assert!(chase_map.towards[map.xy_idx(5, 2)] < the_long_way);
+    assert_eq!(chase_map.step_towards(&map, Point::new(5, 2)), Some(Point::new(6, 2)));
+}
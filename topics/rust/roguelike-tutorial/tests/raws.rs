@@ -0,0 +1,98 @@
+use roguelike_tutorial::raws::{RawMaster,RawsError};
+
+fn raws_from(entities: &str, spawn_table: &str) -> Result<RawMaster, RawsError> {
+    let data = format!(r#"{{ "entities": [ {} ], "spawn_table": [ {} ] }}"#, entities, spawn_table);
+    RawMaster::from_json("test", &data)
+}
+
+/// Why these raws don't load.
+fn error_from(entities: &str, spawn_table: &str) -> RawsError {
+    match raws_from(entities, spawn_table) {
+        Ok(_) => panic!("raws loaded, but shouldn't have:\n{}\n{}", entities, spawn_table),
+        Err(e) => e,
+    }
+}
+
+const ORC: &str = r##"{ "name": "Orc", "glyph": "o", "fg": "#FF0000", "render_order": 1, "components": [ { "type": "Monster" } ] }"##;
+const ORC_SPAWN: &str = r#"{ "name": "Orc", "weight": 1 }"#;
+
+fn raws_with(components: &str) -> Result<RawMaster, RawsError> {
+    let gold = format!(r##"{{ "name": "Gold", "glyph": "$", "fg": "#FFD700", "render_order": 2, "components": [ {} ] }}"##, components);
+    raws_from(&gold, r#"{ "name": "Gold", "weight": 1 }"#)
+}
+
+#[test]
+fn good_raws_load() {
+    let raws = raws_from(ORC, ORC_SPAWN).expect("raws should load");
+    assert!(raws.template("Orc").is_some());
+}
+
+#[test]
+fn missing_file_is_an_io_error() {
+    let e = RawMaster::load("raws/no-such-file.json").err().expect("raws shouldn't load from a missing file");
+    assert!(matches!(e, RawsError::Io(ref path, _) if path == "raws/no-such-file.json"));
+    assert!(e.to_string().starts_with("unable to read raws/no-such-file.json: "), "{}", e);
+}
+
+#[test]
+fn malformed_json_is_a_parse_error() {
+    let e = RawMaster::from_json("test", r#"{ "entities": [ "#).err().expect("raws shouldn't load from broken json");
+    assert!(matches!(e, RawsError::Parse(ref path, _) if path == "test"));
+    assert!(e.to_string().starts_with("test is not a valid raws file: "), "{}", e);
+}
+
+#[test]
+fn unknown_component_types_are_a_parse_error() {
+    let flying = ORC.replace(r#"{ "type": "Monster" }"#, r#"{ "type": "Flying" }"#);
+    let e = error_from(&flying, ORC_SPAWN);
+    assert!(matches!(e, RawsError::Parse(..)));
+    assert!(e.to_string().starts_with("test is not a valid raws file: unknown variant `Flying`"), "{}", e);
+}
+
+#[test]
+fn names_must_be_unique() {
+    let e = error_from(&format!("{}, {}", ORC, ORC), ORC_SPAWN);
+    assert!(matches!(e, RawsError::DuplicateName(ref name) if name == "Orc"));
+    assert_eq!(e.to_string(), "entity \"Orc\" is defined more than once");
+}
+
+#[test]
+fn glyphs_must_be_one_character() {
+    let e = error_from(&ORC.replace(r#""glyph": "o""#, r#""glyph": "orc""#), ORC_SPAWN);
+    assert!(matches!(e, RawsError::BadGlyph{ ref entity, ref glyph } if entity == "Orc" && glyph == "orc"));
+    assert_eq!(e.to_string(), "entity \"Orc\" has glyph \"orc\", expected exactly one character");
+}
+
+#[test]
+fn colours_must_be_hex() {
+    let e = error_from(&ORC.replace("#FF0000", "red"), ORC_SPAWN);
+    assert!(matches!(e, RawsError::BadColour{ ref entity, ref colour } if entity == "Orc" && colour == "red"));
+    assert_eq!(e.to_string(), "entity \"Orc\" has colour \"red\", expected a hex colour like \"#FF00FF\"");
+
+    let bad_bg = ORC.replace(r#""render_order""#, r#""bg": "black", "render_order""#);
+    assert!(matches!(error_from(&bad_bg, ORC_SPAWN), RawsError::BadColour{ ref colour, .. } if colour == "black"));
+}
+
+#[test]
+fn spawns_must_be_defined_entities() {
+    let e = error_from(ORC, &format!(r#"{}, {{ "name": "Troll", "weight": 1 }}"#, ORC_SPAWN));
+    assert!(matches!(e, RawsError::UnknownSpawn(ref name) if name == "Troll"));
+    assert_eq!(e.to_string(), "spawn table references \"Troll\", which is not a defined entity");
+}
+
+#[test]
+fn spawn_weights_must_not_be_negative() {
+    assert!(raws_from(ORC, r#"{ "name": "Orc", "weight": 0 }"#).is_ok());
+    let e = error_from(ORC, r#"{ "name": "Orc", "weight": -1 }"#);
+    assert!(matches!(e, RawsError::BadWeight{ ref entity, weight: -1 } if entity == "Orc"));
+    assert_eq!(e.to_string(), "spawn table gives \"Orc\" a weight of -1, weights must not be negative");
+}
+
+#[test]
+fn gold_must_drop_at_least_one_coin() {
+    assert!(raws_with(r#"{ "type": "Item" }, { "type": "Gold", "amount": 20 }"#).is_ok());
+    assert!(matches!(raws_with(r#"{ "type": "Gold", "amount": 0 }"#), Err(RawsError::BadGold{ amount: 0, .. })));
+    assert!(matches!(raws_with(r#"{ "type": "Gold", "amount": -5 }"#), Err(RawsError::BadGold{ amount: -5, .. })));
+    let e = raws_with(r#"{ "type": "Gold", "amount": 0 }"#).err().expect("an empty gold pile shouldn't load");
+    assert_eq!(e.to_string(), "entity \"Gold\" drops up to 0 gold, it must be at least 1");
+}
@@ -0,0 +1,136 @@
+use std::fs;
+use rltk::Point;
+use specs::WorldExt;
+use roguelike_tutorial::components::{Experience,HungerClock,HungerState,InBackpack,Initiative,LightSource,Wallet};
+use roguelike_tutorial::initiative_system::NORMAL_SPEED;
+use roguelike_tutorial::map::{Map,TileType};
+use roguelike_tutorial::raws::{RawMaster,RAWS_FILE_NAME};
+use roguelike_tutorial::replay::{PlayerAction,state_hash};
+use roguelike_tutorial::saveload_system::{self,SaveError,SAVE_FORMAT_VERSION};
+use roguelike_tutorial::simulation::Simulation;
+use roguelike_tutorial::spawner::PLAYER_TORCH_RANGE;
+
+fn raws() -> RawMaster {
+    RawMaster::load(RAWS_FILE_NAME).expect("Unable to load raws")
+}
+
+/// Put `contents` in `slot`, as though the game had saved it there.
+fn write_slot(slot: &str, contents: &str) {
+    let path = saveload_system::slot_path(slot);
+    fs::create_dir_all(std::path::Path::new(&path).parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+fn clear_slot(slot: &str) {
+    let _ = fs::remove_file(saveload_system::slot_path(slot));
+}
+
+#[test]
+fn each_slot_loads_back_the_game_saved_in_it() {
+    let mut sim = Simulation::new_game(21, raws());
+    sim.act_all(&[PlayerAction::SkipTurn; 3]);
+    sim.save("Test Slot A").unwrap();
+    let slot_a = state_hash(sim.world());
+
+    sim.act_all(&[PlayerAction::SkipTurn; 5]);
+    sim.save("Test Slot B").unwrap();
+    let slot_b = state_hash(sim.world());
+    assert_ne!(slot_a, slot_b);
+
+    sim.act_all(&[PlayerAction::SkipTurn; 5]);
+    sim.load("Test Slot A").unwrap();
+    assert_eq!(state_hash(sim.world()), slot_a);
+    sim.load("Test Slot B").unwrap();
+    assert_eq!(state_hash(sim.world()), slot_b);
+
+    let metadata = saveload_system::read_metadata("Test Slot A").unwrap().unwrap();
+    assert_eq!((metadata.character_name.as_str(), metadata.depth, metadata.turns), ("Player", 1, 3));
+
+    clear_slot("Test Slot A");
+    clear_slot("Test Slot B");
+}
+
+#[test]
+fn levels_the_player_has_left_are_saved_too() {
+    let mut map = Map::new(1, 80, 43);
+    for y in 1..map.height - 1 {
+        for x in 1..map.width - 1 {
+            map.set_tile(x, y, TileType::Floor);
+        }
+    }
+    map.set_tile(5, 5, TileType::DownStairs);
+    let mut sim = Simulation::with_map(1, raws(), map, 5, 5);
+    sim.spawn("Dagger", 8, 5);
+    sim.act(PlayerAction::SkipTurn);
+    let revealed = sim.world().fetch::<Map>().revealed_tiles.clone();
+    sim.act(PlayerAction::Descend);
+    sim.save("Test Left Levels").unwrap();
+
+    let mut loaded = Simulation::new_game(2, raws());
+    loaded.load("Test Left Levels").unwrap();
+    assert_eq!(loaded.world().fetch::<Map>().depth, 2);
+    loaded.act(PlayerAction::Ascend);
+
+    assert_eq!(loaded.world().fetch::<Map>().depth, 1);
+    assert_eq!(loaded.world().fetch::<Map>().revealed_tiles, revealed);
+    let daggers = loaded.find("Dagger");
+    assert!(daggers.iter().any(|dagger| loaded.position(*dagger) == Some(Point::new(8, 5))), "the dagger should be where it was left");
+
+    clear_slot("Test Left Levels");
+}
+
+#[test]
+fn saves_from_the_original_game_are_upgraded_through_every_migration() {
+    // Written by the game as it was before saves had a version, with a health potion in the pack.
+    write_slot("Test Original Save", include_str!("data/v0-save.json"));
+    let mut sim = Simulation::new_game(1, raws());
+
+    sim.load("Test Original Save").unwrap();
+
+    let player = sim.player();
+    assert_eq!(sim.position(player), Some(Point::new(16, 27)));
+    assert_eq!(sim.hp(player), Some(30));
+    let potion = sim.find("Health Potion").into_iter()
+        .find(|item| sim.world().read_storage::<InBackpack>().get(*item).is_some_and(|pack| pack.owner == player));
+    assert!(potion.is_some(), "the potion should still be in the player's pack");
+    {
+        let world = sim.world();
+        assert_eq!(world.read_storage::<HungerClock>().get(player).unwrap().state, HungerState::Normal);
+        assert_eq!(world.read_storage::<Experience>().get(player).unwrap().level, 1);
+        assert_eq!(world.read_storage::<Initiative>().get(player).unwrap().speed, NORMAL_SPEED);
+        assert_eq!(world.read_storage::<Wallet>().get(player).unwrap().gold, 0);
+        assert_eq!(world.read_storage::<LightSource>().get(player).unwrap().range, PLAYER_TORCH_RANGE);
+    }
+    assert_eq!(saveload_system::read_metadata("Test Original Save").unwrap().unwrap().character_name, "Unknown");
+
+    // And it plays on from there.
+    sim.act(PlayerAction::SkipTurn);
+    assert!(sim.is_alive(player));
+
+    clear_slot("Test Original Save");
+}
+
+#[test]
+fn damaged_saves_are_an_error_and_leave_the_game_alone() {
+    let mut sim = Simulation::new_game(5, raws());
+    sim.save("Test Damaged").unwrap();
+    let saved = fs::read_to_string(saveload_system::slot_path("Test Damaged")).unwrap();
+    let before = state_hash(sim.world());
+
+    write_slot("Test Damaged", "this is not a save");
+    assert!(matches!(sim.load("Test Damaged"), Err(SaveError::Json(_))));
+
+    write_slot("Test Damaged", &saved[..saved.len() / 2]);
+    assert!(sim.load("Test Damaged").is_err());
+
+    write_slot("Test Damaged", "");
+    assert!(matches!(sim.load("Test Damaged"), Err(SaveError::Corrupt(_))));
+
+    write_slot("Test Damaged", &format!("{{\"version\":{},\"metadata\":{{}}}}", SAVE_FORMAT_VERSION + 1));
+    assert!(sim.load("Test Damaged").is_err());
+
+    assert!(matches!(sim.load("Test Missing"), Err(SaveError::NoSave(_))));
+    assert_eq!(state_hash(sim.world()), before);
+
+    clear_slot("Test Damaged");
+}
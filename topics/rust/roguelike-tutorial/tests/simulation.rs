@@ -0,0 +1,916 @@
+use rltk::{DistanceAlg,Point};
+use specs::{Entity,RunNow,WorldExt};
+use roguelike_tutorial::RunState;
+use roguelike_tutorial::constants::{VIEW_HEIGHT,MAP_WIDTH,MAP_HEIGHT,FINAL_DEPTH};
+use roguelike_tutorial::damage_system::delete_the_dead;
+use roguelike_tutorial::components::{AiState,CombatStats,Hidden,MonsterBrain,Experience,HungerClock,HungerState,StatBoost,StatusEffect,StatusEffectKind,StatusEffects,Stackable,InBackpack,Equipped,EntityMoved,LightSource,Position,Wallet};
+use roguelike_tutorial::gui::{InventoryView,InventorySort,ItemCategory,inventory_rows};
+use roguelike_tutorial::identification::Identification;
+use roguelike_tutorial::inventory_system::BACKPACK_SLOTS;
+use roguelike_tutorial::lighting_system::can_make_out;
+use roguelike_tutorial::map::{Map,TileType};
+use roguelike_tutorial::map_indexing_system::MapIndexingSystem;
+use roguelike_tutorial::map_builders::{self,Prefab,PREFAB_VAULTS};
+use roguelike_tutorial::morgue::{self,HighScore,RunStats,MAX_HIGH_SCORES,VICTORY_BONUS};
+use roguelike_tutorial::raws::{RawMaster,RAWS_FILE_NAME};
+use roguelike_tutorial::ranged_combat_system::line_of_fire;
+use roguelike_tutorial::replay::{PlayerAction,state_hash};
+use roguelike_tutorial::simulation::Simulation;
+use roguelike_tutorial::spawner::PLAYER_TORCH_RANGE;
+use roguelike_tutorial::travel::TravelGoal;
+use roguelike_tutorial::trigger_system::TriggerSystem;
+
+fn raws() -> RawMaster {
+    RawMaster::load(RAWS_FILE_NAME).expect("Unable to load raws")
+}
+
+/// A single open room covering the whole map.
+fn open_map() -> Map {
+    let mut map = Map::new(1, 80, 43);
+    for y in 1..map.height - 1 {
+        for x in 1..map.width - 1 {
+            map.set_tile(x, y, TileType::Floor);
+        }
+    }
+    map
+}
+
+fn arena() -> Simulation {
+    Simulation::with_map(1, raws(), open_map(), 5, 5)
+}
+
+#[test]
+fn monster_closes_in_on_visible_player() {
+    let mut sim = arena();
+    let goblin = sim.spawn("Goblin", 10, 5);
+    let player_pos = sim.position(sim.player()).unwrap();
+
+    sim.act(PlayerAction::SkipTurn);
+    let before = DistanceAlg::Pythagoras.distance2d(player_pos, sim.position(goblin).unwrap());
+    sim.act(PlayerAction::SkipTurn);
+    let after = DistanceAlg::Pythagoras.distance2d(player_pos, sim.position(goblin).unwrap());
+
+    assert!(after < before, "goblin should move towards the player ({} -> {})", before, after);
+}
+
+#[test]
+fn melee_trades_blows() {
+    let mut sim = arena();
+    let goblin = sim.spawn("Goblin", 6, 5);
+    sim.act(PlayerAction::SkipTurn);
+    let goblin_hp = sim.hp(goblin).unwrap();
+    let player_hp = sim.hp(sim.player()).unwrap();
+
+    sim.act(PlayerAction::Move{ dx: 1, dy: 0 });
+
+    assert!(sim.hp(goblin).unwrap() < goblin_hp);
+    assert!(sim.hp(sim.player()).unwrap() < player_hp);
+    assert!(sim.log_contains("Player hits Goblin"));
+}
+
+#[test]
+fn health_potion_heals_and_is_consumed() {
+    let mut sim = arena();
+    let potion = sim.spawn("Health Potion", 5, 5);
+    sim.act(PlayerAction::PickUp);
+    {
+        let player = sim.player();
+        let mut stats = sim.world_mut().write_storage::<CombatStats>();
+        stats.get_mut(player).unwrap().hp = 10;
+    }
+
+    sim.act(PlayerAction::UseItem{ item: potion.id(), target: None });
+
+    assert!(sim.hp(sim.player()).unwrap() > 10);
+    assert!(!sim.is_alive(potion));
+}
+
+/// The arena, with the way down under the player.
+fn arena_with_stairs() -> Simulation {
+    let mut map = open_map();
+    map.set_tile(5, 5, TileType::DownStairs);
+    Simulation::with_map(1, raws(), map, 5, 5)
+}
+
+#[test]
+fn levels_are_as_the_player_left_them() {
+    let mut sim = arena_with_stairs();
+    let goblin = sim.spawn("Goblin", 60, 30);
+    let dagger = sim.spawn("Dagger", 8, 5);
+    sim.act(PlayerAction::SkipTurn);
+    sim.world_mut().write_storage::<CombatStats>().get_mut(goblin).unwrap().hp = 3;
+    let left = (*sim.world().fetch::<Map>()).clone();
+    let goblin_pos = sim.position(goblin);
+
+    sim.act(PlayerAction::Descend);
+    assert_eq!(sim.world().fetch::<Map>().depth, 2);
+    assert_eq!(sim.position(goblin), None);
+    assert_eq!(sim.position(dagger), None);
+
+    sim.act(PlayerAction::Ascend);
+    {
+        let map = sim.world().fetch::<Map>();
+        assert_eq!(map.depth, 1);
+        assert!(map.tiles == left.tiles);
+        assert_eq!(map.revealed_tiles, left.revealed_tiles);
+    }
+    assert_eq!(sim.position(sim.player()), Some(Point::new(5, 5)));
+    assert_eq!(sim.position(goblin), goblin_pos);
+    assert_eq!(sim.hp(goblin), Some(3));
+    assert_eq!(sim.position(dagger), Some(Point::new(8, 5)));
+    assert!(sim.log_contains("You return to depth 1."));
+}
+
+#[test]
+fn poison_hurts_each_turn_then_wears_off() {
+    let mut sim = arena();
+    {
+        let player = sim.player();
+        let mut statuses = sim.world_mut().write_storage::<StatusEffects>();
+        StatusEffects::add(&mut statuses, player, StatusEffect{ kind: StatusEffectKind::Poison, turns: 3, magnitude: 2 });
+    }
+    let hp = sim.hp(sim.player()).unwrap();
+
+    // Walk rather than wait, so resting doesn't heal anything back.
+    sim.act_all(&[PlayerAction::Move{ dx: 1, dy: 0 }; 4]);
+
+    assert_eq!(sim.hp(sim.player()).unwrap(), hp - 6);
+    assert!(sim.log_contains("You are no longer poisoned."));
+    assert!(sim.world().read_storage::<StatusEffects>().get(sim.player()).is_none());
+}
+
+#[test]
+fn status_effects_stack_by_kind() {
+    let mut status = StatusEffects::default();
+    status.apply(StatusEffect{ kind: StatusEffectKind::Poison, turns: 3, magnitude: 1 });
+    status.apply(StatusEffect{ kind: StatusEffectKind::Poison, turns: 2, magnitude: 2 });
+    status.apply(StatusEffect{ kind: StatusEffectKind::Confusion, turns: 2, magnitude: 0 });
+    status.apply(StatusEffect{ kind: StatusEffectKind::Confusion, turns: 2, magnitude: 0 });
+    status.apply(StatusEffect{ kind: StatusEffectKind::Slow, turns: 4, magnitude: 0 });
+    status.apply(StatusEffect{ kind: StatusEffectKind::Haste, turns: 4, magnitude: 0 });
+
+    let poison = status.get(StatusEffectKind::Poison).unwrap();
+    assert_eq!((poison.turns, poison.magnitude), (3, 3));
+    assert_eq!(status.get(StatusEffectKind::Confusion).unwrap().turns, 4);
+    assert!(!status.has(StatusEffectKind::Slow));
+    assert_eq!(status.adjust_speed(100), 200);
+}
+
+/// How many turns `monster` takes while the player takes `player_turns`, with the player under `player_status`.
+/// A harmless effect that outlasts the test counts them, since effects count down on their owner's turns.
+fn monster_turns(monster: &str, player_turns: usize, player_status: Option<StatusEffectKind>) -> i32 {
+    const FOREVER: i32 = 1000;
+    let mut sim = arena();
+    // Far enough away that it leaves the player alone.
+    let monster = sim.spawn(monster, 70, 35);
+    {
+        let player = sim.player();
+        let mut statuses = sim.world_mut().write_storage::<StatusEffects>();
+        StatusEffects::add(&mut statuses, monster, StatusEffect{ kind: StatusEffectKind::Regeneration, turns: FOREVER, magnitude: 0 });
+        if let Some(kind) = player_status {
+            StatusEffects::add(&mut statuses, player, StatusEffect{ kind, turns: FOREVER, magnitude: 0 });
+        }
+    }
+
+    for _turn in 0..player_turns {
+        sim.act(PlayerAction::SkipTurn);
+    }
+
+    let statuses = sim.world().read_storage::<StatusEffects>();
+    FOREVER - statuses.get(monster).unwrap().get(StatusEffectKind::Regeneration).unwrap().turns
+}
+
+#[test]
+fn monsters_act_as_often_as_their_speed_allows() {
+    // Goblins are quick, orcs slow.
+    let goblin = monster_turns("Goblin", 50, None);
+    let orc = monster_turns("Orc", 50, None);
+
+    assert!((58..=62).contains(&goblin), "a goblin at speed 120 took {} turns to the player's 50", goblin);
+    assert!((38..=42).contains(&orc), "an orc at speed 80 took {} turns to the player's 50", orc);
+}
+
+#[test]
+fn haste_and_slow_change_how_often_the_player_acts() {
+    let normal = monster_turns("Goblin", 50, None);
+    let hasted = monster_turns("Goblin", 50, Some(StatusEffectKind::Haste));
+    let slowed = monster_turns("Goblin", 50, Some(StatusEffectKind::Slow));
+
+    assert!((hasted - normal / 2).abs() <= 2, "hasted, the goblin took {} turns to the player's 50", hasted);
+    assert!((slowed - normal * 2).abs() <= 2, "slowed, the goblin took {} turns to the player's 50", slowed);
+}
+
+#[test]
+fn paralysed_monster_stays_put() {
+    let mut sim = arena();
+    let goblin = sim.spawn("Goblin", 10, 5);
+    {
+        let mut statuses = sim.world_mut().write_storage::<StatusEffects>();
+        StatusEffects::add(&mut statuses, goblin, StatusEffect{ kind: StatusEffectKind::Paralysis, turns: 2, magnitude: 0 });
+    }
+    let start = sim.position(goblin).unwrap();
+
+    sim.act_all(&[PlayerAction::SkipTurn, PlayerAction::SkipTurn]);
+    assert_eq!(sim.position(goblin).unwrap(), start);
+
+    sim.act(PlayerAction::SkipTurn);
+    assert_ne!(sim.position(goblin).unwrap(), start);
+}
+
+#[test]
+fn starving_hurts_and_food_fixes_it() {
+    let mut sim = arena();
+    let rations = sim.spawn("Rations", 5, 5);
+    sim.act(PlayerAction::PickUp);
+    {
+        let player = sim.player();
+        let mut clocks = sim.world_mut().write_storage::<HungerClock>();
+        *clocks.get_mut(player).unwrap() = HungerClock{ state: HungerState::Starving, duration: 0 };
+    }
+    let hp = sim.hp(sim.player()).unwrap();
+
+    // Resting doesn't heal on an empty stomach, and starving costs a point each turn.
+    sim.act(PlayerAction::SkipTurn);
+    assert_eq!(sim.hp(sim.player()).unwrap(), hp - 1);
+
+    sim.act(PlayerAction::UseItem{ item: rations.id(), target: None });
+    let state = sim.world().read_storage::<HungerClock>().get(sim.player()).unwrap().state;
+    assert_eq!(state, HungerState::WellFed);
+    assert!(!sim.is_alive(rations));
+}
+
+#[test]
+fn killing_blow_levels_up_the_player() {
+    let mut sim = arena();
+    let goblin = sim.spawn("Goblin", 6, 5);
+    sim.act(PlayerAction::SkipTurn);
+    {
+        let player = sim.player();
+        sim.world_mut().write_storage::<CombatStats>().get_mut(goblin).unwrap().hp = 1;
+        sim.world_mut().write_storage::<Experience>().get_mut(player).unwrap().xp = 45;
+    }
+    let max_hp = sim.world().read_storage::<CombatStats>().get(sim.player()).unwrap().max_hp;
+
+    sim.act(PlayerAction::Move{ dx: 1, dy: 0 });
+
+    assert!(!sim.is_alive(goblin));
+    {
+        let experience = sim.world().read_storage::<Experience>();
+        let xp = experience.get(sim.player()).unwrap();
+        assert_eq!((xp.level, xp.xp, xp.pending_boosts), (2, 5, 1));
+    }
+    assert!(*sim.world().fetch::<RunState>() == RunState::LevelUp);
+
+    sim.act(PlayerAction::LevelUp{ boost: StatBoost::Strength });
+
+    let stats = sim.world().read_storage::<CombatStats>().get(sim.player()).unwrap().clone();
+    assert_eq!(stats.power, 6);
+    assert!(stats.max_hp > max_hp);
+    assert!(*sim.world().fetch::<RunState>() == RunState::AwaitingInput);
+}
+
+#[test]
+fn kills_are_counted_by_name() {
+    let mut sim = arena();
+    let goblin = sim.spawn("Goblin", 6, 5);
+    sim.act(PlayerAction::SkipTurn);
+    sim.world_mut().write_storage::<CombatStats>().get_mut(goblin).unwrap().hp = 1;
+
+    sim.act(PlayerAction::Move{ dx: 1, dy: 0 });
+
+    assert!(!sim.is_alive(goblin));
+    let stats = sim.world().fetch::<RunStats>();
+    assert_eq!(stats.kills.get("Goblin"), Some(&1));
+    assert_eq!(stats.total_kills(), 1);
+}
+
+#[test]
+fn dying_ends_the_run_and_says_what_did_it() {
+    let mut sim = arena();
+    let rations = sim.spawn("Rations", 5, 5);
+    sim.act(PlayerAction::PickUp);
+    let goblin = sim.spawn("Goblin", 6, 5);
+    sim.act(PlayerAction::SkipTurn);
+    {
+        let player = sim.player();
+        let mut stats = sim.world_mut().write_storage::<CombatStats>();
+        stats.get_mut(player).unwrap().hp = 1;
+        stats.get_mut(goblin).unwrap().hp = 100;
+    }
+
+    sim.act(PlayerAction::Move{ dx: 1, dy: 0 });
+
+    assert!(*sim.world().fetch::<RunState>() == RunState::GameOver);
+    assert_eq!(sim.world().fetch::<RunStats>().cause_of_death, Some("Goblin".to_string()));
+    let morgue = morgue::morgue_text(sim.world(), 0);
+    assert!(morgue.contains("Killed by Goblin, on depth 1."));
+    assert!(morgue.contains("Rations"), "inventory missing from:\n{}", morgue);
+    assert!(sim.is_alive(rations));
+}
+
+#[test]
+fn death_is_only_announced_once() {
+    let mut sim = arena();
+    let player = sim.player();
+    sim.world_mut().write_storage::<CombatStats>().get_mut(player).unwrap().hp = 0;
+
+    // Walk rather than wait, so resting doesn't heal anything back.
+    sim.act(PlayerAction::Move{ dx: 1, dy: 0 });
+    assert!(*sim.world().fetch::<RunState>() == RunState::GameOver);
+    // The game over screen clears away the dead every frame.
+    for _frame in 0..3 {
+        delete_the_dead(sim.world_mut());
+    }
+
+    let deaths: Vec<String> = sim.log().into_iter().filter(|entry| entry.starts_with("You are dead")).collect();
+    assert_eq!(deaths, vec!["You are dead".to_string()]);
+}
+
+fn high_score(score: i32) -> HighScore {
+    HighScore{ name: "Player".to_string(), score, depth: 1, turns: 10, cause: "Goblin".to_string(), timestamp: 0, won: false }
+}
+
+#[test]
+fn high_scores_are_kept_best_first() {
+    let mut scores = Vec::new();
+    assert_eq!(morgue::add_high_score(&mut scores, high_score(100)), Some(0));
+    assert_eq!(morgue::add_high_score(&mut scores, high_score(300)), Some(0));
+    assert_eq!(morgue::add_high_score(&mut scores, high_score(200)), Some(1));
+    let order: Vec<i32> = scores.iter().map(|entry| entry.score).collect();
+    assert_eq!(order, vec![300, 200, 100]);
+
+    for _ in 0..MAX_HIGH_SCORES {
+        morgue::add_high_score(&mut scores, high_score(500));
+    }
+    assert_eq!(scores.len(), MAX_HIGH_SCORES);
+    assert_eq!(morgue::add_high_score(&mut scores, high_score(50)), None);
+    assert!(scores.iter().all(|entry| entry.score == 500));
+}
+
+#[test]
+fn shots_stop_at_walls_and_whatever_is_in_the_way() {
+    let mut map = open_map();
+    map.set_tile(8, 5, TileType::Wall);
+    map.populate_blocked();
+
+    let path = line_of_fire(&map, Point::new(5, 5), Point::new(10, 5));
+    assert_eq!(path, vec![Point::new(6, 5), Point::new(7, 5)]);
+
+    let idx = map.xy_idx(6, 5);
+    map.blocked_tiles[idx] = true;
+    assert_eq!(line_of_fire(&map, Point::new(5, 5), Point::new(10, 5)).last(), Some(&Point::new(6, 5)));
+}
+
+#[test]
+fn thrown_knives_fly_at_the_target_and_are_used_up() {
+    let mut sim = arena();
+    let knives = sim.spawn("Throwing Knife", 5, 5);
+    let goblin = sim.spawn("Goblin", 8, 5);
+    sim.act(PlayerAction::PickUp);
+
+    let target = sim.position(goblin).unwrap();
+    sim.act(PlayerAction::Fire{ ammo: knives.id(), target });
+
+    assert_eq!(sim.world().read_storage::<Stackable>().get(knives).unwrap().count, 3);
+    assert!(sim.log().iter().any(|entry| entry.starts_with("Player's Throwing Knife") && entry.contains("Goblin")));
+}
+
+#[test]
+fn picked_up_ammo_joins_the_stack() {
+    let mut sim = arena();
+    sim.spawn("Arrow", 5, 5);
+    sim.act(PlayerAction::PickUp);
+    sim.spawn("Arrow", 5, 5);
+    sim.act(PlayerAction::PickUp);
+
+    let arrows = sim.find("Arrow");
+    assert_eq!(arrows.len(), 1);
+    assert_eq!(sim.world().read_storage::<Stackable>().get(arrows[0]).unwrap().count, 24);
+}
+
+#[test]
+fn potions_stack_and_are_drunk_one_at_a_time() {
+    let mut sim = arena();
+    for _potion in 0..3 {
+        sim.spawn("Health Potion", 5, 5);
+        sim.act(PlayerAction::PickUp);
+    }
+    let potions = sim.find("Health Potion");
+    assert_eq!(potions.len(), 1);
+
+    sim.act(PlayerAction::UseItem{ item: potions[0].id(), target: None });
+    assert!(sim.is_alive(potions[0]));
+    assert_eq!(sim.world().read_storage::<Stackable>().get(potions[0]).unwrap().count, 2);
+}
+
+#[test]
+fn a_full_pack_takes_no_more_but_stacks_still_fit() {
+    let mut sim = arena();
+    sim.spawn("Arrow", 5, 5);
+    sim.act(PlayerAction::PickUp);
+    for _item in 1..BACKPACK_SLOTS {
+        sim.spawn("Dagger", 5, 5);
+        sim.act(PlayerAction::PickUp);
+    }
+
+    let shield = sim.spawn("Shield", 5, 5);
+    sim.act(PlayerAction::PickUp);
+    assert!(sim.log_contains("Your pack is full."));
+    assert_eq!(sim.position(shield), Some(Point::new(5, 5)));
+
+    sim.world_mut().delete_entity(shield).expect("Unable to delete shield");
+    sim.spawn("Arrow", 5, 5);
+    sim.act(PlayerAction::PickUp);
+    let arrows = sim.find("Arrow");
+    assert_eq!(sim.world().read_storage::<Stackable>().get(arrows[0]).unwrap().count, 24);
+}
+
+#[test]
+fn gear_stays_on_when_the_pack_is_too_full_to_take_it() {
+    let mut sim = arena();
+    let shield = sim.spawn("Shield", 5, 5);
+    sim.act(PlayerAction::PickUp);
+    sim.act(PlayerAction::UseItem{ item: shield.id(), target: None });
+    for _item in 0..BACKPACK_SLOTS {
+        sim.spawn("Dagger", 5, 5);
+        sim.act(PlayerAction::PickUp);
+    }
+
+    sim.act(PlayerAction::UseItem{ item: shield.id(), target: None });
+    assert!(sim.log_contains("There's no room in your pack for Shield."));
+    assert!(sim.world().read_storage::<Equipped>().get(shield).is_some());
+    assert!(sim.world().read_storage::<InBackpack>().get(shield).is_none());
+}
+
+#[test]
+fn magic_items_go_by_made_up_names_until_used() {
+    let mut sim = arena();
+    let alias = sim.world().fetch::<Identification>().name("Health Potion");
+    assert_ne!(alias, "Health Potion");
+    assert_ne!(alias, sim.world().fetch::<Identification>().name("Haste Potion"));
+
+    let potion = sim.spawn("Health Potion", 5, 5);
+    sim.act(PlayerAction::PickUp);
+    assert!(sim.log_contains(&format!("You pick up the {}.", alias)));
+
+    sim.act(PlayerAction::UseItem{ item: potion.id(), target: None });
+    assert!(sim.log_contains(&format!("The {} was a Health Potion!", alias)));
+    assert_eq!(sim.world().fetch::<Identification>().name("Health Potion"), "Health Potion");
+}
+
+#[test]
+fn inventory_can_be_filtered_and_sorted() {
+    let mut sim = arena();
+    for name in ["Rations", "Dagger", "Rations", "Arrow", "Health Potion"].iter() {
+        sim.spawn(name, 5, 5);
+        sim.act(PlayerAction::PickUp);
+    }
+
+    let by_category: Vec<ItemCategory> = inventory_rows(sim.world(), &InventoryView::default(), true).iter().map(|row| row.category).collect();
+    assert_eq!(by_category, vec![ItemCategory::Potion, ItemCategory::Food, ItemCategory::Equipment, ItemCategory::Ammo]);
+
+    let food = InventoryView{ filter: Some(ItemCategory::Food), ..InventoryView::default() };
+    let rows = inventory_rows(sim.world(), &food, true);
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].label, "Rations (2)");
+
+    let by_count = InventoryView{ sort: InventorySort::Count, ..InventoryView::default() };
+    assert_eq!(inventory_rows(sim.world(), &by_count, true)[0].label, "Arrow (12)");
+}
+
+fn gold(sim: &Simulation) -> i32 {
+    sim.world().read_storage::<Wallet>().get(sim.player()).unwrap().gold
+}
+
+fn owner(sim: &Simulation, item: Entity) -> Option<Entity> {
+    sim.world().read_storage::<InBackpack>().get(item).map(|pack| pack.owner)
+}
+
+#[test]
+fn gold_goes_in_the_wallet() {
+    let mut sim = arena();
+    let pile = sim.spawn("Gold", 5, 5);
+    sim.act(PlayerAction::PickUp);
+
+    assert!(gold(&sim) > 0);
+    assert!(!sim.is_alive(pile));
+    assert!(sim.log_contains(&format!("You pick up {} gold.", gold(&sim))));
+}
+
+#[test]
+fn shopkeepers_buy_and_sell() {
+    let mut sim = arena();
+    let vendor = sim.spawn("Shopkeeper", 6, 5);
+    let potion = sim.spawn("Health Potion", 6, 5);
+    sim.world_mut().write_storage::<InBackpack>().insert(potion, InBackpack{ owner: vendor }).expect("Unable to stock vendor");
+    sim.world_mut().write_storage::<Position>().remove(potion);
+
+    sim.act(PlayerAction::Buy{ vendor: vendor.id(), item: potion.id() });
+    assert!(sim.log_contains("You can't afford"));
+    assert_eq!(owner(&sim, potion), Some(vendor));
+
+    let player = sim.player();
+    sim.world_mut().write_storage::<Wallet>().get_mut(player).unwrap().gold = 100;
+    sim.act(PlayerAction::Buy{ vendor: vendor.id(), item: potion.id() });
+    assert_eq!(owner(&sim, potion), Some(sim.player()));
+    assert_eq!(gold(&sim), 75);
+
+    sim.act(PlayerAction::Sell{ vendor: vendor.id(), item: potion.id() });
+    assert_eq!(owner(&sim, potion), Some(vendor));
+    assert_eq!(gold(&sim), 87);
+    assert!(matches!(*sim.world().fetch::<RunState>(), RunState::Shop{ .. }));
+}
+
+#[test]
+fn walking_into_a_shopkeeper_asks_before_attacking() {
+    let mut sim = arena();
+    let vendor = sim.spawn("Shopkeeper", 6, 5);
+    let hp = sim.hp(vendor).unwrap();
+
+    sim.act(PlayerAction::Move{ dx: 1, dy: 0 });
+    assert!(*sim.world().fetch::<RunState>() == RunState::ConfirmAttack{ target: vendor });
+    assert_eq!(sim.hp(vendor), Some(hp));
+
+    sim.act(PlayerAction::Attack{ target: vendor.id() });
+    assert!(sim.hp(vendor).unwrap() < hp);
+}
+
+#[test]
+fn shopkeepers_keep_to_themselves() {
+    let mut sim = arena();
+    let vendor = sim.spawn("Shopkeeper", 8, 5);
+    let hp = sim.hp(sim.player()).unwrap();
+
+    for _turn in 0..10 {
+        sim.act(PlayerAction::SkipTurn);
+    }
+    assert_eq!(sim.position(vendor), Some(Point::new(8, 5)));
+    assert_eq!(sim.hp(sim.player()), Some(hp));
+}
+
+#[test]
+fn a_dead_shopkeepers_stock_is_left_where_they_fell() {
+    let mut sim = arena();
+    let vendor = sim.spawn("Shopkeeper", 8, 5);
+    let potion = sim.spawn("Health Potion", 8, 5);
+    sim.world_mut().write_storage::<InBackpack>().insert(potion, InBackpack{ owner: vendor }).expect("Unable to stock vendor");
+    sim.world_mut().write_storage::<Position>().remove(potion);
+    sim.world_mut().write_storage::<CombatStats>().get_mut(vendor).unwrap().hp = 0;
+
+    sim.act(PlayerAction::SkipTurn);
+
+    assert!(!sim.is_alive(vendor));
+    assert!(sim.is_alive(potion));
+    assert_eq!(owner(&sim, potion), None);
+    assert_eq!(sim.position(potion), Some(Point::new(8, 5)));
+}
+
+#[test]
+fn travel_walks_to_the_chosen_tile() {
+    let mut sim = arena();
+    sim.travel(TravelGoal::To(Point::new(15, 9)));
+
+    assert_eq!(sim.position(sim.player()), Some(Point::new(15, 9)));
+}
+
+#[test]
+fn auto_explore_reveals_the_whole_level() {
+    let mut sim = arena();
+    sim.travel(TravelGoal::Explore);
+
+    let map = sim.world().fetch::<Map>();
+    assert!((0..map.tiles.len()).all(|idx| map.tiles[idx] == TileType::Wall || map.revealed_tiles[idx]));
+    assert!(sim.log_contains("nowhere left to explore"));
+}
+
+#[test]
+fn auto_explore_stops_when_a_monster_comes_into_view() {
+    let mut sim = arena();
+    let goblin = sim.spawn("Goblin", 60, 30);
+    sim.act(PlayerAction::SkipTurn);
+    sim.travel(TravelGoal::Explore);
+
+    assert!(sim.log_contains("You stop: a Goblin is in view."));
+    assert!(sim.is_alive(goblin));
+    let map = sim.world().fetch::<Map>();
+    assert!((0..map.tiles.len()).any(|idx| map.tiles[idx] != TileType::Wall && !map.revealed_tiles[idx]));
+}
+
+fn tile_at(sim: &Simulation, x: i32, y: i32) -> TileType {
+    let map = sim.world().fetch::<Map>();
+    map.tiles[map.xy_idx(x, y)]
+}
+
+#[test]
+fn walking_into_a_door_opens_it() {
+    let mut map = open_map();
+    map.set_tile(6, 5, TileType::Door);
+    let mut sim = Simulation::with_map(1, raws(), map, 5, 5);
+
+    sim.act(PlayerAction::Move{ dx: 1, dy: 0 });
+    assert!(tile_at(&sim, 6, 5) == TileType::OpenDoor);
+    assert_eq!(sim.position(sim.player()), Some(Point::new(5, 5)));
+
+    sim.act(PlayerAction::Move{ dx: 1, dy: 0 });
+    assert_eq!(sim.position(sim.player()), Some(Point::new(6, 5)));
+}
+
+#[test]
+fn locked_doors_need_a_key() {
+    let mut map = open_map();
+    map.set_tile(6, 5, TileType::LockedDoor);
+    let mut sim = Simulation::with_map(1, raws(), map, 5, 5);
+
+    sim.act(PlayerAction::Move{ dx: 1, dy: 0 });
+    assert!(sim.log_contains("The door is locked."));
+    assert!(tile_at(&sim, 6, 5) == TileType::LockedDoor);
+
+    let key = sim.spawn("Vault Key", 5, 5);
+    sim.act(PlayerAction::PickUp);
+    sim.act(PlayerAction::Move{ dx: 1, dy: 0 });
+    assert!(tile_at(&sim, 6, 5) == TileType::OpenDoor);
+    assert!(!sim.is_alive(key));
+}
+
+#[test]
+fn monsters_open_doors() {
+    let mut map = open_map();
+    for y in 1..map.height - 1 {
+        map.set_tile(8, y, TileType::Wall);
+    }
+    map.set_tile(8, 5, TileType::Door);
+    let mut sim = Simulation::with_map(1, raws(), map, 5, 5);
+    let goblin = sim.spawn("Goblin", 10, 5);
+    {
+        let mut brains = sim.world_mut().write_storage::<MonsterBrain>();
+        let brain = brains.get_mut(goblin).unwrap();
+        brain.state = AiState::Chasing;
+        brain.last_known_player = Some(Point::new(5, 5));
+    }
+
+    sim.act_all(&[PlayerAction::SkipTurn, PlayerAction::SkipTurn, PlayerAction::SkipTurn, PlayerAction::SkipTurn]);
+
+    assert!(tile_at(&sim, 8, 5) == TileType::OpenDoor);
+}
+
+#[test]
+fn traps_go_off_when_stepped_on_and_are_revealed() {
+    let mut sim = arena();
+    let trap = sim.spawn("Spike Trap", 6, 5);
+    sim.act(PlayerAction::SkipTurn);
+    let hp = sim.hp(sim.player()).unwrap();
+
+    sim.act(PlayerAction::Move{ dx: 1, dy: 0 });
+
+    assert_eq!(sim.hp(sim.player()), Some(hp - 6));
+    assert!(sim.world().read_storage::<Hidden>().get(trap).is_none());
+    assert!(sim.log_contains("You set off a Spike Trap!"));
+}
+
+#[test]
+fn teleported_things_land_apart_and_are_indexed_where_they_land() {
+    let mut sim = arena();
+    let goblins = [sim.spawn("Goblin", 20, 20), sim.spawn("Goblin", 22, 20)];
+    sim.spawn("Teleport Trap", 20, 20);
+    sim.spawn("Teleport Trap", 22, 20);
+    sim.world_mut().maintain();
+    MapIndexingSystem{}.run_now(sim.world());
+
+    // Both step onto their traps in the same tick.
+    for goblin in goblins.iter() {
+        sim.world_mut().write_storage::<EntityMoved>().insert(*goblin, EntityMoved{}).expect("Unable to insert marker");
+    }
+    TriggerSystem{}.run_now(sim.world());
+
+    let landed: Vec<Point> = goblins.iter().map(|goblin| sim.position(*goblin).unwrap()).collect();
+    assert_ne!(landed[0], landed[1]);
+    let map = sim.world().fetch::<Map>();
+    for (goblin, (from, to)) in goblins.iter().zip([Point::new(20, 20), Point::new(22, 20)].iter().zip(landed.iter())) {
+        assert_ne!(from, to);
+        let (from_idx, to_idx) = (map.xy_idx(from.x, from.y), map.xy_idx(to.x, to.y));
+        assert!(!map.tile_content[from_idx].contains(goblin) && !map.blocked_tiles[from_idx]);
+        assert!(map.tile_content[to_idx].contains(goblin) && map.blocked_tiles[to_idx]);
+    }
+}
+
+#[test]
+fn searching_finds_nearby_traps() {
+    let mut sim = arena();
+    let trap = sim.spawn("Spike Trap", 6, 6);
+    for _ in 0..20 {
+        if sim.world().read_storage::<Hidden>().get(trap).is_none() {
+            break;
+        }
+        sim.act(PlayerAction::Search);
+    }
+
+    assert!(sim.world().read_storage::<Hidden>().get(trap).is_none());
+    assert!(sim.log_contains("You find a Spike Trap!"));
+}
+
+#[test]
+fn vault_keys_are_left_where_the_player_can_reach_them() {
+    let mut vaults = 0;
+    for seed in 1..=20 {
+        let sim = Simulation::new_game(seed, raws());
+        let mut map = (*sim.world().fetch::<Map>()).clone();
+        if !map.tiles.contains(&TileType::LockedDoor) {
+            continue;
+        }
+        vaults += 1;
+
+        let keys = sim.find("Vault Key");
+        assert_eq!(keys.len(), 1, "seed {} should have one key for its vault", seed);
+
+        // Ignore monsters in the way.
+        map.populate_blocked();
+        let player = sim.position(sim.player()).unwrap();
+        let key = sim.position(keys[0]).unwrap();
+        let dijkstra = rltk::DijkstraMap::new(map.width as usize, map.height as usize, &[map.xy_idx(player.x, player.y)], &map, 2000.0);
+        assert!(dijkstra.map[map.xy_idx(key.x, key.y)] < f32::MAX, "seed {} has its key out of reach", seed);
+    }
+
+    assert!(vaults > 0, "no vaults generated");
+}
+
+#[test]
+fn same_seed_and_actions_give_same_state() {
+    let actions = [
+        PlayerAction::Move{ dx: 1, dy: 0 },
+        PlayerAction::SkipTurn,
+        PlayerAction::Move{ dx: 0, dy: 1 },
+        PlayerAction::PickUp,
+    ];
+
+    let mut first = Simulation::new_game(1234, raws());
+    first.act_all(&actions);
+    let mut second = Simulation::new_game(1234, raws());
+    second.act_all(&actions);
+
+    assert_eq!(state_hash(first.world()), state_hash(second.world()));
+}
+
+#[test]
+fn screen_shows_the_map_and_hud() {
+    let sim = arena();
+    let screen = sim.screen();
+
+    assert_eq!(screen.line(5).chars().nth(5), Some('@'));
+    assert!(screen.line(VIEW_HEIGHT).starts_with("┌─Depth: 1──HP: 30 / 30"));
+    assert!((VIEW_HEIGHT + 1 .. VIEW_HEIGHT + 6).any(|y| screen.line(y).contains("Welcome to Rusty Roguelike")));
+    let status_line = screen.line(VIEW_HEIGHT + 6);
+    assert!(status_line.contains("Gold: 0"));
+    assert!(status_line.contains("Well Fed"));
+}
+
+fn light_at(sim: &Simulation, x: i32, y: i32) -> f32 {
+    let map = sim.world().fetch::<Map>();
+    map.light_at(map.xy_idx(x, y))
+}
+
+#[test]
+fn the_players_torch_fades_with_distance() {
+    let sim = arena();
+
+    assert_eq!(light_at(&sim, 5, 5), 1.0);
+    assert!(light_at(&sim, 8, 5) < light_at(&sim, 6, 5));
+    assert_eq!(light_at(&sim, 5 + PLAYER_TORCH_RANGE + 1, 5), 0.0);
+}
+
+#[test]
+fn things_in_the_dark_are_only_seen_once_lit() {
+    let mut sim = arena();
+    sim.spawn("Dagger", 12, 5);
+    let player = Point::new(5, 5);
+    {
+        let map = sim.world().fetch::<Map>();
+        assert!(map.visible_tiles[map.xy_idx(12, 5)]);
+        assert!(!can_make_out(&map, player, Point::new(12, 5)));
+    }
+    assert_ne!(sim.screen().line(5).chars().nth(12), Some('/'));
+
+    sim.spawn("Brazier", 12, 6);
+    sim.act(PlayerAction::SkipTurn);
+
+    assert!(can_make_out(&sim.world().fetch::<Map>(), player, Point::new(12, 5)));
+    assert_eq!(sim.screen().line(5).chars().nth(12), Some('/'));
+}
+
+#[test]
+fn monsters_cant_spot_the_player_in_the_dark() {
+    let mut sim = arena();
+    let player = sim.player();
+    sim.world_mut().write_storage::<LightSource>().remove(player);
+    let goblin = sim.spawn("Goblin", 11, 5);
+
+    sim.act_all(&[PlayerAction::SkipTurn, PlayerAction::SkipTurn]);
+
+    let brain = sim.world().read_storage::<MonsterBrain>().get(goblin).unwrap().clone();
+    assert_ne!(brain.state, AiState::Chasing);
+    assert_eq!(brain.last_known_player, None);
+}
+
+#[test]
+fn light_is_only_worked_out_again_when_something_changes() {
+    let mut sim = arena();
+    // Scribble on the light map. Nothing has moved, so it's left alone...
+    sim.world_mut().fetch_mut::<Map>().light[0] = 0.5;
+    sim.act(PlayerAction::SkipTurn);
+    assert_eq!(light_at(&sim, 0, 0), 0.5);
+
+    // ...until the torch moves.
+    sim.act(PlayerAction::Move{ dx: 1, dy: 0 });
+    assert_eq!(light_at(&sim, 0, 0), 0.0);
+}
+
+/// Whether `idx` can be walked to from `from` on `map`, opening doors on the way.
+fn reachable(map: &Map, from: usize, idx: usize) -> bool {
+    let dijkstra = rltk::DijkstraMap::new(map.width as usize, map.height as usize, &[from], map, 2000.0);
+    dijkstra.map[idx] < f32::MAX
+}
+
+#[test]
+fn the_final_depth_is_the_boss_lair() {
+    let mut rng = rltk::RandomNumberGenerator::seeded(1);
+    let mut builder = map_builders::random_builder(FINAL_DEPTH, MAP_WIDTH, MAP_HEIGHT, &mut rng);
+    builder.build_map(&mut rng);
+    let map = builder.get_map();
+    let start = builder.get_starting_position();
+    let start_idx = map.xy_idx(start.x, start.y);
+    let spawns = builder.get_spawn_list();
+
+    assert!(!map.tiles.contains(&TileType::DownStairs), "nowhere further down to go");
+    let raws = raws();
+    for (idx, name) in spawns.iter() {
+        assert!(raws.template(name).is_some(), "the lair spawns {}, which isn't in the raws", name);
+        if name != "Brazier" {
+            assert!(reachable(&map, start_idx, *idx), "{} is out of reach", name);
+        }
+    }
+    assert!(spawns.iter().any(|(_idx, name)| name == "Lich King"));
+    assert!(spawns.iter().any(|(_idx, name)| name == "Amulet of the Ancients"));
+}
+
+#[test]
+fn prefab_vaults_can_be_walked_all_the_way_round() {
+    let raws = raws();
+    for template in PREFAB_VAULTS.iter() {
+        let prefab = Prefab::parse(template);
+        let mut map = Map::new(1, prefab.width + 4, prefab.height + 4);
+        for y in 1..map.height - 1 {
+            for x in 1..map.width - 1 {
+                map.set_tile(x, y, TileType::Floor);
+            }
+        }
+
+        let spawns = prefab.stamp(&mut map, 2, 2);
+
+        for (_idx, name) in spawns.iter() {
+            assert!(raws.template(name).is_some(), "a prefab spawns {}, which isn't in the raws", name);
+        }
+        let corner = map.xy_idx(1, 1);
+        for idx in 0..map.tiles.len() {
+            if !map.tiles[idx].blocks_movement() {
+                assert!(reachable(&map, corner, idx), "part of this prefab is walled off:\n{}", template);
+            }
+        }
+    }
+}
+
+#[test]
+fn prefabs_only_skip_blank_lines_at_either_end() {
+    let prefab = Prefab::parse("\n\n###\n   \n#.#\n\n");
+
+    assert_eq!((prefab.width, prefab.height), (3, 3));
+    assert_eq!(prefab.glyph(1, 1), ' ');
+    assert_eq!(prefab.glyph(1, 2), '.');
+}
+
+#[test]
+fn taking_the_amulet_wins_the_game() {
+    let mut sim = arena();
+    sim.spawn("Amulet of the Ancients", 5, 5);
+
+    sim.act(PlayerAction::PickUp);
+
+    assert!(*sim.world().fetch::<RunState>() == RunState::Victory);
+    let entry = morgue::high_score_for_run(sim.world(), 0);
+    assert!(entry.won);
+    assert!(entry.score >= VICTORY_BONUS);
+    let morgue = morgue::morgue_text(sim.world(), 0);
+    assert!(morgue.contains("Took the Amulet of the Ancients on depth 1."), "no victory in:\n{}", morgue);
+}
+
+#[test]
+fn the_lich_king_uses_its_special_attacks() {
+    let mut sim = arena();
+    sim.spawn("Lich King", 9, 5);
+    let hp = sim.hp(sim.player()).unwrap();
+
+    for _turn in 0..3 {
+        sim.act(PlayerAction::SkipTurn);
+    }
+
+    assert!(sim.log_contains("Lich King uses Necrotic Blast!"));
+    assert!(sim.hp(sim.player()).unwrap() < hp);
+}